@@ -0,0 +1,233 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::board::{Captured, Dragged};
+use crate::game::TextFocus;
+use crate::piece::{Piece, PieceType};
+
+// Easing curves pieces can be moved with, picked through AnimationSettings
+#[derive(Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseOutCubic,
+    EaseInOutCubic,
+    EaseInOutSine,
+}
+
+impl Easing {
+    // Maps the linear progress of an animation, from 0 to 1, to the eased progress
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::EaseInOutSine => -((PI * t).cos() - 1.0) / 2.0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseOutCubic => "ease out",
+            Easing::EaseInOutCubic => "ease in and out",
+            Easing::EaseInOutSine => "gentle ease in and out",
+        }
+    }
+
+    fn next(self) -> Easing {
+        match self {
+            Easing::Linear => Easing::EaseOutCubic,
+            Easing::EaseOutCubic => Easing::EaseInOutCubic,
+            Easing::EaseInOutCubic => Easing::EaseInOutSine,
+            Easing::EaseInOutSine => Easing::Linear,
+        }
+    }
+}
+
+pub struct AnimationSettings {
+    pub easing: Easing,
+    // A move takes the base duration plus the duration per square travelled, in seconds
+    pub base_duration: f32,
+    pub duration_per_square: f32,
+    // Peak height of the arc knights and capturing pieces hop in
+    pub hop_height: f32,
+    // Put pieces straight on their squares the next time they move. Replaying a saved game
+    // and browsing the move history set it, and it's cleared once the pieces are placed.
+    pub instant: bool,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        AnimationSettings {
+            easing: Easing::EaseInOutCubic,
+            base_duration: 0.15,
+            duration_per_square: 0.06,
+            hop_height: 0.6,
+            instant: false,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct PieceAnimation {
+    from: Vec3,
+    to: Vec3,
+    elapsed: f32,
+    // Seconds to wait before starting, the rook waits for the king when castling
+    delay: f32,
+    duration: f32,
+    hop: f32,
+}
+
+// Sent when a piece has arrived on its square
+pub struct AnimationFinished {
+    pub entity: Entity,
+}
+
+pub struct PieceAnimationPlugin;
+
+impl Plugin for PieceAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AnimationSettings>()
+            .add_event::<AnimationFinished>()
+            .add_system(switch_easing)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                animate_pieces.before(TransformSystem::TransformPropagate),
+            )
+            // Runs after animate_pieces so an animation it replaces doesn't move the piece again
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                start_animations
+                    .after(animate_pieces)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, hide_captured.after(start_animations));
+    }
+}
+
+// E goes to the next easing curve
+fn switch_easing(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    mut settings: ResMut<AnimationSettings>,
+) {
+    if keys.just_pressed(KeyCode::E) && !focus.0 {
+        settings.easing = settings.easing.next();
+    }
+}
+
+// Starts an animation for every piece whose square changed.
+// A piece that's still moving starts over from wherever it is.
+#[allow(clippy::type_complexity)]
+fn start_animations(
+    mut commands: Commands,
+    mut settings: ResMut<AnimationSettings>,
+    mut finished_events: EventWriter<AnimationFinished>,
+    mut query: Query<
+        (Entity, &Piece, ChangeTrackers<Piece>, &mut Transform),
+        (Changed<Piece>, Without<Dragged>),
+    >,
+    captured_query: Query<&Captured>,
+) {
+    // A castling king moves two files along its rank, its rook follows once it has arrived
+    let mut castling_duration = None;
+    for (_, piece, _, transform) in query.iter() {
+        let travel = Vec3::new(piece.x as f32, 0., piece.y as f32) - transform.translation;
+        if piece.piece_type == PieceType::King
+            && travel.x.abs() < 0.1
+            && (travel.z.abs() - 2.0).abs() < 0.1
+        {
+            castling_duration =
+                Some(settings.base_duration + settings.duration_per_square * travel.length());
+        }
+    }
+
+    for (entity, piece, tracker, mut transform) in query.iter_mut() {
+        // Newly spawned pieces already stand on their squares
+        if tracker.is_added() {
+            continue;
+        }
+
+        let to = Vec3::new(piece.x as f32, 0., piece.y as f32);
+        let distance = to.distance(transform.translation);
+        if settings.instant || distance < 0.01 {
+            transform.translation = to;
+            commands.entity(entity).remove::<PieceAnimation>();
+            finished_events.send(AnimationFinished { entity });
+            continue;
+        }
+
+        let is_capture = captured_query.iter().any(|captured| captured.by == entity);
+        let castling_rook_delay = castling_duration.filter(|_| piece.piece_type == PieceType::Rook);
+        // Knights and captures jump, so does the rook over the king when castling
+        let hop =
+            if piece.piece_type == PieceType::Knight || is_capture || castling_rook_delay.is_some()
+            {
+                settings.hop_height
+            } else {
+                0.
+            };
+
+        commands.entity(entity).insert(PieceAnimation {
+            from: transform.translation,
+            to,
+            elapsed: 0.,
+            delay: castling_rook_delay.unwrap_or(0.),
+            duration: settings.base_duration + settings.duration_per_square * distance,
+            hop,
+        });
+    }
+
+    if settings.instant {
+        settings.instant = false;
+    }
+}
+
+fn animate_pieces(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    mut finished_events: EventWriter<AnimationFinished>,
+    mut query: Query<(Entity, &mut Transform, &mut PieceAnimation), Without<Dragged>>,
+) {
+    for (entity, mut transform, mut animation) in query.iter_mut() {
+        animation.elapsed += time.delta_seconds();
+        let t = ((animation.elapsed - animation.delay) / animation.duration).clamp(0.0, 1.0);
+
+        let mut translation = animation.from.lerp(animation.to, settings.easing.apply(t));
+        // Parabolic arc peaking halfway through the move
+        translation.y += animation.hop * 4.0 * t * (1.0 - t);
+        transform.translation = translation;
+
+        if t >= 1.0 {
+            commands.entity(entity).remove::<PieceAnimation>();
+            finished_events.send(AnimationFinished { entity });
+        }
+    }
+}
+
+// Captured pieces disappear once the piece that took them has landed.
+// They're only hidden so going back through the move history can bring them back.
+fn hide_captured(
+    mut commands: Commands,
+    mut finished_events: EventReader<AnimationFinished>,
+    mut captured_query: Query<(Entity, &Captured, &mut Visibility)>,
+) {
+    for event in finished_events.iter() {
+        for (entity, captured, mut visibility) in captured_query.iter_mut() {
+            if captured.by == event.entity {
+                visibility.is_visible = false;
+                commands.entity(entity).remove::<Captured>();
+            }
+        }
+    }
+}
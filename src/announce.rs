@@ -0,0 +1,263 @@
+use bevy::prelude::*;
+
+use crate::animation::{AnimationSettings, Easing};
+use crate::board::{
+    color_name, piece_name, square_name, MovePlayed, MoveRejected, Square, SquareEvent,
+};
+use crate::cursor::KeyboardCursor;
+use crate::highlight::HighlightScheme;
+use crate::history::MoveHistory;
+use crate::piece::Piece;
+
+const FONT_SIZE: f32 = 22.;
+const BACKGROUND_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const NOTICE_WIDTH: f32 = 500.;
+const NOTICE_COLOR: Color = Color::rgb(1., 0.5, 0.4);
+// How long a rejected move's reason stays up, it fades out over the last second
+const NOTICE_SECONDS: f32 = 2.5;
+
+// Spoken-style description of the last hovered square or played move
+#[derive(Default)]
+pub struct Announcement(pub String);
+
+#[derive(Component)]
+struct AnnouncementText;
+
+// Why the last move was turned down, shown over the board for a moment
+#[derive(Component, Default)]
+struct RejectionNotice {
+    seconds_left: f32,
+}
+
+pub struct AnnouncePlugin;
+
+impl Plugin for AnnouncePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Announcement>()
+            .add_startup_system(create_announcement)
+            .add_system(announce_squares)
+            .add_system(announce_moves.after(announce_squares))
+            .add_system(announce_scheme.after(announce_moves))
+            .add_system(announce_easing.after(announce_scheme))
+            .add_system(show_announcement.after(announce_easing))
+            .add_system(show_rejections);
+    }
+}
+
+fn create_announcement(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: FONT_SIZE,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..default()
+                },
+                padding: UiRect::all(Val::Px(6.)),
+                ..default()
+            }),
+        )
+        .insert(UiColor(BACKGROUND_COLOR))
+        .insert(AnnouncementText);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Percent(20.),
+                    left: Val::Percent(50.),
+                    ..default()
+                },
+                margin: UiRect {
+                    left: Val::Px(-NOTICE_WIDTH / 2.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(NOTICE_WIDTH), Val::Undefined),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(RejectionNotice::default())
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: FONT_SIZE,
+                    color: NOTICE_COLOR,
+                },
+            ));
+        });
+}
+
+// Like "White knight f3", or "f3, empty"
+fn describe_square(pos: (u8, u8), pieces_query: &Query<&Piece>) -> String {
+    match pieces_query.iter().find(|piece| (piece.x, piece.y) == pos) {
+        Some(piece) => format!(
+            "{} {} {}",
+            color_name(piece.color),
+            piece_name(piece.piece_type),
+            square_name(pos)
+        ),
+        None => format!("{}, empty", square_name(pos)),
+    }
+}
+
+// Squares hovered with the mouse or reached with the keyboard cursor
+fn announce_squares(
+    mut square_events: EventReader<SquareEvent>,
+    cursor: Res<KeyboardCursor>,
+    pieces_query: Query<&Piece>,
+    mut announcement: ResMut<Announcement>,
+) {
+    let hovered = square_events
+        .iter()
+        .filter_map(|event| match event {
+            SquareEvent::Hovered(pos) => *pos,
+            SquareEvent::Clicked(_) => None,
+        })
+        .next_back();
+    let keyboard = cursor.0.filter(|_| cursor.is_changed());
+    if let Some(pos) = keyboard.or(hovered) {
+        announcement.0 = describe_square(pos, &pieces_query);
+    }
+}
+
+// Like "White knight f3 takes e5, check"
+fn announce_moves(
+    mut move_events: EventReader<MovePlayed>,
+    history: Res<MoveHistory>,
+    mut announcement: ResMut<Announcement>,
+) {
+    if move_events.iter().count() == 0 {
+        return;
+    }
+    let record = match history.moves.last() {
+        Some(record) => record,
+        None => return,
+    };
+    let before = history.position(history.moves.len() - 1);
+    let piece = match before
+        .iter()
+        .map(|(_, piece)| piece)
+        .find(|piece| (piece.x, piece.y) == record.from)
+    {
+        Some(piece) => piece,
+        None => return,
+    };
+
+    let color = color_name(piece.color);
+    let mut text = if record.san.starts_with("O-O-O") {
+        format!("{} castles queenside", color)
+    } else if record.san.starts_with("O-O") {
+        format!("{} castles kingside", color)
+    } else {
+        let capture = if record.san.contains('x') {
+            "takes"
+        } else {
+            "to"
+        };
+        format!(
+            "{} {} {} {} {}",
+            color,
+            piece_name(piece.piece_type),
+            square_name(record.from),
+            capture,
+            square_name(record.to)
+        )
+    };
+    if let Some(promotion) = record.promotion {
+        text.push_str(&format!(", promotes to {}", piece_name(promotion)));
+    }
+    if record.san.ends_with('#') {
+        text.push_str(", checkmate");
+    } else if record.san.ends_with('+') {
+        text.push_str(", check");
+    }
+    announcement.0 = text;
+}
+
+fn announce_scheme(scheme: Res<HighlightScheme>, mut announcement: ResMut<Announcement>) {
+    if scheme.is_changed() && !scheme.is_added() {
+        announcement.0 = format!("Highlights: {}", scheme.name());
+    }
+}
+
+// The settings also change when pieces are snapped into place, so the curve is compared
+// with the last one seen
+fn announce_easing(
+    settings: Res<AnimationSettings>,
+    mut last: Local<Option<Easing>>,
+    mut announcement: ResMut<Announcement>,
+) {
+    if last.is_some() && *last != Some(settings.easing) {
+        announcement.0 = format!("Piece movement: {}", settings.easing.name());
+    }
+    *last = Some(settings.easing);
+}
+
+// Only shown while there's a board, the text is cleared along with it
+fn show_announcement(
+    mut announcement: ResMut<Announcement>,
+    squares_query: Query<&Square>,
+    mut text_query: Query<(&mut Text, &mut Visibility), With<AnnouncementText>>,
+) {
+    if squares_query.is_empty() && !announcement.0.is_empty() {
+        announcement.0.clear();
+    }
+    if !announcement.is_changed() {
+        return;
+    }
+    for (mut text, mut visibility) in text_query.iter_mut() {
+        text.sections[0].value = announcement.0.clone();
+        visibility.is_visible = !announcement.0.is_empty();
+    }
+}
+
+// The reason a move was turned down, faded out after a moment
+fn show_rejections(
+    time: Res<Time>,
+    mut rejected_events: EventReader<MoveRejected>,
+    mut notices_query: Query<(&mut RejectionNotice, &mut Visibility, &Children)>,
+    mut texts_query: Query<&mut Text>,
+) {
+    let rejected = rejected_events.iter().next_back();
+    for (mut notice, mut visibility, children) in notices_query.iter_mut() {
+        if let Some(MoveRejected(err)) = rejected {
+            notice.seconds_left = NOTICE_SECONDS;
+            visibility.is_visible = true;
+            for child in children.iter() {
+                if let Ok(mut text) = texts_query.get_mut(*child) {
+                    text.sections[0].value = err.description();
+                }
+            }
+        } else if notice.seconds_left > 0. {
+            notice.seconds_left -= time.delta_seconds();
+            if notice.seconds_left <= 0. {
+                visibility.is_visible = false;
+            }
+        } else {
+            continue;
+        }
+        for child in children.iter() {
+            if let Ok(mut text) = texts_query.get_mut(*child) {
+                let mut color = NOTICE_COLOR;
+                color.set_a(notice.seconds_left.min(1.));
+                text.sections[0].style.color = color;
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use crate::engine::search::DEFAULT_HASH_MB;
+use crate::engine::{Board, Engine, Limits};
+
+// Middlegames and endgames the search is timed on
+const POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 1",
+    "2r3k1/1p3ppp/p3pn2/3r4/3P4/P3PN2/1P3PPP/2R1R1K1 b - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 1",
+];
+const DEFAULT_DEPTH: i32 = 10;
+
+// `bench [depth] [hash MB] [threads]` searches each position to the depth and prints what it took,
+// to see how a change to the search does
+pub fn run(args: &[String]) -> Result<(), String> {
+    let number = |i: usize, default: usize| match args.get(i) {
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| format!("Expected a number instead of '{}'", arg)),
+        None => Ok(default),
+    };
+    let depth = number(0, DEFAULT_DEPTH as usize)? as i32;
+    let mut engine = Engine::new(number(1, DEFAULT_HASH_MB)?);
+    engine.set_threads(number(2, 1)?);
+    let limits = Limits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+
+    let mut nodes = 0;
+    let mut time = Duration::ZERO;
+    for fen in POSITIONS {
+        engine.new_game();
+        let info = engine.search(&Board::from_fen(fen)?, &limits, |_| {});
+        let best = info.best_move().map(|mv| mv.uci()).unwrap_or_default();
+        let score = match info.mate_in() {
+            Some(moves) => format!("mate {}", moves),
+            None => info.score.to_string(),
+        };
+        println!("{}", fen);
+        println!(
+            "  best {} score {} depth {} seldepth {} nodes {} hash hits {} time {} ms",
+            best,
+            score,
+            info.depth,
+            info.seldepth,
+            info.nodes,
+            info.tt_hits,
+            info.elapsed.as_millis()
+        );
+        nodes += info.nodes;
+        time += info.elapsed;
+    }
+    println!(
+        "{} nodes in {} ms, {} nodes/s",
+        nodes,
+        time.as_millis(),
+        (nodes as f64 / time.as_secs_f64().max(0.001)) as u64
+    );
+    Ok(())
+}
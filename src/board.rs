@@ -1,7 +1,15 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy_mod_picking::{PickableBundle, PickingEvent};
+use bevy_mod_picking::{HoverEvent, PickableBundle, PickingCamera, PickingEvent, Primitive3d};
+use serde::{Deserialize, Serialize};
 
-use crate::piece::{Piece, PieceColor};
+use crate::fen::{piece_char, piece_from_char};
+use crate::game::{AppState, GameSetup};
+use crate::highlight::{
+    mark_squares, HighlightLayer, HighlightPalette, HighlightScheme, Highlights, SquareMaterials,
+};
+use crate::history::{san, MoveHistory, MoveRecord};
+use crate::piece::{Piece, PieceColor, PieceType};
 
 #[derive(Component)]
 pub struct Square {
@@ -9,25 +17,209 @@ pub struct Square {
     pub y: u8,
 }
 
+// Picking events resolved to board positions.
+// A picked piece mesh resolves to the square its piece stands on.
+pub enum SquareEvent {
+    Hovered(Option<(u8, u8)>),
+    Clicked((u8, u8)),
+}
+
+// A move asked for without the mouse, like a typed one. It's played like a click would.
+pub struct MoveRequest {
+    pub from: (u8, u8),
+    pub to: (u8, u8),
+    pub promotion: Option<PieceType>,
+}
+
 #[derive(Default)]
-struct SelectedSquare {
+struct SelectedPiece {
     entity: Option<Entity>,
 }
 
+pub struct PlayerTurn(pub PieceColor);
+
+impl Default for PlayerTurn {
+    fn default() -> Self {
+        Self(PieceColor::White)
+    }
+}
+
+impl PlayerTurn {
+    fn change(&mut self) {
+        self.0 = self.0.opposite();
+    }
+}
+
+// How a game ended
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GameEnd {
+    Checkmate { winner: PieceColor },
+    Stalemate,
+    Timeout { winner: PieceColor },
+    // The flag fell but the other side couldn't have mated
+    TimeoutDraw,
+}
+
+impl GameEnd {
+    pub fn description(self) -> String {
+        match self {
+            GameEnd::Checkmate { winner } => format!("{} wins by checkmate", color_name(winner)),
+            GameEnd::Stalemate => "Draw by stalemate".to_string(),
+            GameEnd::Timeout { winner } => format!("{} wins on time", color_name(winner)),
+            GameEnd::TimeoutDraw => "Draw, time out against insufficient material".to_string(),
+        }
+    }
+}
+
+pub fn color_name(color: PieceColor) -> &'static str {
+    match color {
+        PieceColor::White => "White",
+        PieceColor::Black => "Black",
+    }
+}
+
+// Why a move was turned down
+#[derive(Clone, Copy, PartialEq)]
+pub enum MoveError {
+    GameOver,
+    NoPiece,
+    NotYourTurn,
+    OwnPiece,
+    // The piece doesn't move like that
+    WrongGeometry(PieceType),
+    BlockedPath,
+    // A pawn moving diagonally onto an empty square
+    NothingToCapture,
+    NoCastlingRights,
+    CastlingThroughCheck,
+    // The king is in check and stays in check
+    InCheck,
+    KingWouldBeInCheck,
+    // Moving the piece would uncover a check on its king
+    Pinned(PieceType),
+    InvalidPromotion(PieceType),
+}
+
+impl MoveError {
+    pub fn description(self) -> String {
+        match self {
+            MoveError::GameOver => "The game is over".to_string(),
+            MoveError::NoPiece => "There's no piece there".to_string(),
+            MoveError::NotYourTurn => "It's not your turn".to_string(),
+            MoveError::OwnPiece => "That square has one of your pieces".to_string(),
+            MoveError::WrongGeometry(piece_type) => {
+                format!("The {} doesn't move like that", piece_name(piece_type))
+            }
+            MoveError::BlockedPath => "The way is blocked".to_string(),
+            MoveError::NothingToCapture => "Pawns only move diagonally to capture".to_string(),
+            MoveError::NoCastlingRights => {
+                "Can't castle, the king or that rook has moved".to_string()
+            }
+            MoveError::CastlingThroughCheck => {
+                "Can't castle out of, through or into check".to_string()
+            }
+            MoveError::InCheck => "Your king is in check".to_string(),
+            MoveError::KingWouldBeInCheck => "The king would be in check".to_string(),
+            MoveError::Pinned(piece_type) => {
+                format!("The {} is pinned to the king", piece_name(piece_type))
+            }
+            MoveError::InvalidPromotion(piece_type) => {
+                format!("Pawns can't promote to a {}", piece_name(piece_type))
+            }
+        }
+    }
+}
+
+// Sent when a move is turned down, with the reason
+pub struct MoveRejected(pub MoveError);
+
+pub fn piece_name(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "king",
+        PieceType::Queen => "queen",
+        PieceType::Rook => "rook",
+        PieceType::Bishop => "bishop",
+        PieceType::Knight => "knight",
+        PieceType::Pawn => "pawn",
+    }
+}
+
+// Set once the game is over, no more moves can be played after that
 #[derive(Default)]
-struct SelectedPiece {
+pub struct GameResult(pub Option<GameEnd>);
+
+// Sent when a move has been played, with the color that played it
+pub struct MovePlayed {
+    pub color: PieceColor,
+}
+
+// A piece taken off the board, it stays visible until the piece that captured it lands
+#[derive(Component)]
+pub struct Captured {
+    pub by: Entity,
+}
+
+// Marks a piece following the cursor, it isn't moved towards its square while dragged
+#[derive(Component)]
+pub struct Dragged;
+
+// Piece pressed on with the left mouse button, it's dragged once the cursor moves far enough
+#[derive(Default)]
+struct DragState {
     entity: Option<Entity>,
+    start: Vec3,
+    dragging: bool,
 }
 
+const DRAG_THRESHOLD: f32 = 0.25;
+const DRAG_HEIGHT: f32 = 0.3;
+
 pub struct BoardPlugin;
 
 impl Plugin for BoardPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<SelectedSquare>()
-            .init_resource::<SelectedPiece>()
-            .add_startup_system(create_board)
-            .add_system(select_square)
-            .add_system(color_squares);
+        app.init_resource::<SelectedPiece>()
+            .init_resource::<PlayerTurn>()
+            .init_resource::<GameResult>()
+            .add_event::<SquareEvent>()
+            .add_event::<MovePlayed>()
+            .add_event::<MoveRequest>()
+            .add_event::<MoveRejected>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Playing)
+                    .with_system(create_board)
+                    .with_system(start_game),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Playing).with_system(remove_board))
+            // The editor sets pieces up on the same board
+            .add_system_set(SystemSet::on_enter(AppState::Editor).with_system(create_board))
+            .add_system_set(SystemSet::on_exit(AppState::Editor).with_system(remove_board))
+            .add_system(pick_squares)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(select_square.after(pick_squares).after(mark_squares))
+                    .with_system(drag_pieces.after(select_square)),
+            );
+    }
+}
+
+// Nothing is carried over from the previous game
+fn start_game(
+    setup: Res<GameSetup>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut turn: ResMut<PlayerTurn>,
+    mut result: ResMut<GameResult>,
+    mut highlights: ResMut<Highlights>,
+) {
+    *selected_piece = SelectedPiece::default();
+    *turn = PlayerTurn(setup.position().turn);
+    *result = GameResult::default();
+    *highlights = Highlights::default();
+}
+
+fn remove_board(mut commands: Commands, squares_query: Query<Entity, With<Square>>) {
+    for entity in squares_query.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 }
 
@@ -35,138 +227,498 @@ fn create_board(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut square_materials: ResMut<SquareMaterials>,
+    palette: Res<HighlightPalette>,
+    scheme: Res<HighlightScheme>,
 ) {
     // Add meshes and materials
     let mesh = meshes.add(Mesh::from(shape::Plane { size: 1.0 }));
+    // The cached materials have to match what color_squares would make
+    let palette = scheme.apply(&palette);
 
     // spawn 64 squares
     for i in 0..8 {
         for j in 0..8 {
+            let square = Square { x: i, y: j };
             // Plane
             commands
                 .spawn_bundle(PbrBundle {
                     mesh: mesh.clone(),
-                    material: if (i + j + 1) % 2 == 0 {
-                        materials.add(Color::rgb(1.0, 0.9, 0.9).into())
-                    } else {
-                        materials.add(Color::rgb(0.0, 0.1, 0.1).into())
-                    },
+                    // Squares without highlights share the plain light and dark materials
+                    material: square_materials.get(square.is_white(), 0, &palette, &mut materials),
                     transform: Transform::from_translation(Vec3::new(i as f32, 0., j as f32)),
                     ..Default::default()
                 })
                 .insert_bundle(PickableBundle::default())
-                .insert(Square { x: i, y: j });
+                .insert(square);
         }
     }
 }
 
 impl Square {
-    fn is_white(&self) -> bool {
-        (self.x + self.y + 1) % 2 == 0
+    pub fn is_white(&self) -> bool {
+        (self.x + self.y) % 2 == 1
     }
 }
 
-fn color_squares(
+fn pick_squares(
     mut pick_events: EventReader<PickingEvent>,
-    selected_square: ResMut<SelectedSquare>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    query: Query<(Entity, &Square, &Handle<StandardMaterial>)>,
+    mut square_events: EventWriter<SquareEvent>,
+    mut hovered: Local<Option<Entity>>,
+    squares_query: Query<&Square>,
+    parents_query: Query<&Parent>,
+    pieces_query: Query<&Piece>,
 ) {
-    let hover_color = Color::rgb(1.0, 0.0, 0.0);
-    let selected_square_color = Color::rgb(0.0, 1.0, 0.0);
-    let white_color = Color::rgb(1.0, 0.9, 0.9);
-    let black_color = Color::rgb(0.0, 0.0, 0.0);
+    // Piece meshes are children of the entity holding the Piece
+    let position = |entity: Entity| {
+        if let Ok(square) = squares_query.get(entity) {
+            return Some((square.x, square.y));
+        }
+        let parent = parents_query.get(entity).ok()?;
+        let piece = pieces_query.get(parent.get()).ok()?;
+        Some((piece.x, piece.y))
+    };
 
-    // Get the entity under the cursor if there's one
     for event in pick_events.iter() {
         match event {
             PickingEvent::Selection(_) => {}
-            PickingEvent::Hover(hover_event) => {
-                for (entity, square, material_handle) in query.iter() {
-                    // Get The actual material
-                    let material = materials.get_mut(material_handle).unwrap();
-
-                    match hover_event {
-                        bevy_mod_picking::HoverEvent::JustEntered(square_under_cursor) => {
-                            // Change the material color of square below the cursor
-                            material.base_color = if &entity == square_under_cursor {
-                                hover_color
-                            } else if Some(entity) == selected_square.entity {
-                                selected_square_color
-                            } else if square.is_white() {
-                                white_color
-                            } else {
-                                black_color
-                            }
-                        }
-                        bevy_mod_picking::HoverEvent::JustLeft(_) => {}
-                    };
+            PickingEvent::Hover(HoverEvent::JustEntered(e)) => {
+                *hovered = Some(*e);
+                square_events.send(SquareEvent::Hovered(position(*e)));
+            }
+            PickingEvent::Hover(HoverEvent::JustLeft(e)) => {
+                // Entering the next entity can be reported before leaving the previous one
+                if *hovered == Some(*e) {
+                    *hovered = None;
+                    square_events.send(SquareEvent::Hovered(None));
                 }
             }
             PickingEvent::Clicked(e) => {
-                for (entity, square, material_handle) in query.iter() {
-                    // Get The actual material
-                    let material = materials.get_mut(material_handle).unwrap();
-
-                    // Change the material color
-                    material.base_color = if &entity == e {
-                        selected_square_color
-                    } else if Some(entity) == selected_square.entity {
-                        selected_square_color
-                    } else if square.is_white() {
-                        white_color
-                    } else {
-                        black_color
-                    }
+                if let Some(pos) = position(*e) {
+                    square_events.send(SquareEvent::Clicked(pos));
                 }
             }
         }
     }
 }
 
+// Selection state machine:
+// - nothing selected: clicking a piece of the side to move selects it
+// - piece selected: clicking it again, right click or Esc deselects it,
+//   clicking another piece of the same color selects that piece instead,
+//   clicking any other square attempts the move and deselects
+// Requested moves, like typed ones, are attempted straight away and clear the selection
 fn select_square(
-    mut pick_events: EventReader<PickingEvent>,
-    mut selected_square: ResMut<SelectedSquare>,
+    mut square_events: EventReader<SquareEvent>,
+    mut move_requests: EventReader<MoveRequest>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
     mut selected_piece: ResMut<SelectedPiece>,
-    squares_query: Query<&Square>,
-    mut pieces_query: Query<(Entity, &mut Piece)>,
+    setup: Res<GameSetup>,
+    mut moves: MoveParams,
 ) {
-    // Get the square under the cursor and set it as selected
-    for event in pick_events.iter() {
-        match event {
-            PickingEvent::Selection(_) => {}
-            PickingEvent::Hover(_) => {}
-            PickingEvent::Clicked(e) => {
-                selected_square.entity = Some(*e);
-                if let Ok(square) = squares_query.get(*e) {
-                    if let Some(selected_piece_entity) = selected_piece.entity {
-                        let pieces_vec = pieces_query.iter_mut().map(|(_, piece)| *piece).collect();
-                        // Move the selected piece to the selected square
-                        if let Ok((_piece_entity, mut piece)) =
-                            pieces_query.get_mut(selected_piece_entity)
-                        {
-                            if piece.is_move_valid((square.x, square.y), pieces_vec) {
-                                piece.x = square.x;
-                                piece.y = square.y;
-                            } else {
-                                println!("Invalid move")
-                            }
+    // Earlier positions can be looked at but not played from
+    if moves.history.is_browsing() {
+        if selected_piece.entity.is_some() {
+            deselect_piece(&mut selected_piece, &mut moves.highlights);
+        }
+        square_events.iter().for_each(drop);
+        move_requests.iter().for_each(drop);
+        return;
+    }
+
+    if selected_piece.entity.is_some()
+        && (mouse_buttons.just_pressed(MouseButton::Right) || keys.just_pressed(KeyCode::Escape))
+    {
+        deselect_piece(&mut selected_piece, &mut moves.highlights);
+    }
+
+    for request in move_requests.iter() {
+        let result = match moves.piece_at(request.from) {
+            Some(entity) => moves.try_move(entity, request.to, request.promotion),
+            None => Err(MoveError::NoPiece),
+        };
+        if let Err(err) = result {
+            moves.reject(err);
+        }
+        if selected_piece.entity.is_some() {
+            deselect_piece(&mut selected_piece, &mut moves.highlights);
+        }
+    }
+
+    for event in square_events.iter() {
+        let pos = match event {
+            SquareEvent::Clicked(pos) => *pos,
+            SquareEvent::Hovered(_) => continue,
+        };
+        let pieces_vec = moves.pieces();
+        let clicked_piece = moves
+            .pieces_query
+            .iter()
+            .find(|(_, piece)| (piece.x, piece.y) == pos)
+            .map(|(entity, piece)| (entity, *piece));
+        let selected = selected_piece
+            .entity
+            .and_then(|entity| moves.pieces_query.get(entity).ok())
+            .map(|(entity, piece)| (entity, *piece));
+
+        match (selected, clicked_piece) {
+            // Clicking the selected piece again deselects it
+            (Some((selected_entity, _)), Some((clicked_entity, _)))
+                if selected_entity == clicked_entity =>
+            {
+                deselect_piece(&mut selected_piece, &mut moves.highlights);
+            }
+            // Clicking another friendly piece changes the selection
+            (Some((_, selected)), Some((clicked_entity, clicked)))
+                if selected.color == clicked.color =>
+            {
+                select_piece(
+                    clicked_entity,
+                    &clicked,
+                    &pieces_vec,
+                    &mut selected_piece,
+                    &mut moves.highlights,
+                );
+            }
+            // Anything else is a move attempt
+            (Some((selected_entity, _)), _) => {
+                if let Err(err) = moves.try_move(selected_entity, pos, None) {
+                    moves.reject(err);
+                }
+                deselect_piece(&mut selected_piece, &mut moves.highlights);
+            }
+            // The computer's pieces are moved by the computer
+            (None, Some((clicked_entity, clicked)))
+                if clicked.color == moves.turn.0
+                    && Some(clicked.color) != setup.computer_color()
+                    && moves.result.0.is_none() =>
+            {
+                select_piece(
+                    clicked_entity,
+                    &clicked,
+                    &pieces_vec,
+                    &mut selected_piece,
+                    &mut moves.highlights,
+                );
+            }
+            (None, Some(_)) if moves.result.0.is_none() => {
+                moves.reject(MoveError::NotYourTurn);
+            }
+            (None, _) => {}
+        }
+    }
+}
+
+// Everything a move is played against
+#[derive(SystemParam)]
+pub struct MoveParams<'w, 's> {
+    commands: Commands<'w, 's>,
+    pieces_query: Query<'w, 's, (Entity, &'static mut Piece)>,
+    highlights: ResMut<'w, Highlights>,
+    turn: ResMut<'w, PlayerTurn>,
+    history: ResMut<'w, MoveHistory>,
+    result: ResMut<'w, GameResult>,
+    move_events: EventWriter<'w, 's, MovePlayed>,
+    rejected_events: EventWriter<'w, 's, MoveRejected>,
+}
+
+impl<'w, 's> MoveParams<'w, 's> {
+    pub fn pieces(&self) -> Vec<Piece> {
+        self.pieces_query.iter().map(|(_, piece)| *piece).collect()
+    }
+
+    pub fn piece_at(&self, pos: (u8, u8)) -> Option<Entity> {
+        self.pieces_query
+            .iter()
+            .find(|(_, piece)| (piece.x, piece.y) == pos)
+            .map(|(entity, _)| entity)
+    }
+
+    // Logs why a move the player tried was turned down and passes it on to be shown
+    pub fn reject(&mut self, err: MoveError) {
+        info!("Move rejected: {}", err.description());
+        self.rejected_events.send(MoveRejected(err));
+    }
+
+    // Moves a piece to the given square if it's a legal move for the side to move.
+    // A pawn reaching the last rank becomes the promotion piece, a queen if none is given.
+    // Every way of moving a piece goes through here. Rejected moves are left for the caller
+    // to report, replays don't show them to the player.
+    pub fn try_move(
+        &mut self,
+        entity: Entity,
+        pos: (u8, u8),
+        promotion: Option<PieceType>,
+    ) -> Result<(), MoveError> {
+        let before: Vec<(Entity, Piece)> = self
+            .pieces_query
+            .iter()
+            .map(|(entity, piece)| (entity, *piece))
+            .collect();
+        let pieces_before = self.pieces();
+        let piece = match self.pieces_query.get(entity) {
+            Ok((_, piece)) => *piece,
+            Err(_) => return Err(MoveError::NoPiece),
+        };
+        if self.result.0.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if piece.color != self.turn.0 {
+            return Err(MoveError::NotYourTurn);
+        }
+        piece.check_move(pos, &pieces_before)?;
+        let promotion = match promotion {
+            _ if !piece.promotes(pos) => None,
+            Some(piece_type @ (PieceType::King | PieceType::Pawn)) => {
+                return Err(MoveError::InvalidPromotion(piece_type))
+            }
+            Some(piece_type) => Some(piece_type),
+            None => Some(PieceType::Queen),
+        };
+
+        // The captured piece leaves the game now, its model is hidden once the capturing piece lands.
+        // A pawn taken en passant isn't on the square moved to.
+        let captured_square = piece.en_passant_victim(pos, &pieces_before).unwrap_or(pos);
+        let captured = before
+            .iter()
+            .find(|(_, other)| (other.x, other.y) == captured_square)
+            .map(|(captured_entity, _)| *captured_entity);
+        if let Some(captured_entity) = captured {
+            self.commands
+                .entity(captured_entity)
+                .remove::<Piece>()
+                .insert(Captured { by: entity });
+        }
+
+        // Castling moves the rook to the square the king passed over
+        if piece.piece_type == PieceType::King && (piece.y as i8 - pos.1 as i8).abs() == 2 {
+            let (rook_from, rook_to) = piece.castling_rook(pos);
+            if let Some((_, mut rook)) = self
+                .pieces_query
+                .iter_mut()
+                .find(|(_, rook)| (rook.x, rook.y) == rook_from)
+            {
+                rook.y = rook_to.1;
+                rook.has_moved = true;
+            }
+        }
+
+        // Only the pawn that just moved two squares can be taken en passant
+        for (other, mut other_piece) in self.pieces_query.iter_mut() {
+            if other != entity && other_piece.en_passant {
+                other_piece.en_passant = false;
+            }
+        }
+        if let Ok((_, mut piece)) = self.pieces_query.get_mut(entity) {
+            piece.en_passant =
+                piece.piece_type == PieceType::Pawn && (piece.x as i8 - pos.0 as i8).abs() == 2;
+            piece.x = pos.0;
+            piece.y = pos.1;
+            piece.has_moved = true;
+            if let Some(piece_type) = promotion {
+                piece.piece_type = piece_type;
+            }
+        }
+
+        let after: Vec<(Entity, Piece)> = self
+            .pieces_query
+            .iter()
+            .filter(|(other, _)| Some(*other) != captured)
+            .map(|(entity, piece)| (entity, *piece))
+            .collect();
+        let pieces_after: Vec<Piece> = after.iter().map(|(_, piece)| *piece).collect();
+
+        self.highlights
+            .set(HighlightLayer::LastMove, [(piece.x, piece.y), pos]);
+        self.highlights.set(
+            HighlightLayer::Check,
+            [PieceColor::White, PieceColor::Black]
+                .into_iter()
+                .filter_map(|color| king_in_check(color, &pieces_after)),
+        );
+
+        let san = san(&piece, pos, promotion, &pieces_before, &pieces_after);
+        self.history.record(
+            before,
+            MoveRecord {
+                san,
+                from: (piece.x, piece.y),
+                to: pos,
+                promotion,
+                position: after,
+            },
+        );
+        self.turn.change();
+        self.move_events.send(MovePlayed { color: piece.color });
+
+        let opponent = piece.color.opposite();
+        if !has_legal_moves(opponent, &pieces_after) {
+            self.result.0 = Some(match king_in_check(opponent, &pieces_after) {
+                Some(_) => GameEnd::Checkmate {
+                    winner: piece.color,
+                },
+                None => GameEnd::Stalemate,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn select_piece(
+    entity: Entity,
+    piece: &Piece,
+    pieces: &[Piece],
+    selected_piece: &mut SelectedPiece,
+    highlights: &mut Highlights,
+) {
+    selected_piece.entity = Some(entity);
+    // Occupied destinations can only be enemy pieces
+    let (captures, moves): (Vec<_>, Vec<_>) = piece
+        .legal_moves(pieces)
+        .into_iter()
+        .partition(|pos| pieces.iter().any(|p| (p.x, p.y) == *pos));
+    highlights.set(HighlightLayer::Selection, [(piece.x, piece.y)]);
+    highlights.set(HighlightLayer::LegalMove, moves);
+    highlights.set(HighlightLayer::LegalCapture, captures);
+}
+
+fn deselect_piece(selected_piece: &mut SelectedPiece, highlights: &mut Highlights) {
+    selected_piece.entity = None;
+    highlights.clear(HighlightLayer::Selection);
+    highlights.clear(HighlightLayer::LegalMove);
+    highlights.clear(HighlightLayer::LegalCapture);
+}
+
+// Returns the square under a point on the board plane
+fn board_square(point: Vec3) -> Option<(u8, u8)> {
+    let (x, y) = (point.x.round(), point.z.round());
+    if (0.0..8.0).contains(&x) && (0.0..8.0).contains(&y) {
+        Some((x as u8, y as u8))
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn drag_pieces(
+    mut square_events: EventReader<SquareEvent>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    cameras: Query<&PickingCamera>,
+    mut drag: Local<DragState>,
+    mut last_selected: Local<Option<Entity>>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut moves: MoveParams,
+    mut transforms: Query<&mut Transform, With<Piece>>,
+) {
+    // Where the cursor ray hits the board plane
+    let cursor = cameras
+        .iter()
+        .find_map(|camera| {
+            camera.intersect_primitive(Primitive3d::Plane {
+                point: Vec3::ZERO,
+                normal: Vec3::Y,
+            })
+        })
+        .map(|intersection| intersection.position());
+
+    // The piece is gone if the game was left in the middle of a drag
+    if drag
+        .entity
+        .is_some_and(|entity| moves.pieces_query.get(entity).is_err())
+    {
+        *drag = DragState::default();
+    }
+
+    for event in square_events.iter() {
+        if let (SquareEvent::Clicked(pos), Some(cursor)) = (event, cursor) {
+            // Only the selected piece, or the one this press just deselected, can be dragged
+            let pressed = moves.pieces_query.iter().find(|(entity, piece)| {
+                (piece.x, piece.y) == *pos
+                    && (selected_piece.entity == Some(*entity) || *last_selected == Some(*entity))
+            });
+            if let Some((entity, _)) = pressed {
+                if !moves.history.is_browsing() {
+                    *drag = DragState {
+                        entity: Some(entity),
+                        start: cursor,
+                        dragging: false,
+                    };
+                }
+            }
+        }
+    }
+
+    if let Some(entity) = drag.entity {
+        if mouse_buttons.pressed(MouseButton::Left) {
+            if let Some(cursor) = cursor {
+                // Small movements while pressing still count as a click
+                if !drag.dragging && cursor.distance(drag.start) > DRAG_THRESHOLD {
+                    drag.dragging = true;
+                    moves.commands.entity(entity).insert(Dragged);
+                    if selected_piece.entity != Some(entity) {
+                        let pieces_vec = moves.pieces();
+                        if let Ok((_, piece)) = moves.pieces_query.get(entity) {
+                            select_piece(
+                                entity,
+                                piece,
+                                &pieces_vec,
+                                &mut selected_piece,
+                                &mut moves.highlights,
+                            );
                         }
-                        selected_square.entity = None;
-                        selected_piece.entity = None
-                    } else {
-                        // Select the piece in the currently selected square
-                        for (piece_entity, piece) in pieces_query.iter_mut() {
-                            if piece.x == square.x && piece.y == square.y {
-                                selected_piece.entity = Some(piece_entity);
-                                break;
-                            }
+                    }
+                }
+                if drag.dragging {
+                    if let Ok(mut transform) = transforms.get_mut(entity) {
+                        transform.translation = cursor + Vec3::Y * DRAG_HEIGHT;
+                    }
+                    let hovered = board_square(cursor);
+                    if hovered
+                        != moves
+                            .highlights
+                            .squares(HighlightLayer::Hover)
+                            .next()
+                            .copied()
+                    {
+                        match hovered {
+                            Some(pos) => moves.highlights.set(HighlightLayer::Hover, [pos]),
+                            None => moves.highlights.clear(HighlightLayer::Hover),
                         }
                     }
                 }
             }
+        } else {
+            // Dropped
+            if drag.dragging {
+                moves.commands.entity(entity).remove::<Dragged>();
+                // Putting it back where it was isn't a move
+                let from = moves
+                    .pieces_query
+                    .get(entity)
+                    .ok()
+                    .map(|(_, piece)| (piece.x, piece.y));
+                if let Some(pos) = cursor
+                    .and_then(board_square)
+                    .filter(|pos| Some(*pos) != from)
+                {
+                    if let Err(err) = moves.try_move(entity, pos, None) {
+                        moves.reject(err);
+                    }
+                }
+                // Illegal drops snap back, legal ones land on the new square
+                if let (Ok((_, piece)), Ok(mut transform)) =
+                    (moves.pieces_query.get(entity), transforms.get_mut(entity))
+                {
+                    transform.translation = Vec3::new(piece.x as f32, 0., piece.y as f32);
+                }
+                deselect_piece(&mut selected_piece, &mut moves.highlights);
+            }
+            *drag = DragState::default();
         }
     }
+
+    *last_selected = selected_piece.entity;
 }
 
 pub fn color_of_square(pos: (u8, u8), pieces: &Vec<Piece>) -> Option<PieceColor> {
@@ -230,3 +782,303 @@ pub fn is_path_empty(begin: (u8, u8), end: (u8, u8), pieces: &Vec<Piece>) -> boo
 
     true
 }
+
+// Returns whether any piece of the other color attacks the square
+pub fn is_square_attacked(square: (u8, u8), color: PieceColor, pieces: &[Piece]) -> bool {
+    pieces
+        .iter()
+        .any(|piece| piece.color != color && piece.attacks(square, pieces))
+}
+
+// Returns the square of the king of the given color if any enemy piece attacks it
+pub fn king_in_check(color: PieceColor, pieces: &[Piece]) -> Option<(u8, u8)> {
+    let king = pieces
+        .iter()
+        .find(|piece| piece.color == color && piece.piece_type == PieceType::King)?;
+    is_square_attacked((king.x, king.y), color, pieces).then_some((king.x, king.y))
+}
+
+// Returns whether the side has any legal move left
+pub fn has_legal_moves(color: PieceColor, pieces: &[Piece]) -> bool {
+    pieces
+        .iter()
+        .filter(|piece| piece.color == color)
+        .any(|piece| !piece.legal_moves(pieces).is_empty())
+}
+
+// Name of a square in algebraic notation, files are the y axis and ranks the x axis
+pub fn square_name(pos: (u8, u8)) -> String {
+    format!("{}{}", (b'a' + pos.1) as char, pos.0 + 1)
+}
+
+// Reads a square name like e4
+pub fn parse_square(name: &str) -> Option<(u8, u8)> {
+    match name.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some((rank - b'1', file - b'a')),
+        _ => None,
+    }
+}
+
+// A move in UCI notation, the from and to squares and the promotion piece like e7e8q
+pub fn uci(from: (u8, u8), to: (u8, u8), promotion: Option<PieceType>) -> String {
+    let mut uci = format!("{}{}", square_name(from), square_name(to));
+    if let Some(piece_type) = promotion {
+        uci.push(piece_char(PieceColor::Black, piece_type));
+    }
+    uci
+}
+
+// Reads a move in UCI notation
+pub fn parse_uci(uci: &str) -> Option<MoveRequest> {
+    let from = parse_square(uci.get(..2)?)?;
+    let to = parse_square(uci.get(2..4)?)?;
+    let promotion = match uci.get(4..)? {
+        "" => None,
+        c => match c.chars().next().and_then(piece_from_char) {
+            Some((_, piece_type)) if c.len() == 1 => Some(piece_type),
+            _ => return None,
+        },
+    };
+    Some(MoveRequest {
+        from,
+        to,
+        promotion,
+    })
+}
+
+// Returns whether the side could still checkmate with the help of the other side's pieces,
+// which is what decides a flag fall. A lone king can't, and neither can bishops that are all
+// on squares of one color. A single bishop or knight needs an enemy piece other than the king
+// to box the king in, like a pawn, so king and knight against king and pawn can still be lost
+// on time. Positions where the pieces happen to be locked in aren't worked out.
+pub fn has_mating_material(color: PieceColor, pieces: &[Piece]) -> bool {
+    let (own, others): (Vec<&Piece>, Vec<&Piece>) = pieces
+        .iter()
+        .filter(|piece| piece.piece_type != PieceType::King)
+        .partition(|piece| piece.color == color);
+    let square_color = |piece: &Piece| (piece.x + piece.y) % 2;
+    let bishops_on_one_color = |first: &Piece| {
+        own.iter().chain(others.iter()).all(|piece| {
+            piece.piece_type == PieceType::Bishop && square_color(piece) == square_color(first)
+        })
+    };
+    match own.as_slice() {
+        [] => false,
+        [first, ..] if bishops_on_one_color(first) => false,
+        [piece] if matches!(piece.piece_type, PieceType::Bishop | PieceType::Knight) => {
+            !others.is_empty()
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::fen::{read_fen, START_FEN};
+
+    // A world with just what try_move needs, set up with the position
+    fn game(fen: &str) -> World {
+        let position = read_fen(fen).unwrap();
+        let mut world = World::new();
+        world.init_resource::<Highlights>();
+        world.init_resource::<MoveHistory>();
+        world.init_resource::<GameResult>();
+        world.init_resource::<Events<MovePlayed>>();
+        world.init_resource::<Events<MoveRejected>>();
+        world.insert_resource(PlayerTurn(position.turn));
+        for piece in position.pieces {
+            world.spawn().insert(piece);
+        }
+        world
+    }
+
+    fn play(
+        world: &mut World,
+        from: &str,
+        to: &str,
+        promotion: Option<PieceType>,
+    ) -> Result<(), MoveError> {
+        let mut state: SystemState<MoveParams> = SystemState::new(world);
+        let mut moves = state.get_mut(world);
+        let entity = moves.piece_at(parse_square(from).unwrap()).unwrap();
+        let result = moves.try_move(entity, parse_square(to).unwrap(), promotion);
+        state.apply(world);
+        result
+    }
+
+    fn piece_on(world: &mut World, name: &str) -> Option<Piece> {
+        let square = parse_square(name).unwrap();
+        world
+            .query::<&Piece>()
+            .iter(world)
+            .find(|piece| (piece.x, piece.y) == square)
+            .copied()
+    }
+
+    #[test]
+    fn moves_out_of_turn_or_after_the_game_are_rejected() {
+        let mut world = game(START_FEN);
+        assert!(play(&mut world, "e7", "e5", None) == Err(MoveError::NotYourTurn));
+        assert!(play(&mut world, "e2", "e4", None).is_ok());
+        assert!(play(&mut world, "e4", "e5", None) == Err(MoveError::NotYourTurn));
+
+        world.resource_mut::<GameResult>().0 = Some(GameEnd::Stalemate);
+        assert!(play(&mut world, "e7", "e5", None) == Err(MoveError::GameOver));
+        assert_eq!(world.resource::<MoveHistory>().moves.len(), 1);
+    }
+
+    #[test]
+    fn only_reported_rejections_are_sent() {
+        let mut world = game(START_FEN);
+        assert!(play(&mut world, "e2", "e5", None).is_err());
+        assert!(world.resource::<Events<MoveRejected>>().is_empty());
+
+        let mut state: SystemState<MoveParams> = SystemState::new(&mut world);
+        state.get_mut(&mut world).reject(MoveError::BlockedPath);
+        state.apply(&mut world);
+        let events = world.resource::<Events<MoveRejected>>();
+        let reasons: Vec<MoveError> = events
+            .get_reader()
+            .iter(events)
+            .map(|MoveRejected(err)| *err)
+            .collect();
+        assert!(reasons == [MoveError::BlockedPath]);
+    }
+
+    #[test]
+    fn en_passant_takes_the_pawn_that_passed() {
+        let mut world = game("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1");
+        assert!(play(&mut world, "d7", "d5", None).is_ok());
+        assert!(piece_on(&mut world, "d5").is_some_and(|pawn| pawn.en_passant));
+        assert!(play(&mut world, "e5", "d6", None).is_ok());
+
+        assert!(piece_on(&mut world, "d5").is_none());
+        assert!(piece_on(&mut world, "d6")
+            .is_some_and(|pawn| pawn.color == PieceColor::White && !pawn.en_passant));
+        let captured: Vec<Entity> = world
+            .query_filtered::<Entity, With<Captured>>()
+            .iter(&world)
+            .collect();
+        assert_eq!(captured.len(), 1);
+        assert_eq!(world.resource::<MoveHistory>().moves[1].san, "exd6");
+    }
+
+    #[test]
+    fn en_passant_only_right_after_the_double_step() {
+        let mut world = game("4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1");
+        assert!(play(&mut world, "d7", "d5", None).is_ok());
+        assert!(play(&mut world, "e1", "e2", None).is_ok());
+        assert!(play(&mut world, "e8", "e7", None).is_ok());
+        assert!(play(&mut world, "e5", "d6", None) == Err(MoveError::NothingToCapture));
+
+        // A pawn that moved one square at a time can't be taken either
+        let mut world = game("4k3/8/3p4/4P3/8/8/8/4K3 b - - 0 1");
+        assert!(play(&mut world, "d6", "d5", None).is_ok());
+        assert!(play(&mut world, "e5", "d6", None) == Err(MoveError::NothingToCapture));
+    }
+
+    fn can_mate(color: PieceColor, fen: &str) -> bool {
+        has_mating_material(color, &read_fen(fen).unwrap().pieces)
+    }
+
+    #[test]
+    fn pawn_promotes_on_the_last_rank() {
+        let mut world = game("k7/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(play(&mut world, "e7", "e8", Some(PieceType::Knight)).is_ok());
+        assert!(
+            piece_on(&mut world, "e8").is_some_and(|piece| piece.piece_type == PieceType::Knight)
+        );
+        let record = &world.resource::<MoveHistory>().moves[0];
+        assert_eq!(record.san, "e8=N");
+        assert!(record.promotion == Some(PieceType::Knight));
+
+        // A queen unless another piece is asked for
+        let mut world = game("k7/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(play(&mut world, "e7", "e8", None).is_ok());
+        assert!(
+            piece_on(&mut world, "e8").is_some_and(|piece| piece.piece_type == PieceType::Queen)
+        );
+        assert_eq!(world.resource::<MoveHistory>().moves[0].san, "e8=Q+");
+    }
+
+    #[test]
+    fn pawn_cant_promote_to_a_king_or_pawn() {
+        let mut world = game("k7/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        for piece_type in [PieceType::King, PieceType::Pawn] {
+            let result = play(&mut world, "e7", "e8", Some(piece_type));
+            assert!(result == Err(MoveError::InvalidPromotion(piece_type)));
+        }
+        assert!(piece_on(&mut world, "e7").is_some());
+        // Other moves ignore the promotion piece
+        assert!(play(&mut world, "e1", "e2", Some(PieceType::Rook)).is_ok());
+        assert!(piece_on(&mut world, "e2").is_some_and(|piece| piece.piece_type == PieceType::King));
+    }
+
+    #[test]
+    fn mating_material() {
+        // Lone king
+        assert!(!can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/4K2q w - - 0 1"
+        ));
+        // A pawn or a major piece is always enough
+        assert!(can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+        ));
+        assert!(can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/4K2R w - - 0 1"
+        ));
+        // Two knights, or bishops on both colors
+        assert!(can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1"
+        ));
+        assert!(can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1"
+        ));
+    }
+
+    #[test]
+    fn minor_piece_needs_enemy_pieces() {
+        assert!(!can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/4KN2 w - - 0 1"
+        ));
+        assert!(!can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/4KB2 w - - 0 1"
+        ));
+        assert!(can_mate(
+            PieceColor::White,
+            "4k3/4p3/8/8/8/8/8/4KN2 w - - 0 1"
+        ));
+        assert!(can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/8/8/3qKB2 w - - 0 1"
+        ));
+    }
+
+    #[test]
+    fn bishops_on_one_color_cant_mate() {
+        // Bishops on f1 and d3 are both on light squares
+        assert!(!can_mate(
+            PieceColor::White,
+            "4k3/8/8/8/8/3B4/8/4KB2 w - - 0 1"
+        ));
+        // Opposite colored bishops can, the other bishop can box its king in
+        assert!(can_mate(
+            PieceColor::White,
+            "4kb2/8/8/8/8/8/8/4KB2 w - - 0 1"
+        ));
+        assert!(!can_mate(
+            PieceColor::White,
+            "2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1"
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+use std::f32::consts::PI;
+
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::render::camera::{Projection, ScalingMode};
+use bevy_mod_picking::PickingCameraBundle;
+
+use crate::board::PlayerTurn;
+use crate::game::{AppState, GameSetup, TextFocus};
+use crate::piece::PieceColor;
+
+// The camera circles around the middle of the board
+const FOCUS: Vec3 = Vec3::new(3.5, 0., 3.5);
+// World units shown from top to bottom of the window in the 2D view, at the default zoom
+const VIEW_HEIGHT_2D: f32 = 10.;
+
+// 3D scene or flat diagram seen straight from above, V switches between them
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewMode {
+    #[default]
+    ThreeD,
+    TwoD,
+}
+
+pub struct CameraSettings {
+    // Radians turned per pixel the mouse moves while orbiting
+    pub orbit_sensitivity: f32,
+    // Fraction of the distance zoomed per scroll line
+    pub zoom_sensitivity: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+    // How quickly the camera catches up with flips and resets, higher is faster
+    pub smoothing: f32,
+    // Turn the board towards the side to move after every move, for hotseat games
+    pub auto_rotate: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            orbit_sensitivity: 0.005,
+            zoom_sensitivity: 0.1,
+            min_distance: 5.,
+            max_distance: 30.,
+            min_pitch: 0.15,
+            max_pitch: 1.5,
+            smoothing: 6.,
+            auto_rotate: false,
+        }
+    }
+}
+
+// Where the camera is around the board, it eases towards the target angles and distance
+#[derive(Component)]
+pub struct OrbitCamera {
+    // Angle around the board, 0 looks from White's side and PI from Black's
+    pub yaw: f32,
+    // Angle above the board
+    pub pitch: f32,
+    pub distance: f32,
+    pub target_yaw: f32,
+    pub target_pitch: f32,
+    pub target_distance: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        let (yaw, pitch, distance) = default_view(PieceColor::White);
+        OrbitCamera {
+            yaw,
+            pitch,
+            distance,
+            target_yaw: yaw,
+            target_pitch: pitch,
+            target_distance: distance,
+        }
+    }
+}
+
+impl OrbitCamera {
+    // Turns towards a side's view the short way round
+    fn look_from(&mut self, color: PieceColor) {
+        let (yaw, pitch, distance) = default_view(color);
+        self.target_yaw = self.yaw + wrap_angle(yaw - self.yaw);
+        self.target_pitch = pitch;
+        self.target_distance = distance;
+    }
+
+    // The side whose pieces are nearest to the camera
+    pub fn side(&self) -> PieceColor {
+        if self.target_yaw.cos() >= 0. {
+            PieceColor::White
+        } else {
+            PieceColor::Black
+        }
+    }
+
+    fn transform(&self, view_mode: ViewMode) -> Transform {
+        if view_mode == ViewMode::TwoD {
+            // Looking straight down, turning with the yaw so flips still work
+            let up = Quat::from_rotation_y(self.yaw) * Vec3::X;
+            return Transform::from_translation(FOCUS + Vec3::Y * 20.).looking_at(FOCUS, up);
+        }
+        let offset = Quat::from_rotation_y(self.yaw)
+            * Vec3::new(-self.pitch.cos(), self.pitch.sin(), 0.)
+            * self.distance;
+        Transform::from_translation(FOCUS + offset).looking_at(FOCUS, Vec3::Y)
+    }
+}
+
+fn default_view(color: PieceColor) -> (f32, f32, f32) {
+    let yaw = match color {
+        PieceColor::White => 0.,
+        PieceColor::Black => PI,
+    };
+    (yaw, 1.1, 16.)
+}
+
+// Maps an angle to between -PI and PI
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2. * PI) - PI
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraSettings>()
+            .init_resource::<ViewMode>()
+            .add_startup_system(create_camera)
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(face_player))
+            .add_system(control_camera)
+            .add_system(follow_turn)
+            .add_system(move_camera.after(control_camera).after(follow_turn));
+    }
+}
+
+fn create_camera(mut commands: Commands) {
+    let orbit = OrbitCamera::default();
+    commands
+        .spawn_bundle(Camera3dBundle {
+            transform: orbit.transform(ViewMode::ThreeD),
+            ..Default::default()
+        })
+        .insert_bundle(PickingCameraBundle::default())
+        .insert(orbit);
+}
+
+fn face_player(setup: Res<GameSetup>, mut cameras: Query<&mut OrbitCamera>) {
+    for mut orbit in cameras.iter_mut() {
+        orbit.look_from(setup.player_color);
+    }
+}
+
+// Middle mouse drag orbits, the wheel zooms, F flips the board, R goes back to the default view
+// and V switches between the 3D and 2D views
+#[allow(clippy::too_many_arguments)]
+fn control_camera(
+    mouse_buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    mut view_mode: ResMut<ViewMode>,
+    mut motion_events: EventReader<MouseMotion>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut settings: ResMut<CameraSettings>,
+    ui_query: Query<&Interaction, With<Node>>,
+    mut cameras: Query<&mut OrbitCamera>,
+) {
+    // The wheel scrolls panels while the cursor is over them
+    let over_ui = ui_query
+        .iter()
+        .any(|interaction| *interaction != Interaction::None);
+    let drag: Vec2 = motion_events.iter().map(|event| &event.delta).sum();
+    let scroll: f32 = wheel_events
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / 20.,
+        })
+        .sum();
+    let key = |key_code: KeyCode| !focus.0 && keys.just_pressed(key_code);
+
+    if key(KeyCode::T) {
+        settings.auto_rotate = !settings.auto_rotate;
+    }
+    if key(KeyCode::V) {
+        *view_mode = match *view_mode {
+            ViewMode::ThreeD => ViewMode::TwoD,
+            ViewMode::TwoD => ViewMode::ThreeD,
+        };
+    }
+
+    for mut orbit in cameras.iter_mut() {
+        if mouse_buttons.pressed(MouseButton::Middle) && drag != Vec2::ZERO {
+            // Orbiting is direct, only flips and resets are eased
+            orbit.yaw -= drag.x * settings.orbit_sensitivity;
+            orbit.pitch = (orbit.pitch + drag.y * settings.orbit_sensitivity)
+                .clamp(settings.min_pitch, settings.max_pitch);
+            orbit.target_yaw = orbit.yaw;
+            orbit.target_pitch = orbit.pitch;
+        }
+        if scroll != 0. && !over_ui {
+            orbit.target_distance = (orbit.target_distance
+                * (1. - scroll * settings.zoom_sensitivity))
+                .clamp(settings.min_distance, settings.max_distance);
+        }
+        if key(KeyCode::F) {
+            let side = orbit.side();
+            orbit.look_from(side.opposite());
+        }
+        if key(KeyCode::R) {
+            let side = orbit.side();
+            orbit.look_from(side);
+        }
+    }
+}
+
+fn follow_turn(
+    settings: Res<CameraSettings>,
+    turn: Res<PlayerTurn>,
+    mut cameras: Query<&mut OrbitCamera>,
+) {
+    if !settings.auto_rotate || !(turn.is_changed() || settings.is_changed()) {
+        return;
+    }
+    for mut orbit in cameras.iter_mut() {
+        if orbit.side() != turn.0 {
+            orbit.look_from(turn.0);
+        }
+    }
+}
+
+fn move_camera(
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    view_mode: Res<ViewMode>,
+    mut cameras: Query<(&mut OrbitCamera, &mut Transform, &mut Projection)>,
+) {
+    let t = 1. - (-settings.smoothing * time.delta_seconds()).exp();
+    for (mut orbit, mut transform, mut projection) in cameras.iter_mut() {
+        orbit.yaw += (orbit.target_yaw - orbit.yaw) * t;
+        orbit.pitch += (orbit.target_pitch - orbit.pitch) * t;
+        orbit.distance += (orbit.target_distance - orbit.distance) * t;
+        *transform = orbit.transform(*view_mode);
+
+        match (*view_mode, &mut *projection) {
+            // Zooming scales the orthographic view the way it moves the 3D camera
+            (ViewMode::TwoD, Projection::Orthographic(orthographic)) => {
+                let scale = orbit.distance / default_view(PieceColor::White).2;
+                if orthographic.scale != scale {
+                    orthographic.scale = scale;
+                }
+            }
+            (ViewMode::TwoD, _) => {
+                *projection = Projection::Orthographic(OrthographicProjection {
+                    scaling_mode: ScalingMode::FixedVertical(VIEW_HEIGHT_2D),
+                    ..Default::default()
+                });
+            }
+            (ViewMode::ThreeD, Projection::Perspective(_)) => {}
+            (ViewMode::ThreeD, _) => *projection = Projection::default(),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+
+use crate::game::Hud;
+use crate::history::MoveHistory;
+use crate::piece::{Piece, PieceColor, PieceType};
+
+const FONT_SIZE: f32 = 28.;
+const PANEL_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+
+// Row listing the pieces a side has taken
+#[derive(Component)]
+struct CapturedTray(PieceColor);
+
+pub struct CapturedPlugin;
+
+impl Plugin for CapturedPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(create_trays)
+            .add_system(update_trays);
+    }
+}
+
+fn create_trays(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/DejaVuSans.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: UiRect::all(Val::Px(6.)),
+                min_size: Size::new(Val::Px(200.), Val::Undefined),
+                ..default()
+            },
+            color: PANEL_COLOR.into(),
+            ..default()
+        })
+        .insert(Hud)
+        .with_children(|parent| {
+            // Black's captures go on top, next to Black's side of the board
+            for color in [PieceColor::Black, PieceColor::White] {
+                parent
+                    .spawn_bundle(
+                        TextBundle::from_sections([
+                            TextSection::new(
+                                "",
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: FONT_SIZE,
+                                    color: Color::WHITE,
+                                },
+                            ),
+                            TextSection::new(
+                                "",
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: FONT_SIZE * 0.7,
+                                    color: Color::rgb(0.8, 0.8, 0.8),
+                                },
+                            ),
+                        ])
+                        .with_style(Style {
+                            // Keeps the row's height while nothing has been taken
+                            min_size: Size::new(Val::Undefined, Val::Px(FONT_SIZE * 1.2)),
+                            ..default()
+                        }),
+                    )
+                    .insert(CapturedTray(color));
+            }
+        });
+}
+
+pub fn glyph(color: PieceColor, piece_type: PieceType) -> char {
+    match (color, piece_type) {
+        (PieceColor::White, PieceType::King) => '♔',
+        (PieceColor::White, PieceType::Queen) => '♕',
+        (PieceColor::White, PieceType::Rook) => '♖',
+        (PieceColor::White, PieceType::Bishop) => '♗',
+        (PieceColor::White, PieceType::Knight) => '♘',
+        (PieceColor::White, PieceType::Pawn) => '♙',
+        (PieceColor::Black, PieceType::King) => '♚',
+        (PieceColor::Black, PieceType::Queen) => '♛',
+        (PieceColor::Black, PieceType::Rook) => '♜',
+        (PieceColor::Black, PieceType::Bishop) => '♝',
+        (PieceColor::Black, PieceType::Knight) => '♞',
+        (PieceColor::Black, PieceType::Pawn) => '♟',
+    }
+}
+
+// How much more material the side has on the board than its opponent
+fn material_difference(pieces: &[Piece], color: PieceColor) -> i32 {
+    pieces
+        .iter()
+        .map(|piece| {
+            let value = piece.piece_type.value() as i32;
+            if piece.color == color {
+                value
+            } else {
+                -value
+            }
+        })
+        .sum()
+}
+
+// Pieces taken up to the position shown on the board, so browsing the history updates the trays too.
+// They're shown as they were when taken, a promoted pawn counts as its new piece.
+// The material difference is counted from the pieces on the board, so promotions and set up
+// positions with uneven material are counted too.
+fn update_trays(
+    history: Res<MoveHistory>,
+    added_query: Query<(), Added<Piece>>,
+    pieces_query: Query<&Piece>,
+    mut trays_query: Query<(&mut Text, &CapturedTray)>,
+) {
+    if !history.is_changed() && added_query.is_empty() {
+        return;
+    }
+
+    let mut captured: Vec<Piece> = (1..=history.shown)
+        .flat_map(|ply| {
+            let after = history.position(ply);
+            history
+                .position(ply - 1)
+                .iter()
+                .filter(move |(entity, _)| !after.iter().any(|(other, _)| other == entity))
+                .map(|(_, piece)| *piece)
+        })
+        .collect();
+    captured.sort_by_key(|piece| std::cmp::Reverse(piece.piece_type.value()));
+
+    // Before the first move the history has no position yet, the pieces have just been set up
+    let on_board: Vec<Piece> = if history.moves.is_empty() {
+        pieces_query.iter().copied().collect()
+    } else {
+        history
+            .position(history.shown)
+            .iter()
+            .map(|(_, piece)| *piece)
+            .collect()
+    };
+
+    for (mut text, tray) in trays_query.iter_mut() {
+        text.sections[0].value = captured
+            .iter()
+            .filter(|piece| piece.color != tray.0)
+            .map(|piece| glyph(piece.color, piece.piece_type))
+            .collect();
+        let difference = material_difference(&on_board, tray.0);
+        text.sections[1].value = if difference > 0 {
+            format!(" +{}", difference)
+        } else {
+            String::new()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::{read_fen, START_FEN};
+
+    fn difference(fen: &str, color: PieceColor) -> i32 {
+        material_difference(&read_fen(fen).unwrap().pieces, color)
+    }
+
+    #[test]
+    fn even_at_the_start() {
+        assert_eq!(difference(START_FEN, PieceColor::White), 0);
+        assert_eq!(difference(START_FEN, PieceColor::Black), 0);
+    }
+
+    #[test]
+    fn counts_a_promoted_pawn_as_its_new_piece() {
+        // White's h pawn took its way to h8 and became a queen, Black lost a rook and a pawn
+        let fen = "rnbqkbnQ/ppppppp1/8/8/8/8/PPPPPPP1/RNBQKBNR b KQq - 0 5";
+        assert_eq!(difference(fen, PieceColor::White), 9 - 1 + 5 + 1);
+        assert_eq!(difference(fen, PieceColor::Black), -14);
+    }
+
+    #[test]
+    fn counts_uneven_start_positions() {
+        assert_eq!(
+            difference("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", PieceColor::White),
+            5
+        );
+    }
+}
@@ -0,0 +1,477 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{color_name, has_mating_material, GameEnd, GameResult, MovePlayed};
+use crate::game::{AppState, GameSetup, Hud};
+use crate::piece::{Piece, PieceColor};
+
+const FONT_SIZE: f32 = 26.;
+const ACTIVE_COLOR: Color = Color::rgba(0.9, 0.9, 0.9, 0.9);
+const INACTIVE_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const FLAG_COLOR: Color = Color::rgba(0.8, 0.1, 0.1, 0.9);
+
+// Time added or held back around each move
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bonus {
+    None,
+    // Seconds added after every move
+    Fischer(f32),
+    // Time used on a move is given back after it, up to this many seconds
+    Bronstein(f32),
+    // The clock only starts running this many seconds into a move
+    SimpleDelay(f32),
+}
+
+// A period of the game, the time is added when the stage starts
+#[derive(Clone, Copy)]
+pub struct TimeStage {
+    // Moves to be played in the stage, the last stage lasts for the rest of the game
+    pub moves: Option<u32>,
+    // In seconds
+    pub time: f32,
+}
+
+#[derive(Clone)]
+pub struct TimeControl {
+    pub stages: Vec<TimeStage>,
+    pub bonus: Bonus,
+}
+
+impl TimeControl {
+    pub fn sudden_death(minutes: f32) -> Self {
+        TimeControl {
+            stages: vec![TimeStage {
+                moves: None,
+                time: minutes * 60.,
+            }],
+            bonus: Bonus::None,
+        }
+    }
+
+    pub fn with_bonus(mut self, bonus: Bonus) -> Self {
+        self.bonus = bonus;
+        self
+    }
+
+    // 40 moves in 90 minutes, then 30 minutes for the rest of the game, 30 seconds added per move
+    pub fn classical() -> Self {
+        TimeControl {
+            stages: vec![
+                TimeStage {
+                    moves: Some(40),
+                    time: 90. * 60.,
+                },
+                TimeStage {
+                    moves: None,
+                    time: 30. * 60.,
+                },
+            ],
+            bonus: Bonus::Fischer(30.),
+        }
+    }
+}
+
+// Time controls offered when setting up a game
+pub fn time_controls() -> Vec<(&'static str, Option<TimeControl>)> {
+    vec![
+        ("Untimed", None),
+        ("Bullet 1+0", Some(TimeControl::sudden_death(1.))),
+        (
+            "Blitz 3+2",
+            Some(TimeControl::sudden_death(3.).with_bonus(Bonus::Fischer(2.))),
+        ),
+        (
+            "Blitz 5, 3 s delay",
+            Some(TimeControl::sudden_death(5.).with_bonus(Bonus::SimpleDelay(3.))),
+        ),
+        (
+            "Rapid 10+5",
+            Some(TimeControl::sudden_death(10.).with_bonus(Bonus::Fischer(5.))),
+        ),
+        (
+            "Rapid 25, 10 s Bronstein",
+            Some(TimeControl::sudden_death(25.).with_bonus(Bonus::Bronstein(10.))),
+        ),
+        ("Classical 40/90, 30+30", Some(TimeControl::classical())),
+    ]
+}
+
+// Preset picked unless the player chooses another one
+pub const DEFAULT_TIME_CONTROL: usize = 4;
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SideClock {
+    // Seconds left
+    pub remaining: f32,
+    // Moves played by this side
+    pub moves: u32,
+    stage: usize,
+}
+
+// Both sides' clocks, only the side to move's clock runs.
+// Without a time control the game is untimed and no clocks are shown.
+pub struct GameClock {
+    pub control: Option<TimeControl>,
+    pub white: SideClock,
+    pub black: SideClock,
+    pub running: PieceColor,
+    // Time spent on the current move, for delays
+    move_time: f32,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock::new(
+            time_controls().swap_remove(DEFAULT_TIME_CONTROL).1,
+            PieceColor::White,
+        )
+    }
+}
+
+impl GameClock {
+    // Starts with the clock of the side to move running
+    pub fn new(control: Option<TimeControl>, first: PieceColor) -> Self {
+        let start = SideClock {
+            remaining: control
+                .as_ref()
+                .and_then(|control| control.stages.first())
+                .map_or(0., |stage| stage.time),
+            moves: 0,
+            stage: 0,
+        };
+        GameClock {
+            control,
+            white: start,
+            black: start,
+            running: first,
+            move_time: 0.,
+        }
+    }
+
+    pub fn side(&self, color: PieceColor) -> &SideClock {
+        match color {
+            PieceColor::White => &self.white,
+            PieceColor::Black => &self.black,
+        }
+    }
+
+    fn side_mut(&mut self, color: PieceColor) -> &mut SideClock {
+        match color {
+            PieceColor::White => &mut self.white,
+            PieceColor::Black => &mut self.black,
+        }
+    }
+
+    // Puts back clocks saved in the middle of a game
+    pub fn resume(&mut self, white: SideClock, black: SideClock, running: PieceColor) {
+        self.white = white;
+        self.black = black;
+        self.running = running;
+        self.move_time = 0.;
+    }
+
+    // Runs the clock of the side to move. Returns true when its flag falls.
+    fn tick(&mut self, delta: f32) -> bool {
+        let delay = match self.control.as_ref().map(|control| control.bonus) {
+            Some(Bonus::SimpleDelay(delay)) => delay,
+            Some(_) => 0.,
+            None => return false,
+        };
+        let counted = (self.move_time + delta - delay).max(0.) - (self.move_time - delay).max(0.);
+        self.move_time += delta;
+
+        let side = self.side_mut(self.running);
+        side.remaining = (side.remaining - counted).max(0.);
+        side.remaining == 0.
+    }
+
+    // Stops the clock of the side that moved and starts the other one
+    fn press(&mut self, color: PieceColor) {
+        let control = match self.control.clone() {
+            Some(control) => control,
+            None => return,
+        };
+        let move_time = self.move_time;
+        let side = self.side_mut(color);
+        side.moves += 1;
+        match control.bonus {
+            Bonus::Fischer(increment) => side.remaining += increment,
+            Bonus::Bronstein(delay) => side.remaining += move_time.min(delay),
+            Bonus::None | Bonus::SimpleDelay(_) => {}
+        }
+
+        // The next stage's time is added once the moves of this stage have been played
+        let stage_end: u32 = control.stages[..=side.stage]
+            .iter()
+            .map(|stage| stage.moves.unwrap_or(u32::MAX))
+            .fold(0u32, |total, moves| total.saturating_add(moves));
+        if side.moves >= stage_end && side.stage + 1 < control.stages.len() {
+            side.stage += 1;
+            side.remaining += control.stages[side.stage].time;
+        }
+
+        self.running = color.opposite();
+        self.move_time = 0.;
+    }
+}
+
+#[derive(Component)]
+struct ClockDisplay(PieceColor);
+
+#[derive(Component)]
+struct ResultDisplay;
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameClock>()
+            .add_startup_system(create_clocks)
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(reset_clock))
+            .add_system(press_clock)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing).with_system(run_clock.after(press_clock)),
+            )
+            .add_system(show_clocks.after(run_clock));
+    }
+}
+
+fn reset_clock(setup: Res<GameSetup>, mut clock: ResMut<GameClock>) {
+    let control = time_controls().swap_remove(setup.time_control).1;
+    *clock = GameClock::new(control, setup.position().turn);
+}
+
+pub fn press_clock(mut move_events: EventReader<MovePlayed>, mut clock: ResMut<GameClock>) {
+    for event in move_events.iter() {
+        clock.press(event.color);
+    }
+}
+
+// A flag fall loses the game, unless the opponent couldn't have mated anyway
+fn run_clock(
+    time: Res<Time>,
+    mut clock: ResMut<GameClock>,
+    mut result: ResMut<GameResult>,
+    pieces_query: Query<&Piece>,
+) {
+    if result.0.is_some() {
+        return;
+    }
+    if clock.tick(time.delta_seconds()) {
+        let winner = clock.running.opposite();
+        let pieces: Vec<Piece> = pieces_query.iter().copied().collect();
+        result.0 = Some(if has_mating_material(winner, &pieces) {
+            GameEnd::Timeout { winner }
+        } else {
+            GameEnd::TimeoutDraw
+        });
+    }
+}
+
+fn create_clocks(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(10.),
+                    left: Val::Percent(50.),
+                    ..default()
+                },
+                margin: UiRect {
+                    left: Val::Px(-120.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(240.), Val::Undefined),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Hud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Undefined),
+                        justify_content: JustifyContent::SpaceBetween,
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for color in [PieceColor::White, PieceColor::Black] {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(116.), Val::Px(FONT_SIZE * 1.5)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                color: INACTIVE_COLOR.into(),
+                                ..default()
+                            })
+                            .insert(ClockDisplay(color))
+                            .with_children(|parent| {
+                                parent
+                                    .spawn_bundle(TextBundle::from_section("", text_style.clone()));
+                            });
+                    }
+                });
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: FONT_SIZE * 0.7,
+                        ..text_style.clone()
+                    },
+                ))
+                .insert(ResultDisplay);
+        });
+}
+
+// Minutes and seconds, with tenths in the last ten seconds
+fn format_time(seconds: f32) -> String {
+    if seconds < 10. {
+        format!("0:{:04.1}", (seconds * 10.).floor() / 10.)
+    } else {
+        let seconds = seconds.ceil() as u32;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn show_clocks(
+    clock: Res<GameClock>,
+    result: Res<GameResult>,
+    mut displays_query: Query<(&ClockDisplay, &mut UiColor, &mut Visibility, &Children)>,
+    mut texts_query: Query<&mut Text, Without<ResultDisplay>>,
+    mut result_query: Query<&mut Text, With<ResultDisplay>>,
+) {
+    for (display, mut ui_color, mut visibility, children) in displays_query.iter_mut() {
+        visibility.is_visible = clock.control.is_some();
+        let side = clock.side(display.0);
+        let flagged = side.remaining == 0.;
+        let active = clock.running == display.0 && result.0.is_none();
+        ui_color.0 = if flagged {
+            FLAG_COLOR
+        } else if active {
+            ACTIVE_COLOR
+        } else {
+            INACTIVE_COLOR
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = texts_query.get_mut(*child) {
+                text.sections[0].value = format!(
+                    "{} {}",
+                    &color_name(display.0)[..1],
+                    format_time(side.remaining)
+                );
+                text.sections[0].style.color = if active { Color::BLACK } else { Color::WHITE };
+            }
+        }
+    }
+
+    if result.is_changed() {
+        for mut text in result_query.iter_mut() {
+            text.sections[0].value = result.0.map(|end| end.description()).unwrap_or_default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(control: TimeControl) -> GameClock {
+        GameClock::new(Some(control), PieceColor::White)
+    }
+
+    #[test]
+    fn fischer_adds_the_increment() {
+        let mut clock = clock(TimeControl::sudden_death(1.).with_bonus(Bonus::Fischer(2.)));
+        assert!(!clock.tick(10.));
+        clock.press(PieceColor::White);
+        assert_eq!(clock.white.remaining, 52.);
+        assert_eq!(clock.black.remaining, 60.);
+        assert!(clock.running == PieceColor::Black);
+    }
+
+    #[test]
+    fn bronstein_gives_back_up_to_the_delay() {
+        let mut clock = clock(TimeControl::sudden_death(1.).with_bonus(Bonus::Bronstein(5.)));
+        clock.tick(3.);
+        clock.press(PieceColor::White);
+        assert_eq!(clock.white.remaining, 60.);
+        clock.tick(8.);
+        clock.press(PieceColor::Black);
+        assert_eq!(clock.black.remaining, 57.);
+    }
+
+    #[test]
+    fn simple_delay_holds_the_clock_back() {
+        let mut clock = clock(TimeControl::sudden_death(1.).with_bonus(Bonus::SimpleDelay(3.)));
+        clock.tick(2.);
+        assert_eq!(clock.white.remaining, 60.);
+        // Only the second past the delay counts
+        clock.tick(2.);
+        assert_eq!(clock.white.remaining, 59.);
+        clock.press(PieceColor::White);
+        assert_eq!(clock.white.remaining, 59.);
+        // The delay starts over on the next move
+        clock.tick(3.);
+        assert_eq!(clock.black.remaining, 60.);
+    }
+
+    #[test]
+    fn flag_falls_at_zero() {
+        let mut clock = clock(TimeControl::sudden_death(1.));
+        assert!(!clock.tick(59.));
+        assert!(clock.tick(2.));
+        assert_eq!(clock.white.remaining, 0.);
+    }
+
+    #[test]
+    fn untimed_clock_never_runs() {
+        let mut clock = GameClock::new(None, PieceColor::White);
+        assert!(!clock.tick(1000.));
+        clock.press(PieceColor::White);
+        assert_eq!(clock.white.moves, 0);
+    }
+
+    #[test]
+    fn next_stage_starts_after_its_moves() {
+        let mut clock = clock(TimeControl {
+            stages: vec![
+                TimeStage {
+                    moves: Some(2),
+                    time: 60.,
+                },
+                TimeStage {
+                    moves: None,
+                    time: 30.,
+                },
+            ],
+            bonus: Bonus::None,
+        });
+        for color in [PieceColor::White, PieceColor::Black, PieceColor::White] {
+            clock.tick(10.);
+            clock.press(color);
+        }
+        assert_eq!(clock.white.remaining, 40. + 30.);
+        assert_eq!(clock.black.remaining, 50.);
+        // The last stage lasts for the rest of the game
+        for _ in 0..10 {
+            clock.press(PieceColor::White);
+        }
+        assert_eq!(clock.white.remaining, 70.);
+    }
+}
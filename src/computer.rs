@@ -0,0 +1,214 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::board::{GameResult, MoveRequest, PlayerTurn};
+use crate::clock::{Bonus, GameClock};
+use crate::engine::eval::EvalParams;
+use crate::engine::nnue::{Network, DEFAULT_NETWORK_FILE};
+use crate::engine::{Board, Engine, Limits, Move};
+use crate::game::{AppState, GameSetup};
+use crate::history::MoveHistory;
+use crate::piece::{Piece, PieceColor};
+
+// Time the computer takes over a move when the game has no clock
+const UNTIMED_MOVE_TIME: Duration = Duration::from_secs(3);
+
+// A search running on its own thread
+struct Thinking {
+    // Moves played in the position it's searching
+    ply: usize,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Option<Move>>,
+}
+
+impl Thinking {
+    // The thread is left to finish on its own, its move is never read
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+struct ComputerPlayer {
+    engine: Engine,
+    thinking: Option<Thinking>,
+    // Moves played when the computer last asked for its move, so it isn't searched for twice
+    played: Option<usize>,
+}
+
+impl Default for ComputerPlayer {
+    fn default() -> Self {
+        let mut engine = Engine::default();
+        match EvalParams::tuned() {
+            Ok(params) => engine.set_params(params),
+            Err(err) => warn!("Evaluating with the default weights: {}", err),
+        }
+        ComputerPlayer {
+            engine,
+            thinking: None,
+            played: None,
+        }
+    }
+}
+
+impl ComputerPlayer {
+    fn stop_thinking(&mut self) {
+        if let Some(thinking) = self.thinking.take() {
+            thinking.stop();
+        }
+    }
+}
+
+// The evaluation network, if one was found
+pub struct EvalNetwork(pub Option<Arc<Network>>);
+
+impl Default for EvalNetwork {
+    fn default() -> Self {
+        match Network::load(Path::new(DEFAULT_NETWORK_FILE)) {
+            Ok(network) => EvalNetwork(Some(Arc::new(network))),
+            Err(err) => {
+                info!("Evaluating classically, no network: {}", err);
+                EvalNetwork(None)
+            }
+        }
+    }
+}
+
+pub struct ComputerPlugin;
+
+impl Plugin for ComputerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ComputerPlayer>()
+            .init_resource::<EvalNetwork>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(new_game))
+            .add_system_set(SystemSet::on_exit(AppState::Playing).with_system(stop_thinking))
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(play_moves));
+    }
+}
+
+fn new_game(
+    setup: Res<GameSetup>,
+    network: Res<EvalNetwork>,
+    mut computer: ResMut<ComputerPlayer>,
+) {
+    computer.stop_thinking();
+    computer.played = None;
+    computer.engine.set_threads(setup.threads);
+    let network = network.0.clone().filter(|_| setup.use_network);
+    computer.engine.set_network(network);
+    computer.engine.new_game();
+}
+
+fn stop_thinking(mut computer: ResMut<ComputerPlayer>) {
+    computer.stop_thinking();
+}
+
+// Starts a search when it's the computer's turn and plays its move once it's found
+#[allow(clippy::too_many_arguments)]
+fn play_moves(
+    setup: Res<GameSetup>,
+    turn: Res<PlayerTurn>,
+    result: Res<GameResult>,
+    history: Res<MoveHistory>,
+    clock: Res<GameClock>,
+    pieces_query: Query<&Piece>,
+    mut computer: ResMut<ComputerPlayer>,
+    mut move_requests: EventWriter<MoveRequest>,
+) {
+    let ply = history.moves.len();
+    let to_move = setup.computer_color() == Some(turn.0) && result.0.is_none();
+    // A search for a position that's gone, like after the game ended on the clock
+    if computer
+        .thinking
+        .as_ref()
+        .is_some_and(|thinking| !to_move || thinking.ply != ply)
+    {
+        computer.stop_thinking();
+        return;
+    }
+    // The move asked for has been played
+    if computer.played.is_some_and(|played| played != ply) {
+        computer.played = None;
+    }
+    if !to_move || computer.played == Some(ply) {
+        return;
+    }
+
+    match computer.thinking.take() {
+        // Moves aren't played while an earlier position is looked at, so it waits
+        Some(thinking) if !thinking.handle.is_finished() || history.is_browsing() => {
+            computer.thinking = Some(thinking);
+        }
+        Some(thinking) => {
+            computer.played = Some(ply);
+            match thinking.handle.join() {
+                Ok(Some(mv)) => {
+                    let (from, to) = mv.squares();
+                    move_requests.send(MoveRequest {
+                        from,
+                        to,
+                        promotion: mv.promotion,
+                    });
+                }
+                Ok(None) => warn!("The computer found no move to play"),
+                Err(_) => error!("The computer's search failed"),
+            }
+        }
+        None => {
+            let board = current_board(&history, turn.0, &pieces_query);
+            let limits = Limits {
+                stop: Some(Arc::new(AtomicBool::new(false))),
+                ..move_limits(&clock, turn.0)
+            };
+            let stop = limits.stop.clone().unwrap();
+            let engine = computer.engine.clone();
+            let handle = thread::spawn(move || engine.search(&board, &limits, |_| {}).best_move());
+            computer.thinking = Some(Thinking { ply, stop, handle });
+        }
+    }
+}
+
+// The game is replayed from its start, so the engine knows which positions were repeated
+fn current_board(history: &MoveHistory, turn: PieceColor, pieces_query: &Query<&Piece>) -> Board {
+    if history.moves.is_empty() {
+        let pieces: Vec<Piece> = pieces_query.iter().copied().collect();
+        return Board::from_pieces(&pieces, turn);
+    }
+    let pieces: Vec<Piece> = history.start.iter().map(|(_, piece)| *piece).collect();
+    let first = if history.moves.len().is_multiple_of(2) {
+        turn
+    } else {
+        turn.opposite()
+    };
+    let mut board = Board::from_pieces(&pieces, first);
+    for record in &history.moves {
+        board.make(Move::new(record.from, record.to, record.promotion));
+    }
+    board
+}
+
+fn move_limits(clock: &GameClock, color: PieceColor) -> Limits {
+    let control = match &clock.control {
+        Some(control) => control,
+        None => {
+            return Limits {
+                time: Some(UNTIMED_MOVE_TIME),
+                ..Default::default()
+            }
+        }
+    };
+    let increment = match control.bonus {
+        Bonus::None => 0.,
+        Bonus::Fischer(seconds) | Bonus::Bronstein(seconds) | Bonus::SimpleDelay(seconds) => {
+            seconds
+        }
+    };
+    Limits::for_clock(
+        Duration::from_secs_f32(clock.side(color).remaining.max(0.)),
+        Duration::from_secs_f32(increment),
+    )
+}
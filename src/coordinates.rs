@@ -0,0 +1,154 @@
+use std::f32::consts::PI;
+
+use ab_glyph::{Font as _, PxScale, ScaleFont as _};
+use bevy::prelude::*;
+
+use crate::board::Square;
+use crate::camera::OrbitCamera;
+use crate::piece::PieceColor;
+
+const LABEL_COLOR: Color = Color::rgb(0.85, 0.85, 0.8);
+// Height of the font in world units, and in pixels in the label textures
+const LABEL_SIZE: f32 = 0.35;
+const GLYPH_PIXELS: f32 = 64.;
+// How far outside the squares the labels sit
+const MARGIN: f32 = 0.8;
+
+// A file (a-h) or rank (1-8) label next to the board.
+// They're quads lying on the board's frame, so they turn with the board and the camera
+// can lose sight of them like anything else in the scene.
+#[derive(Component)]
+enum CoordinateLabel {
+    File(u8),
+    Rank(u8),
+}
+
+// How far the center of a label's quad is above the line the labels are centered on
+#[derive(Component)]
+struct GlyphOffset(f32);
+
+struct LabelFont(Handle<Font>);
+
+impl FromWorld for LabelFont {
+    fn from_world(world: &mut World) -> Self {
+        LabelFont(
+            world
+                .resource::<AssetServer>()
+                .load("fonts/FiraSans-Bold.ttf"),
+        )
+    }
+}
+
+pub struct CoordinatesPlugin;
+
+impl Plugin for CoordinatesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LabelFont>()
+            .add_system(create_labels)
+            .add_system(place_labels.after(create_labels));
+    }
+}
+
+// Each label is drawn from its glyph once the font has loaded
+fn create_labels(
+    mut commands: Commands,
+    label_font: Res<LabelFont>,
+    fonts: Res<Assets<Font>>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    labels_query: Query<(), With<CoordinateLabel>>,
+) {
+    if !labels_query.is_empty() {
+        return;
+    }
+    let font = match fonts.get(&label_font.0) {
+        Some(font) => &font.font,
+        None => return,
+    };
+    let scaled = font.as_scaled(PxScale::from(GLYPH_PIXELS));
+    // Labels are centered on the middle of a capital letter rather than their own middle,
+    // so that they all sit on the same baseline
+    let middle = scaled.ascent() * 0.35;
+
+    for i in 0..8 {
+        for (c, label) in [
+            ((b'a' + i) as char, CoordinateLabel::File(i)),
+            ((b'1' + i) as char, CoordinateLabel::Rank(i)),
+        ] {
+            let glyph = match font.outline_glyph(scaled.scaled_glyph(c)) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            let bounds = glyph.px_bounds();
+            let to_world = LABEL_SIZE / GLYPH_PIXELS;
+            // Glyph bounds grow downwards from the baseline
+            let offset = (-(bounds.min.y + bounds.max.y) / 2. - middle) * to_world;
+            let size = Vec2::new(bounds.width(), bounds.height()) * to_world;
+
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Quad::new(size))),
+                    material: materials.add(StandardMaterial {
+                        base_color: LABEL_COLOR,
+                        base_color_texture: Some(
+                            images.add(Font::get_outlined_glyph_texture(glyph)),
+                        ),
+                        alpha_mode: AlphaMode::Blend,
+                        unlit: true,
+                        ..Default::default()
+                    }),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(label)
+                .insert(GlyphOffset(offset));
+        }
+    }
+}
+
+// Files are written along the rank nearest the camera and ranks along the file on its left,
+// upright for the player on that side, so the labels move to the other edges when the board is
+// seen from Black's side. They're hidden while there's no board, like in the menus.
+fn place_labels(
+    cameras: Query<&OrbitCamera>,
+    squares_query: Query<(), With<Square>>,
+    mut labels: Query<(
+        &CoordinateLabel,
+        &GlyphOffset,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    let side = match cameras.get_single() {
+        Ok(orbit) => orbit.side(),
+        Err(_) => return,
+    };
+    // Quads face +Z with their text running along +X. Lying down, the text runs along the
+    // files, a to h, and its top points towards Black.
+    let flat = Quat::from_mat3(&Mat3::from_cols(Vec3::Z, Vec3::X, Vec3::Y));
+    let (near_rank, left_file, rotation) = if side == PieceColor::Black {
+        (7. + MARGIN, 7. + MARGIN, Quat::from_rotation_y(PI) * flat)
+    } else {
+        (-MARGIN, -MARGIN, flat)
+    };
+    let board_shown = !squares_query.is_empty();
+
+    for (label, offset, mut transform, mut visibility) in labels.iter_mut() {
+        let point = match label {
+            CoordinateLabel::File(file) => Vec3::new(near_rank, 0., *file as f32),
+            CoordinateLabel::Rank(rank) => Vec3::new(*rank as f32, 0., left_file),
+        };
+        let placed = Transform {
+            translation: point + rotation * Vec3::Y * offset.0,
+            rotation,
+            ..Default::default()
+        };
+        if *transform != placed {
+            *transform = placed;
+        }
+        if visibility.is_visible != board_shown {
+            visibility.is_visible = board_shown;
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+
+use crate::board::{Square, SquareEvent};
+use crate::camera::OrbitCamera;
+use crate::game::{GameSetup, TextFocus};
+use crate::highlight::{HighlightLayer, Highlights};
+use crate::piece::PieceColor;
+
+// Square picked with the keyboard, there's none until Up or Down is pressed and Esc hides it.
+// Left and Right step through the move history while there's no cursor.
+#[derive(Default)]
+pub struct KeyboardCursor(pub Option<(u8, u8)>);
+
+pub struct CursorPlugin;
+
+impl Plugin for CursorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KeyboardCursor>()
+            .add_system(move_cursor)
+            .add_system(show_cursor.after(move_cursor));
+    }
+}
+
+// Board step for "up" on screen, the direction the camera looks along
+fn forward(camera: &OrbitCamera) -> (i8, i8) {
+    let direction = Quat::from_rotation_y(camera.yaw) * Vec3::X;
+    if direction.x.abs() >= direction.z.abs() {
+        (direction.x.signum() as i8, 0)
+    } else {
+        (0, direction.z.signum() as i8)
+    }
+}
+
+// The arrow keys move the cursor as seen from the camera. Up or Down brings it up on the
+// player's king square. Enter clicks the square under it, selecting a piece or dropping the
+// selected one.
+fn move_cursor(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    setup: Res<GameSetup>,
+    mut cursor: ResMut<KeyboardCursor>,
+    mut square_events: EventWriter<SquareEvent>,
+    squares_query: Query<&Square>,
+    cameras: Query<&OrbitCamera>,
+) {
+    if squares_query.is_empty() {
+        if cursor.0.is_some() {
+            cursor.0 = None;
+        }
+        return;
+    }
+    if focus.0 {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) && cursor.0.is_some() {
+        cursor.0 = None;
+        return;
+    }
+
+    let (fx, fy) = cameras.get_single().map(forward).unwrap_or((1, 0));
+    // Right of forward, looking down on the board
+    let (rx, ry) = (-fy, fx);
+    let step = [
+        (KeyCode::Up, (fx, fy)),
+        (KeyCode::Down, (-fx, -fy)),
+        (KeyCode::Right, (rx, ry)),
+        (KeyCode::Left, (-rx, -ry)),
+    ]
+    .into_iter()
+    .find(|(key, _)| keys.just_pressed(*key))
+    .map(|(_, step)| step);
+
+    let starts = keys.any_just_pressed([KeyCode::Up, KeyCode::Down]);
+    match (cursor.0, step) {
+        (Some((x, y)), Some((dx, dy))) => {
+            cursor.0 = Some((
+                (x as i8 + dx).clamp(0, 7) as u8,
+                (y as i8 + dy).clamp(0, 7) as u8,
+            ));
+        }
+        (None, _) if starts => {
+            cursor.0 = Some(match setup.player_color {
+                PieceColor::White => (0, 4),
+                PieceColor::Black => (7, 4),
+            });
+        }
+        _ => {}
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        if let Some(pos) = cursor.0 {
+            square_events.send(SquareEvent::Clicked(pos));
+        }
+    }
+}
+
+// Highlights are reset when a game starts, so the layer is checked rather than the cursor's changes
+fn show_cursor(cursor: Res<KeyboardCursor>, mut highlights: ResMut<Highlights>) {
+    match cursor.0 {
+        Some(pos) if !highlights.contains(HighlightLayer::Cursor, pos) => {
+            highlights.set(HighlightLayer::Cursor, [pos])
+        }
+        None if !highlights.is_empty(HighlightLayer::Cursor) => {
+            highlights.clear(HighlightLayer::Cursor)
+        }
+        _ => {}
+    }
+}
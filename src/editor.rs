@@ -0,0 +1,479 @@
+use bevy::prelude::*;
+
+use crate::board::{color_name, square_name, SquareEvent};
+use crate::captured::glyph;
+use crate::fen::{
+    castling_rights, parse_fen, read_fen, set_castling_rights, to_fen, CASTLING, START_FEN,
+};
+use crate::game::{change_state, AppState, GameSetup};
+use crate::highlight::{HighlightLayer, Highlights};
+use crate::piece::{Piece, PieceColor, PieceType};
+use crate::pieces::spawn_piece;
+
+const FONT_SIZE: f32 = 20.;
+const GLYPH_SIZE: f32 = 28.;
+const PANEL_WIDTH: f32 = 300.;
+const BUTTON_HEIGHT: f32 = 32.;
+
+const PANEL_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.9);
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+const HOVERED_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+const SELECTED_COLOR: Color = Color::rgb(0.3, 0.5, 0.8);
+const ERROR_COLOR: Color = Color::rgb(1., 0.4, 0.4);
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::King,
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Pawn,
+];
+
+// The position being set up. The pieces are the ones on the board.
+struct BoardEditor {
+    // Piece placed by left clicks
+    brush: (PieceColor, PieceType),
+    turn: PieceColor,
+    // Wanted castling rights, in FEN order. They only count while the king and rook
+    // are on their starting squares.
+    castling: [bool; 4],
+    // Pawn that just moved two squares
+    en_passant: Option<(u8, u8)>,
+    // Why the position can't be played
+    error: String,
+}
+
+impl Default for BoardEditor {
+    fn default() -> Self {
+        BoardEditor {
+            brush: (PieceColor::White, PieceType::Queen),
+            turn: PieceColor::White,
+            castling: [false; 4],
+            en_passant: None,
+            error: String::new(),
+        }
+    }
+}
+
+impl BoardEditor {
+    fn castling(&self) -> String {
+        CASTLING
+            .iter()
+            .zip(self.castling)
+            .filter(|(_, on)| *on)
+            .map(|((c, _, _), _)| *c)
+            .collect()
+    }
+
+    // The pieces with the castling and en passant options applied
+    fn position(&self, pieces: &[Piece]) -> Vec<Piece> {
+        let mut pieces = pieces.to_vec();
+        set_castling_rights(&mut pieces, &self.castling());
+        for piece in pieces.iter_mut() {
+            piece.en_passant = self.en_passant == Some((piece.x, piece.y));
+        }
+        pieces
+    }
+
+    fn fen(&self, pieces: &[Piece]) -> String {
+        to_fen(&self.position(pieces), self.turn)
+    }
+}
+
+// Pawns that could have just moved two squares: pawns of the side that moved
+// on their fourth rank, with the two squares behind them empty
+fn en_passant_pawns(pieces: &[Piece], turn: PieceColor) -> Vec<(u8, u8)> {
+    let color = turn.opposite();
+    let (rank, passed, start) = match color {
+        PieceColor::White => (3, 2, 1),
+        PieceColor::Black => (4, 5, 6),
+    };
+    let mut pawns: Vec<(u8, u8)> = pieces
+        .iter()
+        .filter(|piece| {
+            piece.color == color && piece.piece_type == PieceType::Pawn && piece.x == rank
+        })
+        .filter(|pawn| {
+            !pieces.iter().any(|piece| {
+                (piece.x, piece.y) == (passed, pawn.y) || (piece.x, piece.y) == (start, pawn.y)
+            })
+        })
+        .map(|pawn| (pawn.x, pawn.y))
+        .collect();
+    pawns.sort_by_key(|pawn| pawn.1);
+    pawns
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+enum EditorButton {
+    Brush(PieceColor, PieceType),
+    Clear,
+    StartPosition,
+    Turn,
+    // Index into the castling rights
+    Castling(usize),
+    EnPassant,
+    Back,
+    Play,
+}
+
+#[derive(Component)]
+struct EditorPanel;
+
+#[derive(Component)]
+struct EditorError;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BoardEditor>()
+            .add_system_set(SystemSet::on_enter(AppState::Editor).with_system(open_editor))
+            .add_system_set(SystemSet::on_exit(AppState::Editor).with_system(close_editor))
+            .add_system_set(
+                SystemSet::on_update(AppState::Editor)
+                    .with_system(edit_squares)
+                    .with_system(press_buttons.after(edit_squares))
+                    .with_system(drop_en_passant.after(press_buttons))
+                    .with_system(update_panel.after(drop_en_passant)),
+            );
+    }
+}
+
+fn spawn_position(commands: &mut Commands, editor: &mut BoardEditor, fen: &str) {
+    let position =
+        read_fen(fen).unwrap_or_else(|_| read_fen(START_FEN).expect("the start is valid"));
+    let rights = castling_rights(&position.pieces);
+    *editor = BoardEditor {
+        brush: editor.brush,
+        turn: position.turn,
+        castling: CASTLING.map(|(c, _, _)| rights.contains(c)),
+        en_passant: position
+            .pieces
+            .iter()
+            .find(|piece| piece.en_passant)
+            .map(|pawn| (pawn.x, pawn.y)),
+        error: String::new(),
+    };
+    for piece in position.pieces {
+        spawn_piece(commands, piece);
+    }
+}
+
+// Starts from the position in the game setup
+fn open_editor(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    setup: Res<GameSetup>,
+    mut editor: ResMut<BoardEditor>,
+    mut highlights: ResMut<Highlights>,
+) {
+    *highlights = Highlights::default();
+    spawn_position(&mut commands, &mut editor, &setup.fen);
+
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+    let glyph_style = TextStyle {
+        font: asset_server.load("fonts/DejaVuSans.ttf"),
+        font_size: GLYPH_SIZE,
+        color: Color::WHITE,
+    };
+    let button =
+        |parent: &mut ChildBuilder, button: EditorButton, width: Val, style: &TextStyle| {
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(width, Val::Px(BUTTON_HEIGHT)),
+                        margin: UiRect::all(Val::Px(2.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: BUTTON_COLOR.into(),
+                    ..default()
+                })
+                .insert(button)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section("", style.clone()));
+                });
+        };
+    let row = |parent: &mut ChildBuilder, spawn_buttons: &dyn Fn(&mut ChildBuilder)| {
+        parent
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Undefined),
+                    ..default()
+                },
+                color: Color::NONE.into(),
+                ..default()
+            })
+            .with_children(|parent| spawn_buttons(parent));
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(PANEL_WIDTH), Val::Undefined),
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: UiRect::all(Val::Px(8.)),
+                ..default()
+            },
+            color: PANEL_COLOR.into(),
+            ..default()
+        })
+        // Keeps clicks on the panel from reaching the board
+        .insert(Interaction::default())
+        .insert(EditorPanel)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                "Left click places, right click removes",
+                TextStyle {
+                    font_size: FONT_SIZE * 0.8,
+                    ..text_style.clone()
+                },
+            ));
+            for color in [PieceColor::White, PieceColor::Black] {
+                row(parent, &|parent| {
+                    for piece_type in PIECE_TYPES {
+                        button(
+                            parent,
+                            EditorButton::Brush(color, piece_type),
+                            Val::Px(GLYPH_SIZE + 12.),
+                            &glyph_style,
+                        );
+                    }
+                });
+            }
+            let full = Val::Percent(100.);
+            row(parent, &|parent| {
+                button(parent, EditorButton::Clear, Val::Percent(50.), &text_style);
+                button(
+                    parent,
+                    EditorButton::StartPosition,
+                    Val::Percent(50.),
+                    &text_style,
+                );
+            });
+            button(parent, EditorButton::Turn, full, &text_style);
+            for i in 0..CASTLING.len() {
+                button(parent, EditorButton::Castling(i), full, &text_style);
+            }
+            button(parent, EditorButton::EnPassant, full, &text_style);
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: FONT_SIZE * 0.8,
+                        color: ERROR_COLOR,
+                        ..text_style.clone()
+                    },
+                ))
+                .insert(EditorError);
+            row(parent, &|parent| {
+                button(parent, EditorButton::Back, Val::Percent(50.), &text_style);
+                button(parent, EditorButton::Play, Val::Percent(50.), &text_style);
+            });
+        });
+}
+
+fn close_editor(mut commands: Commands, panels_query: Query<Entity, With<EditorPanel>>) {
+    for entity in panels_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Left click puts the brush's piece on a square, or takes it off if it's already there.
+// Right click empties the hovered square.
+fn edit_squares(
+    mut commands: Commands,
+    mut square_events: EventReader<SquareEvent>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    highlights: Res<Highlights>,
+    mut editor: ResMut<BoardEditor>,
+    pieces_query: Query<(Entity, &Piece)>,
+) {
+    let mut edits: Vec<((u8, u8), bool)> = square_events
+        .iter()
+        .filter_map(|event| match event {
+            SquareEvent::Clicked(pos) => Some((*pos, true)),
+            SquareEvent::Hovered(_) => None,
+        })
+        .collect();
+    if mouse_buttons.just_pressed(MouseButton::Right) {
+        if let Some(pos) = highlights.squares(HighlightLayer::Hover).next() {
+            edits.push((*pos, false));
+        }
+    }
+
+    let (color, piece_type) = editor.brush;
+    for (pos, place) in edits {
+        let current = pieces_query
+            .iter()
+            .find(|(_, piece)| (piece.x, piece.y) == pos);
+        if let Some((entity, _)) = current {
+            commands.entity(entity).despawn_recursive();
+        }
+        let same =
+            current.is_some_and(|(_, piece)| (piece.color, piece.piece_type) == editor.brush);
+        if place && !same {
+            spawn_piece(
+                &mut commands,
+                Piece {
+                    color,
+                    piece_type,
+                    x: pos.0,
+                    y: pos.1,
+                    has_moved: false,
+                    en_passant: false,
+                },
+            );
+        }
+        editor.error.clear();
+    }
+}
+
+fn press_buttons(
+    mut commands: Commands,
+    buttons_query: Query<(&EditorButton, &Interaction), Changed<Interaction>>,
+    pieces_query: Query<(Entity, &Piece)>,
+    mut editor: ResMut<BoardEditor>,
+    mut setup: ResMut<GameSetup>,
+    mut state: ResMut<State<AppState>>,
+) {
+    let pieces: Vec<Piece> = pieces_query.iter().map(|(_, piece)| *piece).collect();
+    for (button, interaction) in buttons_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match button {
+            EditorButton::Brush(color, piece_type) => editor.brush = (*color, *piece_type),
+            EditorButton::Clear | EditorButton::StartPosition => {
+                for (entity, _) in pieces_query.iter() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                let fen = match button {
+                    EditorButton::Clear => "8/8/8/8/8/8/8/8 w - - 0 1",
+                    _ => START_FEN,
+                };
+                spawn_position(&mut commands, &mut editor, fen);
+            }
+            EditorButton::Turn => {
+                editor.turn = editor.turn.opposite();
+                editor.en_passant = None;
+            }
+            EditorButton::Castling(i) => editor.castling[*i] = !editor.castling[*i],
+            // Goes through the pawns that can be taken, then none
+            EditorButton::EnPassant => {
+                let pawns = en_passant_pawns(&pieces, editor.turn);
+                editor.en_passant = match editor.en_passant {
+                    None => pawns.first().copied(),
+                    Some(current) => pawns
+                        .iter()
+                        .skip_while(|pawn| **pawn != current)
+                        .nth(1)
+                        .copied(),
+                };
+            }
+            // The position is kept for the next time, even if it can't be played
+            EditorButton::Back => {
+                setup.fen = editor.fen(&pieces);
+                change_state(state.replace(AppState::Setup));
+            }
+            EditorButton::Play => {
+                let fen = editor.fen(&pieces);
+                match parse_fen(&fen) {
+                    Ok(_) => {
+                        setup.fen = fen;
+                        change_state(state.replace(AppState::Playing));
+                    }
+                    Err(err) => editor.error = err,
+                }
+            }
+        }
+    }
+}
+
+fn button_label(button: EditorButton, editor: &BoardEditor, pieces: &[Piece]) -> String {
+    match button {
+        EditorButton::Brush(color, piece_type) => glyph(color, piece_type).to_string(),
+        EditorButton::Clear => "Clear board".to_string(),
+        EditorButton::StartPosition => "Start position".to_string(),
+        EditorButton::Turn => format!("{} to move", color_name(editor.turn)),
+        EditorButton::Castling(i) => {
+            let (c, color, rook_file) = CASTLING[i];
+            let side = if rook_file == 7 { "O-O" } else { "O-O-O" };
+            let state = if !editor.castling[i] {
+                "no"
+            } else if castling_rights(&editor.position(pieces)).contains(c) {
+                "yes"
+            } else {
+                "needs king and rook"
+            };
+            format!("{} {}: {}", color_name(color), side, state)
+        }
+        EditorButton::EnPassant => match editor.en_passant {
+            // Named by the square the pawn passed
+            Some((x, y)) => {
+                let passed = if x == 3 { (2, y) } else { (5, y) };
+                format!("En passant: {}", square_name(passed))
+            }
+            None => "En passant: none".to_string(),
+        },
+        EditorButton::Back => "Back".to_string(),
+        EditorButton::Play => "Play".to_string(),
+    }
+}
+
+// The en passant pawn goes away with edits around it
+fn drop_en_passant(mut editor: ResMut<BoardEditor>, pieces_query: Query<&Piece>) {
+    if let Some(pawn) = editor.en_passant {
+        let pieces: Vec<Piece> = pieces_query.iter().copied().collect();
+        if !en_passant_pawns(&pieces, editor.turn).contains(&pawn) {
+            editor.en_passant = None;
+        }
+    }
+}
+
+fn update_panel(
+    editor: Res<BoardEditor>,
+    pieces_query: Query<&Piece>,
+    mut buttons_query: Query<(&EditorButton, &Interaction, &mut UiColor, &Children)>,
+    mut texts_query: Query<&mut Text, Without<EditorError>>,
+    mut errors_query: Query<&mut Text, With<EditorError>>,
+) {
+    let pieces: Vec<Piece> = pieces_query.iter().copied().collect();
+    for (button, interaction, mut color, children) in buttons_query.iter_mut() {
+        let selected = *button == EditorButton::Brush(editor.brush.0, editor.brush.1);
+        let new_color = match interaction {
+            _ if selected => SELECTED_COLOR,
+            Interaction::Clicked | Interaction::Hovered => HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        };
+        if color.0 != new_color {
+            color.0 = new_color;
+        }
+        let label = button_label(*button, &editor, &pieces);
+        for child in children.iter() {
+            if let Ok(mut text) = texts_query.get_mut(*child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.clone();
+                }
+            }
+        }
+    }
+    for mut text in errors_query.iter_mut() {
+        if text.sections[0].value != editor.error {
+            text.sections[0].value = editor.error.clone();
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Sets of squares, one bit per square. Square indexes are rank * 8 + file, so a1 is 0,
+// h1 is 7 and h8 is 63, with ranks counted from White's side like the board's x axis.
+pub type Bitboard = u64;
+
+pub const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+
+pub fn rank_of(square: u8) -> u8 {
+    square / 8
+}
+
+pub fn file_of(square: u8) -> u8 {
+    square % 8
+}
+
+// Squares one step away in each direction, as (rank, file) steps.
+// The first four point up the board or to the h file, their blockers are found from the lowest bit.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (1, 0),
+    (0, 1),
+    (1, 1),
+    (1, -1),
+    (-1, 0),
+    (0, -1),
+    (-1, -1),
+    (-1, 1),
+];
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+
+const fn step_table(steps: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut i = 0;
+        while i < steps.len() {
+            let rank = square as i8 / 8 + steps[i].0;
+            let file = square as i8 % 8 + steps[i].1;
+            if rank >= 0 && rank < 8 && file >= 0 && file < 8 {
+                table[square] |= 1 << (rank * 8 + file);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn pawn_table(forward: i8) -> [Bitboard; 64] {
+    step_table(&[(forward, 1), (forward, -1)])
+}
+
+// Every square in a direction up to the edge of the board
+const fn ray_table() -> [[Bitboard; 64]; 8] {
+    let mut table = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let mut square = 0;
+        while square < 64 {
+            let mut rank = square as i8 / 8 + DIRECTIONS[direction].0;
+            let mut file = square as i8 % 8 + DIRECTIONS[direction].1;
+            while rank >= 0 && rank < 8 && file >= 0 && file < 8 {
+                table[direction][square] |= 1 << (rank * 8 + file);
+                rank += DIRECTIONS[direction].0;
+                file += DIRECTIONS[direction].1;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    table
+}
+
+const KNIGHT_ATTACKS: [Bitboard; 64] = step_table(&KNIGHT_STEPS);
+const KING_ATTACKS: [Bitboard; 64] = step_table(&DIRECTIONS);
+// Indexed by color, White's pawns capture up the board
+const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [pawn_table(1), pawn_table(-1)];
+const RAYS: [[Bitboard; 64]; 8] = ray_table();
+
+pub fn knight_attacks(square: u8) -> Bitboard {
+    KNIGHT_ATTACKS[square as usize]
+}
+
+pub fn king_attacks(square: u8) -> Bitboard {
+    KING_ATTACKS[square as usize]
+}
+
+pub fn pawn_attacks(color: usize, square: u8) -> Bitboard {
+    PAWN_ATTACKS[color][square as usize]
+}
+
+// Squares in the direction up to and including the first occupied one
+fn ray_attacks(direction: usize, square: u8, occupied: Bitboard) -> Bitboard {
+    let ray = RAYS[direction][square as usize];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    let blocker = if direction < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ RAYS[direction][blocker as usize]
+}
+
+pub fn rook_attacks(square: u8, occupied: Bitboard) -> Bitboard {
+    [0, 1, 4, 5].iter().fold(0, |attacks, &direction| {
+        attacks | ray_attacks(direction, square, occupied)
+    })
+}
+
+pub fn bishop_attacks(square: u8, occupied: Bitboard) -> Bitboard {
+    [2, 3, 6, 7].iter().fold(0, |attacks, &direction| {
+        attacks | ray_attacks(direction, square, occupied)
+    })
+}
+
+// Iterates over the squares of a bitboard from a1 upwards
+pub struct Squares(pub Bitboard);
+
+impl Iterator for Squares {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
+        }
+        let square = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(square)
+    }
+}
@@ -0,0 +1,401 @@
+use std::fs;
+use std::path::Path;
+
+use crate::piece::{PieceColor, PieceType};
+
+use super::bitboard::{
+    bishop_attacks, file_of, king_attacks, knight_attacks, rank_of, rook_attacks, Bitboard,
+    Squares, FILE_A,
+};
+use super::position::{Board, PIECE_TYPES};
+
+// Evaluation terms. Each one has a middlegame and an endgame weight, a position's score is
+// the sum of the weights times how often the term shows up for White less for Black,
+// blended by how much material is left. Since the score is a sum like that the weights can be
+// tuned from positions with known results.
+pub const MATERIAL: usize = 0;
+// A piece on a square, squares are seen from the piece's side
+pub const PIECE_SQUARE: usize = MATERIAL + 6;
+// Squares a knight, bishop, rook or queen attacks that aren't taken by its own pieces
+pub const MOBILITY: usize = PIECE_SQUARE + 6 * 64;
+pub const BISHOP_PAIR: usize = MOBILITY + 4;
+pub const DOUBLED_PAWN: usize = BISHOP_PAIR + 1;
+pub const ISOLATED_PAWN: usize = DOUBLED_PAWN + 1;
+// No enemy pawn in front or on the files next to it, by rank from the pawn's side
+pub const PASSED_PAWN: usize = ISOLATED_PAWN + 1;
+pub const ROOK_OPEN_FILE: usize = PASSED_PAWN + 8;
+pub const ROOK_HALF_OPEN_FILE: usize = ROOK_OPEN_FILE + 1;
+// Own pawns right in front of the king
+pub const KING_SHIELD: usize = ROOK_HALF_OPEN_FILE + 1;
+pub const TERM_COUNT: usize = KING_SHIELD + 1;
+
+// Where `tune` writes the weights and the engine reads them at startup
+pub const TUNED_PARAMS_FILE: &str = "assets/eval.txt";
+
+// How far into the endgame a position is, from the pieces left
+const PHASE_WEIGHTS: [i32; 6] = [0, 4, 2, 1, 1, 0];
+pub const MAX_PHASE: i32 = 24;
+
+// Middlegame and endgame weights of every term, in centipawns
+#[derive(Clone)]
+pub struct EvalParams {
+    pub weights: Vec<[i32; 2]>,
+}
+
+// Piece-square tables from White's side, with the 8th rank first so they read like the board
+#[rustfmt::skip]
+const PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
+    // King, in the middlegame
+    [
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -20, -30, -30, -40, -40, -30, -30, -20,
+        -10, -20, -20, -20, -20, -20, -20, -10,
+         20,  20,   0,   0,   0,   0,  20,  20,
+         20,  30,  10,   0,   0,  10,  30,  20,
+    ],
+    // Queen
+    [
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+         -5,   0,   5,   5,   5,   5,   0,  -5,
+          0,   0,   5,   5,   5,   5,   0,  -5,
+        -10,   5,   5,   5,   5,   5,   0, -10,
+        -10,   0,   5,   0,   0,   0,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    // Rook
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+          5,  10,  10,  10,  10,  10,  10,   5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+          0,   0,   0,   5,   5,   0,   0,   0,
+    ],
+    // Knight
+    [
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20,   0,   0,   0,   0, -20, -40,
+        -30,   0,  10,  15,  15,  10,   0, -30,
+        -30,   5,  15,  20,  20,  15,   5, -30,
+        -30,   0,  15,  20,  20,  15,   0, -30,
+        -30,   5,  10,  15,  15,  10,   5, -30,
+        -40, -20,   0,   5,   5,   0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    // Bishop
+    [
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   0,  10,  10,  10,  10,   0, -10,
+        -10,  10,  10,  10,  10,  10,  10, -10,
+        -10,   5,   0,   0,   0,   0,   5, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         10,  10,  20,  30,  30,  20,  10,  10,
+          5,   5,  10,  25,  25,  10,   5,   5,
+          0,   0,   0,  20,  20,   0,   0,   0,
+          5,  -5, -10,   0,   0, -10,  -5,   5,
+          5,  10,  10, -20, -20,  10,  10,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+];
+
+// The king heads for the middle once the queens are off
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        let mut weights = vec![[0; 2]; TERM_COUNT];
+        let material = [
+            [0, 0],
+            [1025, 936],
+            [477, 512],
+            [337, 281],
+            [365, 297],
+            [82, 94],
+        ];
+        weights[MATERIAL..MATERIAL + 6].copy_from_slice(&material);
+        for (piece, table) in PIECE_SQUARE_TABLES.iter().enumerate() {
+            for square in 0..64 {
+                // The tables start from the 8th rank
+                let mut weight = [table[square ^ 56]; 2];
+                if piece == PieceType::King as usize {
+                    weight[1] = KING_ENDGAME_TABLE[square ^ 56];
+                }
+                weights[PIECE_SQUARE + piece * 64 + square] = weight;
+            }
+        }
+        weights[MOBILITY..MOBILITY + 4].copy_from_slice(&[[4, 4], [5, 5], [2, 4], [1, 2]]);
+        weights[BISHOP_PAIR] = [30, 50];
+        weights[DOUBLED_PAWN] = [-10, -20];
+        weights[ISOLATED_PAWN] = [-10, -10];
+        weights[PASSED_PAWN..PASSED_PAWN + 8].copy_from_slice(&[
+            [0, 0],
+            [5, 10],
+            [5, 15],
+            [10, 25],
+            [20, 45],
+            [35, 70],
+            [60, 110],
+            [0, 0],
+        ]);
+        weights[ROOK_OPEN_FILE] = [25, 10];
+        weights[ROOK_HALF_OPEN_FILE] = [10, 5];
+        weights[KING_SHIELD] = [10, 0];
+        EvalParams { weights }
+    }
+}
+
+impl EvalParams {
+    // Reads weights written by `save`, one term per line as its index and two weights.
+    // Terms missing from the file keep their default weights.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut params = EvalParams::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let numbers: Vec<i32> = line
+                .split_whitespace()
+                .map(|number| number.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("Line {}: {}", i + 1, err))?;
+            match numbers[..] {
+                [term, middlegame, endgame] if (0..TERM_COUNT as i32).contains(&term) => {
+                    params.weights[term as usize] = [middlegame, endgame];
+                }
+                _ => return Err(format!("Line {}: expected a term and two weights", i + 1)),
+            }
+        }
+        Ok(params)
+    }
+
+    // The weights `tune` saved where the engine looks for them, or the defaults without a file
+    pub fn tuned() -> Result<Self, String> {
+        let path = Path::new(TUNED_PARAMS_FILE);
+        if path.exists() {
+            EvalParams::load(path).map_err(|err| format!("{}: {}", TUNED_PARAMS_FILE, err))
+        } else {
+            Ok(EvalParams::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::from("# term middlegame endgame\n");
+        for (term, [middlegame, endgame]) in self.weights.iter().enumerate() {
+            text.push_str(&format!("{} {} {}\n", term, middlegame, endgame));
+        }
+        fs::write(path, text).map_err(|err| err.to_string())
+    }
+}
+
+// Where the weights of a position's terms are added up. Tuning records the terms instead.
+pub trait Terms {
+    fn add(&mut self, term: usize, count: i32);
+}
+
+// Middlegame and endgame sums
+struct Score<'a> {
+    params: &'a EvalParams,
+    total: [i32; 2],
+}
+
+impl Terms for Score<'_> {
+    fn add(&mut self, term: usize, count: i32) {
+        let [middlegame, endgame] = self.params.weights[term];
+        self.total[0] += middlegame * count;
+        self.total[1] += endgame * count;
+    }
+}
+
+// Phase of the position, from MAX_PHASE with every piece on the board down to 0 without them
+pub fn phase(board: &Board) -> i32 {
+    let phase: i32 = PIECE_TYPES
+        .iter()
+        .map(|piece_type| {
+            let count = board.pieces_of(PieceColor::White, *piece_type).count_ones()
+                + board.pieces_of(PieceColor::Black, *piece_type).count_ones();
+            count as i32 * PHASE_WEIGHTS[*piece_type as usize]
+        })
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// Score of the position for the side to move, in centipawns
+pub fn evaluate(board: &Board, params: &EvalParams) -> i32 {
+    let mut score = Score {
+        params,
+        total: [0; 2],
+    };
+    add_terms(board, &mut score);
+    let phase = phase(board);
+    let white = (score.total[0] * phase + score.total[1] * (MAX_PHASE - phase)) / MAX_PHASE;
+    match board.turn {
+        PieceColor::White => white,
+        PieceColor::Black => -white,
+    }
+}
+
+fn files_next_to(file: u8) -> Bitboard {
+    let file = FILE_A << file;
+    (file << 1 & !FILE_A) | (file >> 1 & !(FILE_A << 7))
+}
+
+// Ranks in front of the given one from the color's side
+fn ranks_ahead(color: PieceColor, rank: u8) -> Bitboard {
+    match color {
+        PieceColor::White => (!0u64).checked_shl((rank as u32 + 1) * 8).unwrap_or(0),
+        PieceColor::Black => !(!0u64 << (rank * 8)),
+    }
+}
+
+// Squares in front of a pawn on its file and the files next to it
+fn passed_pawn_mask(color: PieceColor, square: u8) -> Bitboard {
+    let files = FILE_A << file_of(square) | files_next_to(file_of(square));
+    files & ranks_ahead(color, rank_of(square))
+}
+
+// Counts every term of the position, positive for White and negative for Black
+pub fn add_terms(board: &Board, terms: &mut impl Terms) {
+    let occupied = board.occupied();
+    for color in [PieceColor::White, PieceColor::Black] {
+        let sign = if color == PieceColor::White { 1 } else { -1 };
+        // Squares are flipped for Black so they're seen from its side
+        let flip = if color == PieceColor::White { 0 } else { 56 };
+        let own = board.color(color);
+        let pawns = board.pieces_of(color, PieceType::Pawn);
+        let enemy_pawns = board.pieces_of(color.opposite(), PieceType::Pawn);
+
+        for piece_type in PIECE_TYPES {
+            let pieces = board.pieces_of(color, piece_type);
+            terms.add(
+                MATERIAL + piece_type as usize,
+                sign * pieces.count_ones() as i32,
+            );
+            for square in Squares(pieces) {
+                let relative = (square ^ flip) as usize;
+                terms.add(PIECE_SQUARE + piece_type as usize * 64 + relative, sign);
+                let attacks = match piece_type {
+                    PieceType::Knight => knight_attacks(square),
+                    PieceType::Bishop => bishop_attacks(square, occupied),
+                    PieceType::Rook => rook_attacks(square, occupied),
+                    PieceType::Queen => {
+                        bishop_attacks(square, occupied) | rook_attacks(square, occupied)
+                    }
+                    _ => continue,
+                };
+                let mobility = match piece_type {
+                    PieceType::Knight => 0,
+                    PieceType::Bishop => 1,
+                    PieceType::Rook => 2,
+                    _ => 3,
+                };
+                terms.add(
+                    MOBILITY + mobility,
+                    sign * (attacks & !own).count_ones() as i32,
+                );
+            }
+        }
+
+        if board.pieces_of(color, PieceType::Bishop).count_ones() >= 2 {
+            terms.add(BISHOP_PAIR, sign);
+        }
+
+        for file in 0..8 {
+            let on_file = (pawns & FILE_A << file).count_ones() as i32;
+            if on_file > 1 {
+                terms.add(DOUBLED_PAWN, sign * (on_file - 1));
+            }
+            if on_file > 0 && pawns & files_next_to(file) == 0 {
+                terms.add(ISOLATED_PAWN, sign * on_file);
+            }
+        }
+        for square in Squares(pawns) {
+            if passed_pawn_mask(color, square) & enemy_pawns == 0 {
+                terms.add(PASSED_PAWN + rank_of(square ^ flip) as usize, sign);
+            }
+        }
+
+        for square in Squares(board.pieces_of(color, PieceType::Rook)) {
+            let file = FILE_A << file_of(square);
+            if file & pawns == 0 {
+                if file & enemy_pawns == 0 {
+                    terms.add(ROOK_OPEN_FILE, sign);
+                } else {
+                    terms.add(ROOK_HALF_OPEN_FILE, sign);
+                }
+            }
+        }
+
+        let king = board.king_square(color);
+        let shield = king_attacks(king) & ranks_ahead(color, rank_of(king)) & pawns;
+        terms.add(KING_SHIELD, sign * shield.count_ones() as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::START_FEN;
+
+    fn evaluate_fen(fen: &str) -> i32 {
+        evaluate(&Board::from_fen(fen).unwrap(), &EvalParams::default())
+    }
+
+    #[test]
+    fn start_position_is_even() {
+        assert_eq!(evaluate_fen(START_FEN), 0);
+    }
+
+    #[test]
+    fn same_for_both_sides() {
+        // The same position with the colors swapped and the board turned around
+        let white =
+            evaluate_fen("r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 0 1");
+        let black =
+            evaluate_fen("rnbqk2r/ppp2ppp/3p1n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQK2R b KQkq - 0 1");
+        assert_eq!(white, black);
+    }
+
+    #[test]
+    fn extra_material_counts() {
+        assert!(evaluate_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1") > 800);
+        assert!(evaluate_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1") < -800);
+    }
+
+    #[test]
+    fn reads_back_saved_weights() {
+        let mut params = EvalParams::default();
+        params.weights[BISHOP_PAIR] = [12, -3];
+        let path = std::env::temp_dir().join("chess-eval-params-test.txt");
+        params.save(&path).unwrap();
+        let loaded = EvalParams::load(&path).unwrap();
+        assert_eq!(loaded.weights, params.weights);
+        fs::remove_file(path).unwrap();
+    }
+}
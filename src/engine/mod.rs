@@ -0,0 +1,10 @@
+// The computer player: its own board made for searching, an evaluation and the search itself
+pub mod bitboard;
+pub mod eval;
+pub mod nnue;
+pub mod position;
+pub mod search;
+pub mod tt;
+
+pub use position::{Board, Move};
+pub use search::{Engine, Limits};
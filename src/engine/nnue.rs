@@ -0,0 +1,325 @@
+use std::fs;
+use std::path::Path;
+
+use crate::piece::PieceColor;
+
+use super::bitboard::Squares;
+use super::position::{color_index, Board, PieceChange, PIECE_TYPES};
+
+// Where the game and the UCI mode look for a network when none is given
+pub const DEFAULT_NETWORK_FILE: &str = "assets/chess.nnue";
+
+// One input per piece type and color on each square, seen from one side: its own pieces come
+// first and Black sees the board flipped
+pub const INPUTS: usize = 2 * 6 * 64;
+// Neurons of the hidden layer for each side
+pub const HIDDEN: usize = 256;
+// Quantization: hidden activations are clipped to 0..=QA and output weights scaled by QB
+const QA: i32 = 255;
+const QB: i32 = 64;
+// Centipawns per unit of output
+const SCALE: i32 = 400;
+// Kept clear of mate scores
+const MAX_EVAL: i32 = 20_000;
+
+// An efficiently updatable neural network: inputs -> hidden (x2, one per side) -> 1.
+// The hidden layer's sums are kept from move to move, so only the rows of the inputs a
+// move changes are added or subtracted, then the small output layer is run.
+pub struct Network {
+    // A row of HIDDEN weights per input
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    // The side to move's hidden layer, then the other side's
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+// The hidden layer's sums for both sides, indexed by color
+#[derive(Clone)]
+#[repr(align(32))]
+pub struct Accumulator([[i16; HIDDEN]; 2]);
+
+impl Network {
+    // The file holds the feature weights input by input, the feature biases and the output
+    // weights as little-endian i16s, then the output bias as a little-endian i32
+    pub fn load(path: &Path) -> Result<Network, String> {
+        let bytes = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Network::from_bytes(&bytes).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, String> {
+        let weights = INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN;
+        if bytes.len() != weights * 2 + 4 {
+            return Err(format!(
+                "Expected a network of {} bytes, found {}",
+                weights * 2 + 4,
+                bytes.len()
+            ));
+        }
+        let mut values = bytes[..weights * 2]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut take = |count: usize| values.by_ref().take(count).collect::<Vec<i16>>();
+        let feature_weights = take(INPUTS * HIDDEN);
+        let feature_bias = take(HIDDEN);
+        let output_weights = take(2 * HIDDEN);
+        let bias = &bytes[weights * 2..];
+        Ok(Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias: i32::from_le_bytes([bias[0], bias[1], bias[2], bias[3]]),
+        })
+    }
+
+    fn row(&self, input: usize) -> &[i16] {
+        &self.feature_weights[input * HIDDEN..(input + 1) * HIDDEN]
+    }
+
+    // Works the sums out from every piece on the board
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator([[0; HIDDEN]; 2]);
+        for values in accumulator.0.iter_mut() {
+            values.copy_from_slice(&self.feature_bias);
+        }
+        for color in [PieceColor::White, PieceColor::Black] {
+            for piece_type in PIECE_TYPES {
+                for square in Squares(board.pieces_of(color, piece_type)) {
+                    let change = PieceChange {
+                        color,
+                        piece_type,
+                        square,
+                        added: true,
+                    };
+                    self.apply(&mut accumulator, change);
+                }
+            }
+        }
+        accumulator
+    }
+
+    // Adds or takes away a piece for both sides
+    pub fn apply(&self, accumulator: &mut Accumulator, change: PieceChange) {
+        for (side, values) in accumulator.0.iter_mut().enumerate() {
+            let row = self.row(input(side, change));
+            if change.added {
+                simd::add(values, row);
+            } else {
+                simd::sub(values, row);
+            }
+        }
+    }
+
+    // Centipawns for the side to move
+    pub fn evaluate(&self, accumulator: &Accumulator, turn: PieceColor) -> i32 {
+        let us = color_index(turn);
+        let sum = simd::output(
+            &accumulator.0[us],
+            &accumulator.0[1 - us],
+            &self.output_weights,
+        );
+        let score = (sum as i64 + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        (score as i32).clamp(-MAX_EVAL, MAX_EVAL)
+    }
+}
+
+// The input a piece sets for a side
+fn input(side: usize, change: PieceChange) -> usize {
+    let color = color_index(change.color);
+    let (relative, square) = if side == 0 {
+        (color, change.square)
+    } else {
+        (1 - color, change.square ^ 56)
+    };
+    relative * 6 * 64 + change.piece_type as usize * 64 + square as usize
+}
+
+// The hot loops, with AVX2 when the processor has it. Sums wrap like the vector
+// instructions do, so both ways give the same results.
+mod simd {
+    use super::{HIDDEN, QA};
+
+    pub fn add(values: &mut [i16; HIDDEN], row: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // Safe as the processor supports AVX2
+            unsafe { return avx2::add(values, row) }
+        }
+        scalar::add(values, row)
+    }
+
+    pub fn sub(values: &mut [i16; HIDDEN], row: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            unsafe { return avx2::sub(values, row) }
+        }
+        scalar::sub(values, row)
+    }
+
+    // Clipped activations of both sides times the output weights
+    pub fn output(us: &[i16; HIDDEN], them: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            unsafe { return avx2::output(us, them, weights) }
+        }
+        scalar::output(us, them, weights)
+    }
+
+    pub mod scalar {
+        use super::{HIDDEN, QA};
+
+        pub fn add(values: &mut [i16; HIDDEN], row: &[i16]) {
+            for (value, weight) in values.iter_mut().zip(row) {
+                *value = value.wrapping_add(*weight);
+            }
+        }
+
+        pub fn sub(values: &mut [i16; HIDDEN], row: &[i16]) {
+            for (value, weight) in values.iter_mut().zip(row) {
+                *value = value.wrapping_sub(*weight);
+            }
+        }
+
+        pub fn output(us: &[i16; HIDDEN], them: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+            us.iter()
+                .chain(them.iter())
+                .zip(weights)
+                .fold(0i32, |sum, (value, weight)| {
+                    let activation = (*value as i32).clamp(0, QA);
+                    sum.wrapping_add(activation * *weight as i32)
+                })
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub mod avx2 {
+        use std::arch::x86_64::*;
+
+        use super::{HIDDEN, QA};
+
+        // 16 values to a register
+        const LANES: usize = 16;
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn add(values: &mut [i16; HIDDEN], row: &[i16]) {
+            assert!(row.len() >= HIDDEN);
+            for i in (0..HIDDEN).step_by(LANES) {
+                let value = values.as_mut_ptr().add(i) as *mut __m256i;
+                let weight = _mm256_loadu_si256(row.as_ptr().add(i) as *const __m256i);
+                _mm256_storeu_si256(value, _mm256_add_epi16(_mm256_loadu_si256(value), weight));
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn sub(values: &mut [i16; HIDDEN], row: &[i16]) {
+            assert!(row.len() >= HIDDEN);
+            for i in (0..HIDDEN).step_by(LANES) {
+                let value = values.as_mut_ptr().add(i) as *mut __m256i;
+                let weight = _mm256_loadu_si256(row.as_ptr().add(i) as *const __m256i);
+                _mm256_storeu_si256(value, _mm256_sub_epi16(_mm256_loadu_si256(value), weight));
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn output(us: &[i16; HIDDEN], them: &[i16; HIDDEN], weights: &[i16]) -> i32 {
+            assert!(weights.len() >= 2 * HIDDEN);
+            let zero = _mm256_setzero_si256();
+            let max = _mm256_set1_epi16(QA as i16);
+            let mut sum = zero;
+            for (half, values) in [us, them].into_iter().enumerate() {
+                for i in (0..HIDDEN).step_by(LANES) {
+                    let value = _mm256_loadu_si256(values.as_ptr().add(i) as *const __m256i);
+                    let activation = _mm256_min_epi16(_mm256_max_epi16(value, zero), max);
+                    let weight = weights.as_ptr().add(half * HIDDEN + i) as *const __m256i;
+                    let products = _mm256_madd_epi16(activation, _mm256_loadu_si256(weight));
+                    sum = _mm256_add_epi32(sum, products);
+                }
+            }
+            let halves = _mm_add_epi32(
+                _mm256_castsi256_si128(sum),
+                _mm256_extracti128_si256(sum, 1),
+            );
+            let pairs = _mm_add_epi32(halves, _mm_shuffle_epi32(halves, 0b01_00_11_10));
+            let total = _mm_add_epi32(pairs, _mm_shuffle_epi32(pairs, 0b10_11_00_01));
+            _mm_cvtsi128_si32(total)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::fen::START_FEN;
+
+    // Small weights from a fixed seed, the network is nonsense but the same every run
+    pub fn network() -> Network {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut bytes = Vec::new();
+        for _ in 0..INPUTS * HIDDEN + 3 * HIDDEN {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let weight = (state % 129) as i16 - 64;
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        bytes.extend_from_slice(&1000i32.to_le_bytes());
+        Network::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn rejects_files_of_the_wrong_size() {
+        assert!(Network::from_bytes(&[0; 100]).is_err());
+    }
+
+    #[test]
+    fn updates_match_a_refresh() {
+        let network = network();
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        // Castling, a capture, a double step, en passant and a capturing promotion
+        for text in ["e1g1", "h3g2", "a2a4", "b4a3", "e5f7", "g2f1q"] {
+            let mut accumulator = network.refresh(&board);
+            let mv = board.parse_move(text).unwrap();
+            board.make(mv);
+            for change in board.changes() {
+                network.apply(&mut accumulator, change);
+            }
+            let refreshed = network.refresh(&board);
+            assert_eq!(accumulator.0, refreshed.0, "{}", text);
+        }
+    }
+
+    #[test]
+    fn sees_both_sides_the_same() {
+        let network = network();
+        let white =
+            Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let black =
+            Board::from_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3");
+        let (white, black) = (white.unwrap(), black.unwrap());
+        assert_eq!(
+            network.evaluate(&network.refresh(&white), white.turn),
+            network.evaluate(&network.refresh(&black), black.turn)
+        );
+    }
+
+    #[test]
+    fn vector_and_scalar_code_agree() {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            let network = network();
+            let board = Board::from_fen(START_FEN).unwrap();
+            let accumulator = network.refresh(&board);
+            let [white, black] = &accumulator.0;
+            let weights = &network.output_weights;
+            let scalar = simd::scalar::output(white, black, weights);
+            let vector = unsafe { simd::avx2::output(white, black, weights) };
+            assert_eq!(scalar, vector);
+
+            let (mut scalar, mut vector) = (*white, *white);
+            simd::scalar::sub(&mut scalar, network.row(100));
+            unsafe { simd::avx2::sub(&mut vector, network.row(100)) };
+            assert_eq!(scalar, vector);
+        }
+    }
+}
@@ -0,0 +1,866 @@
+use crate::board::uci;
+use crate::fen::{castling_rights, read_fen, to_fen};
+use crate::piece::{Piece, PieceColor, PieceType};
+
+use super::bitboard::{
+    bishop_attacks, file_of, king_attacks, knight_attacks, pawn_attacks, rank_of, rook_attacks,
+    Bitboard, Squares,
+};
+
+pub const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::King,
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Pawn,
+];
+const PROMOTIONS: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+// Castling rights, in the order of fen's CASTLING
+const WHITE_KINGSIDE: u8 = 1;
+const WHITE_QUEENSIDE: u8 = 2;
+const BLACK_KINGSIDE: u8 = 4;
+const BLACK_QUEENSIDE: u8 = 8;
+
+// Rights kept when a piece moves from or to the square, moving a king or rook or taking a rook
+// loses them
+const fn castling_masks() -> [u8; 64] {
+    let mut masks = [15; 64];
+    masks[0] = 15 & !WHITE_QUEENSIDE;
+    masks[4] = 15 & !(WHITE_KINGSIDE | WHITE_QUEENSIDE);
+    masks[7] = 15 & !WHITE_KINGSIDE;
+    masks[56] = 15 & !BLACK_QUEENSIDE;
+    masks[60] = 15 & !(BLACK_KINGSIDE | BLACK_QUEENSIDE);
+    masks[63] = 15 & !BLACK_KINGSIDE;
+    masks
+}
+
+const CASTLING_MASKS: [u8; 64] = castling_masks();
+
+// Random numbers the position hash is made of
+struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+const fn splitmix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn zobrist_keys() -> ZobristKeys {
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        castling: [0; 16],
+        en_passant: [0; 8],
+        black_to_move: 0,
+    };
+    let mut state = 0x2545_f491_4f6c_dd1d;
+    let mut key;
+    let mut i = 0;
+    while i < 2 * 6 * 64 {
+        (state, key) = splitmix(state);
+        keys.pieces[i / 384][i / 64 % 6][i % 64] = key;
+        i += 1;
+    }
+    i = 0;
+    while i < 16 {
+        (state, key) = splitmix(state);
+        keys.castling[i] = key;
+        i += 1;
+    }
+    i = 0;
+    while i < 8 {
+        (state, key) = splitmix(state);
+        keys.en_passant[i] = key;
+        i += 1;
+    }
+    (_, keys.black_to_move) = splitmix(state);
+    keys
+}
+
+const KEYS: ZobristKeys = zobrist_keys();
+
+pub fn color_index(color: PieceColor) -> usize {
+    color as usize
+}
+
+// A move between two squares. Castling is the king's move, en passant the pawn's.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub from: u8,
+    pub to: u8,
+    pub promotion: Option<PieceType>,
+}
+
+impl Move {
+    // Stands for no move, like the null move or an empty hash table entry
+    pub const NONE: Move = Move {
+        from: 0,
+        to: 0,
+        promotion: None,
+    };
+
+    pub fn new(from: (u8, u8), to: (u8, u8), promotion: Option<PieceType>) -> Move {
+        Move {
+            from: from.0 * 8 + from.1,
+            to: to.0 * 8 + to.1,
+            promotion,
+        }
+    }
+
+    // The squares as (rank, file), like the board's (x, y)
+    pub fn squares(self) -> ((u8, u8), (u8, u8)) {
+        (
+            (rank_of(self.from), file_of(self.from)),
+            (rank_of(self.to), file_of(self.to)),
+        )
+    }
+
+    pub fn uci(self) -> String {
+        let (from, to) = self.squares();
+        uci(from, to, self.promotion)
+    }
+
+    // Fits the move in 16 bits for the transposition table
+    pub fn pack(self) -> u16 {
+        let promotion = match self.promotion {
+            Some(piece_type) => piece_type as u16 + 1,
+            None => 0,
+        };
+        self.from as u16 | (self.to as u16) << 6 | promotion << 12
+    }
+
+    pub fn unpack(packed: u16) -> Move {
+        Move {
+            from: (packed & 63) as u8,
+            to: (packed >> 6 & 63) as u8,
+            promotion: match packed >> 12 {
+                0 => None,
+                n => Some(PIECE_TYPES[n as usize - 1]),
+            },
+        }
+    }
+}
+
+// Moves of a position, kept on the stack while searching
+pub struct MoveList {
+    moves: [Move; 256],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [Move::NONE; 256],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn as_slice(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+// Squares a piece on a square attacks, given the occupied squares
+type Attacks = fn(u8, Bitboard) -> Bitboard;
+
+// A piece put on or taken off a square
+#[derive(Clone, Copy)]
+pub struct PieceChange {
+    pub color: PieceColor,
+    pub piece_type: PieceType,
+    pub square: u8,
+    pub added: bool,
+}
+
+// What unmake needs to take a move back
+#[derive(Clone, Copy)]
+struct Undo {
+    mv: Move,
+    captured: Option<PieceType>,
+    castling: u8,
+    en_passant: Option<u8>,
+    halfmove: u16,
+    hash: u64,
+}
+
+// The engine's board. Unlike the game's pieces it's made for searching: moves are made and
+// taken back in place, and the position keeps a hash of itself up to date.
+#[derive(Clone)]
+pub struct Board {
+    by_piece: [[Bitboard; 6]; 2],
+    by_color: [Bitboard; 2],
+    squares: [Option<(PieceColor, PieceType)>; 64],
+    pub turn: PieceColor,
+    castling: u8,
+    // Square a pawn can be taken on en passant, only set when a pawn could take it
+    en_passant: Option<u8>,
+    // Moves since the last capture or pawn move
+    halfmove: u16,
+    hash: u64,
+    undo: Vec<Undo>,
+    // Hashes of the positions before each move, to find repetitions
+    keys: Vec<u64>,
+    // What the last move made changed, at most four pieces when castling
+    changes: [Option<PieceChange>; 4],
+}
+
+impl Board {
+    fn empty(turn: PieceColor) -> Self {
+        Board {
+            by_piece: [[0; 6]; 2],
+            by_color: [0; 2],
+            squares: [None; 64],
+            turn,
+            castling: 0,
+            en_passant: None,
+            halfmove: 0,
+            hash: match turn {
+                PieceColor::White => 0,
+                PieceColor::Black => KEYS.black_to_move,
+            },
+            undo: Vec::new(),
+            keys: Vec::new(),
+            changes: [None; 4],
+        }
+    }
+
+    // Castling rights come from the kings and rooks that haven't moved, like in a FEN
+    pub fn from_pieces(pieces: &[Piece], turn: PieceColor) -> Self {
+        let mut board = Board::empty(turn);
+        for piece in pieces {
+            board.put(piece.color, piece.piece_type, piece.x * 8 + piece.y);
+        }
+        let rights = castling_rights(pieces);
+        for (c, right) in "KQkq".chars().zip([1, 2, 4, 8]) {
+            if rights.contains(c) {
+                board.castling |= right;
+            }
+        }
+        board.hash ^= KEYS.castling[board.castling as usize];
+        if let Some(pawn) = pieces.iter().find(|piece| piece.en_passant) {
+            let behind = match pawn.color {
+                PieceColor::White => pawn.x - 1,
+                PieceColor::Black => pawn.x + 1,
+            };
+            board.set_en_passant(behind * 8 + pawn.y);
+        }
+        board
+    }
+
+    // The halfmove clock is read too, unlike for positions played from on the board
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let position = read_fen(fen)?;
+        let mut board = Board::from_pieces(&position.pieces, position.turn);
+        board.halfmove = fen
+            .split_whitespace()
+            .nth(4)
+            .and_then(|halfmove| halfmove.parse().ok())
+            .unwrap_or(0);
+        Ok(board)
+    }
+
+    // The pieces as the game keeps them
+    pub fn pieces(&self) -> Vec<Piece> {
+        let unmoved = |square: u8| {
+            let rights = match square {
+                0 => WHITE_QUEENSIDE,
+                4 => WHITE_KINGSIDE | WHITE_QUEENSIDE,
+                7 => WHITE_KINGSIDE,
+                56 => BLACK_QUEENSIDE,
+                60 => BLACK_KINGSIDE | BLACK_QUEENSIDE,
+                63 => BLACK_KINGSIDE,
+                _ => 0,
+            };
+            self.castling & rights != 0
+        };
+        (0..64)
+            .filter_map(|square| {
+                let (color, piece_type) = self.squares[square as usize]?;
+                let passed = self.en_passant.map(|en_passant| match color {
+                    PieceColor::White => en_passant + 8,
+                    PieceColor::Black => en_passant - 8,
+                });
+                Some(Piece {
+                    color,
+                    piece_type,
+                    x: rank_of(square),
+                    y: file_of(square),
+                    has_moved: !unmoved(square),
+                    en_passant: piece_type == PieceType::Pawn && passed == Some(square),
+                })
+            })
+            .collect()
+    }
+
+    pub fn fen(&self) -> String {
+        to_fen(&self.pieces(), self.turn)
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn piece_at(&self, square: u8) -> Option<(PieceColor, PieceType)> {
+        self.squares[square as usize]
+    }
+
+    pub fn pieces_of(&self, color: PieceColor, piece_type: PieceType) -> Bitboard {
+        self.by_piece[color_index(color)][piece_type as usize]
+    }
+
+    pub fn color(&self, color: PieceColor) -> Bitboard {
+        self.by_color[color_index(color)]
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.by_color[0] | self.by_color[1]
+    }
+
+    // Pieces put on or taken off squares by the last move made, for evaluations that are kept
+    // up to date move by move
+    pub fn changes(&self) -> impl Iterator<Item = PieceChange> + '_ {
+        self.changes.iter().map_while(|change| *change)
+    }
+
+    fn record(&mut self, color: PieceColor, piece_type: PieceType, square: u8, added: bool) {
+        if let Some(slot) = self.changes.iter_mut().find(|change| change.is_none()) {
+            *slot = Some(PieceChange {
+                color,
+                piece_type,
+                square,
+                added,
+            });
+        }
+    }
+
+    fn put(&mut self, color: PieceColor, piece_type: PieceType, square: u8) {
+        self.record(color, piece_type, square, true);
+        let bit = 1 << square;
+        self.by_piece[color_index(color)][piece_type as usize] |= bit;
+        self.by_color[color_index(color)] |= bit;
+        self.squares[square as usize] = Some((color, piece_type));
+        self.hash ^= KEYS.pieces[color_index(color)][piece_type as usize][square as usize];
+    }
+
+    fn remove(&mut self, color: PieceColor, piece_type: PieceType, square: u8) {
+        self.record(color, piece_type, square, false);
+        let bit = 1 << square;
+        self.by_piece[color_index(color)][piece_type as usize] &= !bit;
+        self.by_color[color_index(color)] &= !bit;
+        self.squares[square as usize] = None;
+        self.hash ^= KEYS.pieces[color_index(color)][piece_type as usize][square as usize];
+    }
+
+    // Only kept when a pawn of the side to move could take there, so positions that only
+    // differ by a capture nobody can make hash the same
+    fn set_en_passant(&mut self, square: u8) {
+        let them = color_index(self.turn.opposite());
+        if pawn_attacks(them, square) & self.pieces_of(self.turn, PieceType::Pawn) != 0 {
+            self.en_passant = Some(square);
+            self.hash ^= KEYS.en_passant[file_of(square) as usize];
+        }
+    }
+
+    pub fn king_square(&self, color: PieceColor) -> u8 {
+        self.pieces_of(color, PieceType::King).trailing_zeros() as u8
+    }
+
+    // Whether a piece of the given color attacks the square
+    pub fn attacked(&self, square: u8, by: PieceColor) -> bool {
+        let occupied = self.occupied();
+        let queens = self.pieces_of(by, PieceType::Queen);
+        pawn_attacks(color_index(by.opposite()), square) & self.pieces_of(by, PieceType::Pawn) != 0
+            || knight_attacks(square) & self.pieces_of(by, PieceType::Knight) != 0
+            || king_attacks(square) & self.pieces_of(by, PieceType::King) != 0
+            || bishop_attacks(square, occupied) & (self.pieces_of(by, PieceType::Bishop) | queens)
+                != 0
+            || rook_attacks(square, occupied) & (self.pieces_of(by, PieceType::Rook) | queens) != 0
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.attacked(self.king_square(self.turn), self.turn.opposite())
+    }
+
+    // After making a move, whether it left the king of the side that played it in check
+    pub fn left_in_check(&self) -> bool {
+        self.attacked(self.king_square(self.turn.opposite()), self.turn)
+    }
+
+    // The piece type a move takes, a pawn for en passant
+    pub fn captured(&self, mv: Move) -> Option<PieceType> {
+        match self.squares[mv.to as usize] {
+            Some((_, piece_type)) => Some(piece_type),
+            None if Some(mv.to) == self.en_passant
+                && self.squares[mv.from as usize].map(|(_, piece_type)| piece_type)
+                    == Some(PieceType::Pawn) =>
+            {
+                Some(PieceType::Pawn)
+            }
+            None => None,
+        }
+    }
+
+    // Captures and promotions change the material, the search looks at them to the end
+    pub fn is_tactical(&self, mv: Move) -> bool {
+        mv.promotion.is_some() || self.captured(mv).is_some()
+    }
+
+    // Whether the side has anything but pawns, without it passing a move can lose
+    pub fn has_pieces(&self, color: PieceColor) -> bool {
+        self.color(color)
+            & !(self.pieces_of(color, PieceType::Pawn) | self.pieces_of(color, PieceType::King))
+            != 0
+    }
+
+    // Pseudo-legal moves, they can leave the king in check. Without quiet moves only captures
+    // and queen promotions are generated.
+    pub fn generate(&self, moves: &mut MoveList, quiets: bool) {
+        let us = self.turn;
+        let own = self.color(us);
+        let enemy = self.color(us.opposite());
+        let occupied = own | enemy;
+        let targets = if quiets { !own } else { enemy };
+
+        let (forward, start_rank, last_rank): (i8, u8, u8) = match us {
+            PieceColor::White => (8, 1, 7),
+            PieceColor::Black => (-8, 6, 0),
+        };
+        let push_pawn = |moves: &mut MoveList, from: u8, to: u8| {
+            if rank_of(to) != last_rank {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: None,
+                });
+                return;
+            }
+            let promotions = if quiets {
+                &PROMOTIONS[..]
+            } else {
+                &PROMOTIONS[..1]
+            };
+            for promotion in promotions {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: Some(*promotion),
+                });
+            }
+        };
+        for from in Squares(self.pieces_of(us, PieceType::Pawn)) {
+            let mut captures = pawn_attacks(color_index(us), from) & enemy;
+            if let Some(en_passant) = self.en_passant {
+                captures |= pawn_attacks(color_index(us), from) & 1 << en_passant;
+            }
+            for to in Squares(captures) {
+                push_pawn(moves, from, to);
+            }
+            let one = (from as i8 + forward) as u8;
+            if occupied & 1 << one != 0 || !quiets && rank_of(one) != last_rank {
+                continue;
+            }
+            push_pawn(moves, from, one);
+            let two = (one as i8 + forward) as u8;
+            if quiets && rank_of(from) == start_rank && occupied & 1 << two == 0 {
+                push_pawn(moves, from, two);
+            }
+        }
+
+        let queens = self.pieces_of(us, PieceType::Queen);
+        let pieces: [(Bitboard, Attacks); 4] = [
+            (self.pieces_of(us, PieceType::Knight), |square, _| {
+                knight_attacks(square)
+            }),
+            (
+                self.pieces_of(us, PieceType::Bishop) | queens,
+                bishop_attacks,
+            ),
+            (self.pieces_of(us, PieceType::Rook) | queens, rook_attacks),
+            (self.pieces_of(us, PieceType::King), |square, _| {
+                king_attacks(square)
+            }),
+        ];
+        for (from_squares, attacks) in pieces {
+            for from in Squares(from_squares) {
+                for to in Squares(attacks(from, occupied) & targets) {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: None,
+                    });
+                }
+            }
+        }
+
+        if !quiets {
+            return;
+        }
+        // The king can't castle out of, through or into check
+        let (kingside, queenside, king) = match us {
+            PieceColor::White => (WHITE_KINGSIDE, WHITE_QUEENSIDE, 4),
+            PieceColor::Black => (BLACK_KINGSIDE, BLACK_QUEENSIDE, 60),
+        };
+        let them = us.opposite();
+        if self.castling & (kingside | queenside) == 0 || self.attacked(king, them) {
+            return;
+        }
+        if self.castling & kingside != 0
+            && occupied & (0b11 << (king + 1)) == 0
+            && !self.attacked(king + 1, them)
+            && !self.attacked(king + 2, them)
+        {
+            moves.push(Move {
+                from: king,
+                to: king + 2,
+                promotion: None,
+            });
+        }
+        if self.castling & queenside != 0
+            && occupied & (0b111 << (king - 3)) == 0
+            && !self.attacked(king - 1, them)
+            && !self.attacked(king - 2, them)
+        {
+            moves.push(Move {
+                from: king,
+                to: king - 2,
+                promotion: None,
+            });
+        }
+    }
+
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let mut moves = MoveList::new();
+        self.generate(&mut moves, true);
+        moves
+            .as_slice()
+            .iter()
+            .copied()
+            .filter(|mv| {
+                self.make(*mv);
+                let legal = !self.left_in_check();
+                self.unmake();
+                legal
+            })
+            .collect()
+    }
+
+    // Finds the legal move written in UCI notation
+    pub fn parse_move(&mut self, text: &str) -> Option<Move> {
+        self.legal_moves().into_iter().find(|mv| mv.uci() == text)
+    }
+
+    // Plays a pseudo-legal move
+    pub fn make(&mut self, mv: Move) {
+        let (color, piece_type) = self.squares[mv.from as usize].expect("a piece to move");
+        let them = color.opposite();
+        let captured = self.captured(mv);
+        self.undo.push(Undo {
+            mv,
+            captured,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove: self.halfmove,
+            hash: self.hash,
+        });
+        self.keys.push(self.hash);
+        self.changes = [None; 4];
+
+        if let Some(captured) = captured {
+            let square = if self.squares[mv.to as usize].is_none() {
+                // En passant, the pawn is next to the square moved to
+                (mv.to as i8 + if color == PieceColor::White { -8 } else { 8 }) as u8
+            } else {
+                mv.to
+            };
+            self.remove(them, captured, square);
+        }
+        self.remove(color, piece_type, mv.from);
+        self.put(color, mv.promotion.unwrap_or(piece_type), mv.to);
+        if piece_type == PieceType::King && (mv.to as i8 - mv.from as i8).abs() == 2 {
+            let (rook_from, rook_to) = castling_rook(mv);
+            self.remove(color, PieceType::Rook, rook_from);
+            self.put(color, PieceType::Rook, rook_to);
+        }
+
+        self.hash ^= KEYS.castling[self.castling as usize];
+        self.castling &= CASTLING_MASKS[mv.from as usize] & CASTLING_MASKS[mv.to as usize];
+        self.hash ^= KEYS.castling[self.castling as usize];
+        if let Some(en_passant) = self.en_passant.take() {
+            self.hash ^= KEYS.en_passant[file_of(en_passant) as usize];
+        }
+        self.halfmove = if piece_type == PieceType::Pawn || captured.is_some() {
+            0
+        } else {
+            self.halfmove + 1
+        };
+        self.turn = them;
+        self.hash ^= KEYS.black_to_move;
+        if piece_type == PieceType::Pawn && (mv.to as i8 - mv.from as i8).abs() == 16 {
+            self.set_en_passant((mv.from + mv.to) / 2);
+        }
+    }
+
+    pub fn unmake(&mut self) {
+        let undo = self.undo.pop().expect("a move to take back");
+        self.keys.pop();
+        self.turn = self.turn.opposite();
+        let mv = undo.mv;
+        if mv != Move::NONE {
+            let color = self.turn;
+            let (_, piece_type) = self.squares[mv.to as usize].expect("the piece that moved");
+            self.remove(color, piece_type, mv.to);
+            let moved = if mv.promotion.is_some() {
+                PieceType::Pawn
+            } else {
+                piece_type
+            };
+            self.put(color, moved, mv.from);
+            if let Some(captured) = undo.captured {
+                let square = if moved == PieceType::Pawn && Some(mv.to) == undo.en_passant {
+                    (mv.to as i8 + if color == PieceColor::White { -8 } else { 8 }) as u8
+                } else {
+                    mv.to
+                };
+                self.put(color.opposite(), captured, square);
+            }
+            if moved == PieceType::King && (mv.to as i8 - mv.from as i8).abs() == 2 {
+                let (rook_from, rook_to) = castling_rook(mv);
+                self.remove(color, PieceType::Rook, rook_to);
+                self.put(color, PieceType::Rook, rook_from);
+            }
+        }
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove = undo.halfmove;
+        self.hash = undo.hash;
+    }
+
+    // Passes the turn, for null move pruning
+    pub fn make_null(&mut self) {
+        self.undo.push(Undo {
+            mv: Move::NONE,
+            captured: None,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove: self.halfmove,
+            hash: self.hash,
+        });
+        self.keys.push(self.hash);
+        if let Some(en_passant) = self.en_passant.take() {
+            self.hash ^= KEYS.en_passant[file_of(en_passant) as usize];
+        }
+        self.halfmove += 1;
+        self.turn = self.turn.opposite();
+        self.hash ^= KEYS.black_to_move;
+    }
+
+    // Draws the search can see without looking further: the fifty move rule, a position
+    // repeated since the last capture or pawn move, and bare kings or a lone minor piece
+    pub fn is_draw(&self) -> bool {
+        if self.halfmove >= 100 {
+            return true;
+        }
+        let repeated = self
+            .keys
+            .iter()
+            .rev()
+            .take(self.halfmove as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|key| *key == self.hash);
+        let heavy = [PieceType::Pawn, PieceType::Rook, PieceType::Queen]
+            .iter()
+            .any(|piece_type| {
+                self.pieces_of(PieceColor::White, *piece_type)
+                    | self.pieces_of(PieceColor::Black, *piece_type)
+                    != 0
+            });
+        repeated || !heavy && self.occupied().count_ones() <= 3
+    }
+}
+
+// Where the rook starts and ends when the king castles
+fn castling_rook(mv: Move) -> (u8, u8) {
+    if mv.to > mv.from {
+        (mv.from + 3, mv.from + 1)
+    } else {
+        (mv.from - 4, mv.from - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::START_FEN;
+
+    fn perft(board: &mut Board, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let mut moves = MoveList::new();
+        board.generate(&mut moves, true);
+        let mut nodes = 0;
+        for mv in moves.as_mut_slice() {
+            board.make(*mv);
+            if !board.left_in_check() {
+                nodes += perft(board, depth - 1);
+            }
+            board.unmake();
+        }
+        nodes
+    }
+
+    fn play(board: &mut Board, text: &str) {
+        let mut moves = MoveList::new();
+        board.generate(&mut moves, true);
+        let mv = *moves
+            .as_mut_slice()
+            .iter()
+            .find(|mv| mv.uci() == text)
+            .unwrap();
+        board.make(mv);
+    }
+
+    #[test]
+    fn counts_the_moves_of_known_positions() {
+        for (fen, depth, nodes) in [
+            (START_FEN, 4, 197_281),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                3,
+                97_862,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43_238),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                3,
+                9_467,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                3,
+                62_379,
+            ),
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(perft(&mut board, depth), nodes, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn hash_follows_the_moves() {
+        // A double step nobody can take, castling, then a double step that can be taken
+        // en passant and the capture
+        let mut board = Board::from_fen(START_FEN).unwrap();
+        for (text, fen) in [
+            (
+                "e2e4",
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            ),
+            (
+                "g8f6",
+                "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+            ),
+            (
+                "e4e5",
+                "rnbqkb1r/pppppppp/5n2/4P3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            ),
+            (
+                "d7d5",
+                "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 1",
+            ),
+            (
+                "e5d6",
+                "rnbqkb1r/ppp1pppp/3P1n2/8/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            ),
+            (
+                "e7e6",
+                "rnbqkb1r/ppp2ppp/3Ppn2/8/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1",
+            ),
+            (
+                "g1f3",
+                "rnbqkb1r/ppp2ppp/3Ppn2/8/8/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1",
+            ),
+            (
+                "f8d6",
+                "rnbqk2r/ppp2ppp/3bpn2/8/8/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1",
+            ),
+            (
+                "f1e2",
+                "rnbqk2r/ppp2ppp/3bpn2/8/8/5N2/PPPPBPPP/RNBQK2R b KQkq - 0 1",
+            ),
+            (
+                "e8g8",
+                "rnbq1rk1/ppp2ppp/3bpn2/8/8/5N2/PPPPBPPP/RNBQK2R w KQ - 0 1",
+            ),
+        ] {
+            play(&mut board, text);
+            assert_eq!(
+                board.hash(),
+                Board::from_fen(fen).unwrap().hash(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn taking_moves_back_restores_the_position() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        let before = board.clone();
+        let mut moves = MoveList::new();
+        board.generate(&mut moves, true);
+        for mv in moves.as_mut_slice() {
+            board.make(*mv);
+            board.unmake();
+            assert!(board.squares == before.squares, "{}", mv.uci());
+            assert_eq!(board.by_piece, before.by_piece);
+            assert_eq!(board.by_color, before.by_color);
+            assert_eq!(board.hash(), before.hash());
+            assert!(board.castling == before.castling && board.en_passant == before.en_passant);
+        }
+    }
+
+    #[test]
+    fn finds_repetitions() {
+        let mut board = Board::from_fen(START_FEN).unwrap();
+        for text in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            assert!(!board.is_draw(), "{}", text);
+            play(&mut board, text);
+        }
+        assert!(board.is_draw());
+    }
+
+    #[test]
+    fn packs_moves() {
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut moves = MoveList::new();
+        board.generate(&mut moves, true);
+        for mv in moves.as_mut_slice() {
+            assert!(Move::unpack(mv.pack()) == *mv);
+        }
+    }
+}
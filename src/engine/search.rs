@@ -0,0 +1,805 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::piece::PieceType;
+
+use super::eval::{evaluate, EvalParams};
+use super::nnue::{Accumulator, Network};
+use super::position::{color_index, Board, Move, MoveList};
+use super::tt::{Bound, Entry, TranspositionTable};
+
+pub const INFINITY: i32 = 32_001;
+// Mate in n plies scores MATE - n
+pub const MATE: i32 = 32_000;
+pub const MAX_PLY: usize = 128;
+// Scores this close to MATE are mates rather than evaluations
+const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_THREADS: usize = 256;
+
+// When a search stops, without limits it goes as deep as it can
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub depth: Option<i32>,
+    pub time: Option<Duration>,
+    pub nodes: Option<u64>,
+    // Set from another thread to stop the search at once
+    pub stop: Option<Arc<AtomicBool>>,
+}
+
+impl Limits {
+    // A share of the time left on the clock, plus most of the increment
+    pub fn for_clock(remaining: Duration, increment: Duration) -> Self {
+        let time = remaining / 30 + increment * 3 / 4;
+        Limits {
+            time: Some(time.min(remaining / 2)),
+            ..Default::default()
+        }
+    }
+}
+
+// Where a search got to, reported after every depth it finishes
+#[derive(Clone, Default)]
+pub struct SearchInfo {
+    pub depth: i32,
+    // Deepest ply reached, with checks extended and captures played out
+    pub seldepth: i32,
+    // Centipawns for the side to move, or a mate score
+    pub score: i32,
+    pub nodes: u64,
+    // Positions found in the transposition table
+    pub tt_hits: u64,
+    pub elapsed: Duration,
+    // Best line found, starting with the move to play
+    pub pv: Vec<Move>,
+}
+
+impl SearchInfo {
+    pub fn best_move(&self) -> Option<Move> {
+        self.pv.first().copied()
+    }
+
+    // Moves to mate, negative when the side to move gets mated
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score >= MATE_BOUND {
+            Some((MATE - self.score + 1) / 2)
+        } else if self.score <= -MATE_BOUND {
+            Some(-(MATE + self.score) / 2)
+        } else {
+            None
+        }
+    }
+}
+
+// Plays chess. The transposition table is kept from one search to the next.
+#[derive(Clone)]
+pub struct Engine {
+    tt: Arc<TranspositionTable>,
+    params: Arc<EvalParams>,
+    // Evaluates positions instead of the parameters when set
+    network: Option<Arc<Network>>,
+    // Searches running at once, the helpers only fill the shared table for the main one
+    threads: usize,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl Engine {
+    pub fn new(hash_mb: usize) -> Self {
+        Engine {
+            tt: Arc::new(TranspositionTable::new(hash_mb)),
+            params: Arc::new(EvalParams::default()),
+            network: None,
+            threads: 1,
+        }
+    }
+
+    pub fn set_hash_size(&mut self, hash_mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(hash_mb));
+    }
+
+    // Weights of the classical evaluation
+    pub fn set_params(&mut self, params: EvalParams) {
+        self.params = Arc::new(params);
+    }
+
+    // None goes back to the classical evaluation
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.clamp(1, MAX_THREADS);
+    }
+
+    // How full the transposition table is, in thousandths
+    pub fn hashfull(&self) -> usize {
+        self.tt.hashfull()
+    }
+
+    // Forgets what was learned about the positions of the last game
+    pub fn new_game(&self) {
+        self.tt.clear();
+    }
+
+    // Searches deeper and deeper until a limit is reached, reporting each depth.
+    // The best move is None only if the side to move has none or the search was stopped
+    // before finishing a depth.
+    pub fn search(
+        &self,
+        board: &Board,
+        limits: &Limits,
+        report: impl FnMut(&SearchInfo),
+    ) -> SearchInfo {
+        self.tt.new_search();
+        // Lazy SMP: helpers search the same position without limits until the main search is
+        // done. They share the table with it, so it finds their results and cuts off earlier.
+        let stop_helpers = Arc::new(AtomicBool::new(false));
+        let helper_nodes = AtomicU64::new(0);
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as i32 - 1);
+        let mut info = thread::scope(|scope| {
+            for id in 1..self.threads {
+                let limits = Limits {
+                    depth: limits.depth,
+                    stop: Some(stop_helpers.clone()),
+                    ..Default::default()
+                };
+                let helper_nodes = &helper_nodes;
+                scope.spawn(move || {
+                    let mut helper = Searcher::new(board.clone(), self, &limits);
+                    helper.helper_nodes = Some(helper_nodes);
+                    // Half of them start a depth ahead, so the threads don't all search the
+                    // same depth at once
+                    let mut score = 0;
+                    for depth in (1 + id as i32 % 2)..=max_depth {
+                        score = helper.aspiration(depth, score);
+                        if helper.stopped {
+                            break;
+                        }
+                    }
+                    helper_nodes.fetch_add(helper.nodes % 1024, Ordering::Relaxed);
+                });
+            }
+            let info = self.main_search(board, limits, &helper_nodes, report);
+            stop_helpers.store(true, Ordering::Relaxed);
+            info
+        });
+        // The helpers have finished and added up all their nodes
+        info.nodes += helper_nodes.load(Ordering::Relaxed);
+        info
+    }
+
+    fn main_search(
+        &self,
+        board: &Board,
+        limits: &Limits,
+        helper_nodes: &AtomicU64,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> SearchInfo {
+        let mut searcher = Searcher::new(board.clone(), self, limits);
+        let start = searcher.start;
+        let mut info = SearchInfo::default();
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as i32 - 1);
+        let nodes = |searcher: &Searcher| searcher.nodes + helper_nodes.load(Ordering::Relaxed);
+
+        for depth in 1..=max_depth {
+            let score = searcher.aspiration(depth, info.score);
+            if searcher.stopped {
+                break;
+            }
+            info = SearchInfo {
+                depth,
+                seldepth: searcher.seldepth,
+                score,
+                nodes: nodes(&searcher),
+                tt_hits: searcher.tt_hits,
+                elapsed: start.elapsed(),
+                pv: searcher.pv[0][..searcher.pv_len[0]].to_vec(),
+            };
+            report(&info);
+            // Another depth would take longer than the time that's left
+            if let Some(time) = limits.time {
+                if start.elapsed() > time / 2 {
+                    break;
+                }
+            }
+            // Nor can a deeper one find a shorter mate
+            if info.pv.is_empty() || info.mate_in().is_some_and(|moves| depth >= moves.abs() * 2) {
+                break;
+            }
+        }
+        info.nodes = searcher.nodes;
+        info.elapsed = start.elapsed();
+        info
+    }
+}
+
+// Plies a quiet move is searched less deep, the later it comes the more.
+// Moves on the principal variation are reduced a ply less.
+fn reduction(depth: i32, moves: usize, pv_node: bool) -> i32 {
+    let reduction = (0.75 + (depth as f32).ln() * (moves as f32).ln() / 2.25) as i32;
+    (reduction - pv_node as i32).clamp(0, depth - 2)
+}
+
+// Mate scores are stored from the position rather than the root
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+// Capturing a queen with a pawn comes before capturing a pawn with a queen
+fn capture_order(victim: PieceType, attacker: PieceType) -> i32 {
+    let value = |piece_type: PieceType| match piece_type {
+        PieceType::King => 6,
+        _ => piece_type.value() as i32,
+    };
+    value(victim) * 10 - value(attacker)
+}
+
+struct Searcher<'a> {
+    board: Board,
+    tt: &'a TranspositionTable,
+    params: &'a EvalParams,
+    start: Instant,
+    time: Option<Duration>,
+    node_limit: Option<u64>,
+    stop: Option<Arc<AtomicBool>>,
+    stopped: bool,
+    nodes: u64,
+    // Where a helper thread adds up its nodes for the main one to report
+    helper_nodes: Option<&'a AtomicU64>,
+    seldepth: i32,
+    tt_hits: u64,
+    // Two quiet moves per ply that caused a cutoff, tried early in sibling positions
+    killers: [[Move; 2]; MAX_PLY],
+    // How often a quiet move from one square to another caused cutoffs, by side
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    // Best line from each ply
+    pv: Box<[[Move; MAX_PLY]; MAX_PLY]>,
+    pv_len: [usize; MAX_PLY],
+    network: Option<&'a Network>,
+    // The network's hidden layer for the position at each ply, updated as moves are made
+    accumulators: Vec<Accumulator>,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(board: Board, engine: &'a Engine, limits: &Limits) -> Self {
+        let network = engine.network.as_deref();
+        let mut accumulators = Vec::with_capacity(MAX_PLY + 1);
+        accumulators.extend(network.map(|network| network.refresh(&board)));
+        Searcher {
+            board,
+            tt: &engine.tt,
+            params: &engine.params,
+            start: Instant::now(),
+            time: limits.time,
+            node_limit: limits.nodes,
+            stop: limits.stop.clone(),
+            stopped: false,
+            nodes: 0,
+            helper_nodes: None,
+            seldepth: 0,
+            tt_hits: 0,
+            killers: [[Move::NONE; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            pv: Box::new([[Move::NONE; MAX_PLY]; MAX_PLY]),
+            pv_len: [0; MAX_PLY],
+            network,
+            accumulators,
+        }
+    }
+
+    fn make(&mut self, mv: Move) {
+        self.board.make(mv);
+        if let Some(network) = self.network {
+            let mut accumulator = self.accumulators.last().expect("an accumulator").clone();
+            for change in self.board.changes() {
+                network.apply(&mut accumulator, change);
+            }
+            self.accumulators.push(accumulator);
+        }
+    }
+
+    fn make_null(&mut self) {
+        self.board.make_null();
+        if self.network.is_some() {
+            let accumulator = self.accumulators.last().expect("an accumulator").clone();
+            self.accumulators.push(accumulator);
+        }
+    }
+
+    fn unmake(&mut self) {
+        self.board.unmake();
+        self.accumulators.pop();
+    }
+
+    // The first depth always finishes unless the search is stopped, so there's a move to play
+    fn check_limits(&mut self, depth: i32) {
+        if !self.nodes.is_multiple_of(1024) {
+            return;
+        }
+        if let Some(helper_nodes) = self.helper_nodes {
+            helper_nodes.fetch_add(1024, Ordering::Relaxed);
+        }
+        if let Some(stop) = &self.stop {
+            if stop.load(Ordering::Relaxed) {
+                self.stopped = true;
+                return;
+            }
+        }
+        if depth <= 1 {
+            return;
+        }
+        self.stopped = self.time.is_some_and(|time| self.start.elapsed() >= time)
+            || self.node_limit.is_some_and(|nodes| self.nodes >= nodes);
+    }
+
+    // Searches a narrow window around the last depth's score first, widening it until the
+    // score falls inside
+    fn aspiration(&mut self, depth: i32, previous: i32) -> i32 {
+        let mut window = 25;
+        let (mut alpha, mut beta) = if depth >= 5 {
+            (previous - window, previous + window)
+        } else {
+            (-INFINITY, INFINITY)
+        };
+        self.seldepth = 0;
+        loop {
+            let score = self.negamax(depth, alpha, beta, 0, depth, false);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha {
+                beta = (alpha + beta) / 2;
+                alpha = (score - window).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + window).min(INFINITY);
+            } else {
+                return score;
+            }
+            window *= 2;
+        }
+    }
+
+    fn evaluate(&self) -> i32 {
+        match (self.network, self.accumulators.last()) {
+            (Some(network), Some(accumulator)) => network.evaluate(accumulator, self.board.turn),
+            _ => evaluate(&self.board, self.params),
+        }
+    }
+
+    // Orders the moves: the hash move, captures of the most valuable pieces by the least
+    // valuable ones, queen promotions, killers and then quiet moves by their history
+    fn score_moves(&self, moves: &[Move], tt_move: Move, ply: usize, scores: &mut [i32]) {
+        let side = color_index(self.board.turn);
+        for (mv, score) in moves.iter().zip(scores.iter_mut()) {
+            *score = if *mv == tt_move {
+                1_000_000
+            } else if let Some(victim) = self.board.captured(*mv) {
+                let (_, attacker) = self.board.piece_at(mv.from).expect("a piece to move");
+                100_000 + capture_order(victim, attacker)
+            } else if mv.promotion == Some(PieceType::Queen) {
+                90_000
+            } else if *mv == self.killers[ply][0] {
+                80_000
+            } else if *mv == self.killers[ply][1] {
+                79_000
+            } else {
+                self.history[side][mv.from as usize][mv.to as usize]
+            };
+        }
+    }
+
+    // Moves the best scored move left to the front and returns it
+    fn pick(moves: &mut [Move], scores: &mut [i32], index: usize) -> Move {
+        let best = (index..moves.len())
+            .max_by_key(|i| scores[*i])
+            .expect("a move to pick");
+        moves.swap(index, best);
+        scores.swap(index, best);
+        moves[index]
+    }
+
+    // Moves that cause cutoffs more often get tried earlier. Values stay within ±16384.
+    fn update_history(&mut self, mv: Move, bonus: i32) {
+        let entry =
+            &mut self.history[color_index(self.board.turn)][mv.from as usize][mv.to as usize];
+        *entry += bonus - *entry * bonus.abs() / 16384;
+    }
+
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        self.pv[ply][0] = mv;
+        let len = self.pv_len[ply + 1];
+        for i in 0..len {
+            self.pv[ply][i + 1] = self.pv[ply + 1][i];
+        }
+        self.pv_len[ply] = len + 1;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        mut depth: i32,
+        mut alpha: i32,
+        mut beta: i32,
+        ply: usize,
+        root_depth: i32,
+        null_move: bool,
+    ) -> i32 {
+        self.pv_len[ply] = 0;
+        let in_check = self.board.in_check();
+        if in_check {
+            depth += 1;
+        }
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return self.quiesce(alpha, beta, ply, root_depth);
+        }
+        self.nodes += 1;
+        self.check_limits(root_depth);
+        if self.stopped {
+            return 0;
+        }
+        let root = ply == 0;
+        if !root {
+            if self.board.is_draw() {
+                return 0;
+            }
+            // A mate found closer to the root can't be beaten
+            alpha = alpha.max(-MATE + ply as i32);
+            beta = beta.min(MATE - ply as i32 - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        let pv_node = beta - alpha > 1;
+
+        let hash = self.board.hash();
+        let entry = self.tt.probe(hash);
+        let mut tt_move = Move::NONE;
+        let mut static_eval = None;
+        if let Some(entry) = entry {
+            self.tt_hits += 1;
+            tt_move = entry.mv;
+            static_eval = Some(entry.eval);
+            let score = score_from_tt(entry.score, ply);
+            if !pv_node && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+        let static_eval = static_eval.unwrap_or_else(|| self.evaluate());
+
+        // If passing still leaves the side to move above beta, a real move would too.
+        // Not without pieces, where passing would be the only way to avoid zugzwang.
+        if !pv_node
+            && !in_check
+            && !null_move
+            && depth >= 3
+            && static_eval >= beta
+            && self.board.has_pieces(self.board.turn)
+        {
+            let reduction = 3 + depth / 6;
+            self.make_null();
+            let score = -self.negamax(
+                depth - 1 - reduction,
+                -beta,
+                -beta + 1,
+                ply + 1,
+                root_depth,
+                true,
+            );
+            self.unmake();
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return if score >= MATE_BOUND { beta } else { score };
+            }
+        }
+
+        let mut moves = MoveList::new();
+        self.board.generate(&mut moves, true);
+        let moves = moves.as_mut_slice();
+        let mut scores = [0; 256];
+        self.score_moves(moves, tt_move, ply, &mut scores);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = Move::NONE;
+        let mut legal = 0;
+        let mut quiets_tried: Vec<Move> = Vec::new();
+        for i in 0..moves.len() {
+            let mv = Self::pick(moves, &mut scores, i);
+            let tactical = self.board.is_tactical(mv);
+            let killer = self.killers[ply].contains(&mv);
+            self.make(mv);
+            if self.board.left_in_check() {
+                self.unmake();
+                continue;
+            }
+            legal += 1;
+
+            let score = if legal == 1 {
+                -self.negamax(depth - 1, -beta, -alpha, ply + 1, root_depth, false)
+            } else {
+                // Late quiet moves are searched less deep with a null window first,
+                // and again in full if they turn out better than expected
+                let reduced = depth >= 3
+                    && legal > 3
+                    && !tactical
+                    && !killer
+                    && !in_check
+                    && !self.board.in_check();
+                let reduction = if reduced {
+                    reduction(depth, legal, pv_node)
+                } else {
+                    0
+                };
+                let mut score = -self.negamax(
+                    depth - 1 - reduction,
+                    -alpha - 1,
+                    -alpha,
+                    ply + 1,
+                    root_depth,
+                    false,
+                );
+                if score > alpha && reduction > 0 {
+                    score =
+                        -self.negamax(depth - 1, -alpha - 1, -alpha, ply + 1, root_depth, false);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, -beta, -alpha, ply + 1, root_depth, false);
+                }
+                score
+            };
+            self.unmake();
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = mv;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+                }
+            }
+            if score >= beta {
+                if !tactical {
+                    if self.killers[ply][0] != mv {
+                        self.killers[ply][1] = self.killers[ply][0];
+                        self.killers[ply][0] = mv;
+                    }
+                    let bonus = (depth * depth).min(1200);
+                    self.update_history(mv, bonus);
+                    for tried in &quiets_tried {
+                        self.update_history(*tried, -bonus);
+                    }
+                }
+                break;
+            }
+            if !tactical {
+                quiets_tried.push(mv);
+            }
+        }
+
+        if legal == 0 {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(
+            hash,
+            Entry {
+                mv: best_move,
+                score: score_to_tt(best_score, ply),
+                eval: static_eval,
+                depth,
+                bound,
+            },
+        );
+        best_score
+    }
+
+    // Plays out captures until the position is quiet, the side to move can always
+    // stand pat instead of capturing
+    fn quiesce(&mut self, mut alpha: i32, beta: i32, ply: usize, root_depth: i32) -> i32 {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply as i32);
+        self.check_limits(root_depth);
+        if self.stopped {
+            return 0;
+        }
+        let stand_pat = self.evaluate();
+        if ply >= MAX_PLY - 1 || stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = MoveList::new();
+        self.board.generate(&mut moves, false);
+        let moves = moves.as_mut_slice();
+        let mut scores = [0; 256];
+        self.score_moves(moves, Move::NONE, ply, &mut scores);
+        let mut best_score = stand_pat;
+        for i in 0..moves.len() {
+            let mv = Self::pick(moves, &mut scores, i);
+            self.make(mv);
+            if self.board.left_in_check() {
+                self.unmake();
+                continue;
+            }
+            let score = -self.quiesce(-beta, -alpha, ply + 1, root_depth);
+            self.unmake();
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                }
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+        best_score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::nnue;
+    use crate::fen::START_FEN;
+
+    fn search(fen: &str, depth: i32) -> SearchInfo {
+        let board = Board::from_fen(fen).unwrap();
+        let limits = Limits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        Engine::new(1).search(&board, &limits, |_| {})
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let info = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 3);
+        assert_eq!(info.best_move().unwrap().uci(), "a1a8");
+        assert_eq!(info.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn finds_mate_in_two() {
+        // Nf6+ gxf6 Bxf7#
+        let info = search(
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            5,
+        );
+        assert_eq!(info.best_move().unwrap().uci(), "d5f6");
+        assert_eq!(info.mate_in(), Some(2));
+    }
+
+    #[test]
+    fn helper_threads_find_the_same_mate() {
+        let board =
+            Board::from_fen("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1")
+                .unwrap();
+        let limits = Limits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let mut engine = Engine::new(4);
+        engine.set_threads(4);
+        let info = engine.search(&board, &limits, |_| {});
+        assert_eq!(info.best_move().unwrap().uci(), "d5f6");
+        assert_eq!(info.mate_in(), Some(2));
+    }
+
+    #[test]
+    fn one_thread_searches_the_same_way_every_time() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let first = search(fen, 6);
+        let second = search(fen, 6);
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(first.score, second.score);
+        assert!(first.pv == second.pv);
+    }
+
+    #[test]
+    fn searches_with_a_network() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let limits = Limits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let mut engine = Engine::new(1);
+        engine.set_network(Some(Arc::new(nnue::tests::network())));
+        let info = engine.search(&board, &limits, |_| {});
+        assert_eq!(info.best_move().unwrap().uci(), "a1a8");
+    }
+
+    #[test]
+    fn takes_a_hanging_queen() {
+        let info = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 4);
+        assert_eq!(info.best_move().unwrap().uci(), "d2d5");
+    }
+
+    #[test]
+    fn no_move_when_the_game_is_over() {
+        let info = search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert!(info.best_move().is_none());
+        assert_eq!(info.score, 0);
+    }
+
+    #[test]
+    fn hash_table_saves_work() {
+        let board = Board::from_fen(START_FEN).unwrap();
+        let limits = Limits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let engine = Engine::new(4);
+        let first = engine.search(&board, &limits, |_| {});
+        let second = engine.search(&board, &limits, |_| {});
+        assert!(second.tt_hits > 0);
+        assert!(second.nodes < first.nodes);
+    }
+
+    #[test]
+    fn stops_when_told() {
+        let board = Board::from_fen(START_FEN).unwrap();
+        let limits = Limits {
+            stop: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        let info = Engine::new(1).search(&board, &limits, |_| {});
+        assert!(info.nodes <= 1024);
+    }
+
+    #[test]
+    fn stops_at_the_node_limit() {
+        let board = Board::from_fen(START_FEN).unwrap();
+        let limits = Limits {
+            nodes: Some(20_000),
+            ..Default::default()
+        };
+        let info = Engine::new(1).search(&board, &limits, |_| {});
+        assert!(info.best_move().is_some());
+        assert!(info.nodes < 30_000);
+    }
+}
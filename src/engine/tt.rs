@@ -0,0 +1,226 @@
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use super::position::Move;
+
+// What a stored score says about the position's real score
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // The search failed high, the score is at least this
+    Lower,
+    // No move reached alpha, the score is at most this
+    Upper,
+}
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub mv: Move,
+    pub score: i32,
+    // Static evaluation of the position
+    pub eval: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+// Entries are written without locks, so searches on other threads can share the table. The key
+// is stored xored with the data, an entry torn by two threads writing at once doesn't match
+// either hash and is ignored.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// Entries a hash can go in, the one that's least worth keeping is replaced
+const CLUSTER_SIZE: usize = 4;
+const SLOT_BYTES: usize = 16;
+
+// Results of earlier searches by position hash, shared by every search of a game
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    // Counts searches, entries from older ones are replaced first
+    generation: AtomicU8,
+}
+
+impl Entry {
+    // Bits: move 0-15, score 16-31, eval 32-47, depth + 1 48-55, bound 56-57, generation 58-63.
+    // Empty slots have no depth.
+    fn pack(self, generation: u8) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        self.mv.pack() as u64
+            | (self.score as i16 as u16 as u64) << 16
+            | (self.eval as i16 as u16 as u64) << 32
+            | ((self.depth + 1).clamp(1, 255) as u64) << 48
+            | bound << 56
+            | ((generation & 63) as u64) << 58
+    }
+
+    fn unpack(data: u64) -> Entry {
+        Entry {
+            mv: Move::unpack(data as u16),
+            score: (data >> 16) as u16 as i16 as i32,
+            eval: (data >> 32) as u16 as i16 as i32,
+            depth: (data >> 48 & 255) as i32 - 1,
+            bound: match data >> 56 & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+        }
+    }
+}
+
+fn stored_depth(data: u64) -> i32 {
+    (data >> 48 & 255) as i32
+}
+
+fn stored_generation(data: u64) -> u8 {
+    (data >> 58) as u8
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let clusters = (megabytes * 1024 * 1024 / SLOT_BYTES / CLUSTER_SIZE).max(1);
+        let mut slots = Vec::new();
+        slots.resize_with(clusters * CLUSTER_SIZE, Slot::default);
+        TranspositionTable {
+            slots,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // Called before each search, so entries of the ones before age
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn cluster(&self, hash: u64) -> &[Slot] {
+        let clusters = self.slots.len() / CLUSTER_SIZE;
+        let index = ((hash as u128 * clusters as u128) >> 64) as usize * CLUSTER_SIZE;
+        &self.slots[index..index + CLUSTER_SIZE]
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        self.cluster(hash).iter().find_map(|slot| {
+            let data = slot.data.load(Ordering::Relaxed);
+            let key = slot.key.load(Ordering::Relaxed);
+            (stored_depth(data) > 0 && key ^ data == hash).then(|| Entry::unpack(data))
+        })
+    }
+
+    // Keeps the entry of the same position if this search went much deeper, otherwise replaces
+    // the shallowest entry, with entries from older searches counting as shallower
+    pub fn store(&self, hash: u64, mut entry: Entry) {
+        let generation = self.generation.load(Ordering::Relaxed) & 63;
+        let cluster = self.cluster(hash);
+        let worth = |data: u64| {
+            let age = generation.wrapping_sub(stored_generation(data)) & 63;
+            stored_depth(data) - 8 * age as i32
+        };
+        let mut replaced = &cluster[0];
+        for slot in cluster {
+            let data = slot.data.load(Ordering::Relaxed);
+            if slot.key.load(Ordering::Relaxed) ^ data == hash && stored_depth(data) > 0 {
+                let old = Entry::unpack(data);
+                if entry.bound != Bound::Exact
+                    && entry.depth + 2 < old.depth
+                    && stored_generation(data) == generation
+                {
+                    return;
+                }
+                if entry.mv == Move::NONE {
+                    entry.mv = old.mv;
+                }
+                replaced = slot;
+                break;
+            }
+            if worth(data) < worth(replaced.data.load(Ordering::Relaxed)) {
+                replaced = slot;
+            }
+        }
+        let data = entry.pack(generation);
+        replaced.key.store(hash ^ data, Ordering::Relaxed);
+        replaced.data.store(data, Ordering::Relaxed);
+    }
+
+    // How full the table is in thousandths, from a sample of entries of the current search
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed) & 63;
+        self.slots
+            .iter()
+            .take(1000)
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                stored_depth(data) > 0 && stored_generation(data) == generation
+            })
+            .count()
+            * 1000
+            / self.slots.len().min(1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(depth: i32, score: i32) -> Entry {
+        Entry {
+            mv: Move {
+                from: 12,
+                to: 28,
+                promotion: None,
+            },
+            score,
+            eval: -35,
+            depth,
+            bound: Bound::Lower,
+        }
+    }
+
+    #[test]
+    fn finds_stored_entries() {
+        let table = TranspositionTable::new(1);
+        assert!(table.probe(42).is_none());
+        table.store(42, entry(7, -31_990));
+        let found = table.probe(42).unwrap();
+        assert!(found.mv == entry(0, 0).mv);
+        assert_eq!((found.score, found.eval, found.depth), (-31_990, -35, 7));
+        assert!(found.bound == Bound::Lower);
+        assert!(table.probe(43).is_none());
+    }
+
+    #[test]
+    fn keeps_deeper_results_of_a_position() {
+        let table = TranspositionTable::new(1);
+        table.store(42, entry(10, 50));
+        table.store(42, entry(3, 20));
+        assert_eq!(table.probe(42).unwrap().depth, 10);
+        // Unless they're from an earlier search
+        table.new_search();
+        table.store(42, entry(3, 20));
+        assert_eq!(table.probe(42).unwrap().depth, 3);
+    }
+
+    #[test]
+    fn replaces_the_shallowest_entry() {
+        // A table of one cluster
+        let table = TranspositionTable::new(0);
+        for (hash, depth) in [(1, 5), (2, 1), (3, 8), (4, 6)] {
+            table.store(hash, entry(depth, 0));
+        }
+        table.store(5, entry(2, 0));
+        assert!(table.probe(2).is_none());
+        assert!([1, 3, 4, 5].iter().all(|hash| table.probe(*hash).is_some()));
+    }
+}
@@ -0,0 +1,156 @@
+use std::f32::consts::PI;
+
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+
+// Middle of the board, lights are aimed at it
+const FOCUS: Vec3 = Vec3::new(3.5, 0., 3.5);
+
+// Lights and surroundings of the board. The key light casts the shadows, the fill light
+// comes from the other side and the ambient light keeps the shaded sides of the pieces
+// readable, so the board looks right from any camera angle.
+pub struct EnvironmentSettings {
+    pub key_color: Color,
+    // In lux
+    pub key_illuminance: f32,
+    // Angle above the board and around it, 0 shines from White's side
+    pub key_elevation: f32,
+    pub key_azimuth: f32,
+    pub shadows: bool,
+    pub fill_color: Color,
+    pub fill_illuminance: f32,
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    pub frame_color: Color,
+    pub table_color: Color,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        EnvironmentSettings {
+            key_color: Color::rgb(1.0, 0.95, 0.85),
+            key_illuminance: 12000.,
+            key_elevation: 0.9,
+            key_azimuth: -0.6,
+            shadows: true,
+            fill_color: Color::rgb(0.75, 0.85, 1.0),
+            fill_illuminance: 3000.,
+            ambient_color: Color::rgb(0.9, 0.9, 1.0),
+            ambient_brightness: 0.3,
+            frame_color: Color::rgb(0.3, 0.18, 0.1),
+            table_color: Color::rgb(0.15, 0.12, 0.1),
+        }
+    }
+}
+
+#[derive(Component)]
+enum SceneLight {
+    Key,
+    Fill,
+}
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EnvironmentSettings>()
+            .add_startup_system(create_environment)
+            .add_system(update_lights);
+    }
+}
+
+// Points a directional light at the board from the given angles
+fn light_transform(elevation: f32, azimuth: f32) -> Transform {
+    let direction =
+        Quat::from_rotation_y(azimuth) * Vec3::new(-elevation.cos(), elevation.sin(), 0.);
+    Transform::from_translation(FOCUS).looking_at(FOCUS - direction, Vec3::Y)
+}
+
+fn create_environment(
+    mut commands: Commands,
+    settings: Res<EnvironmentSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // Shadows only need to cover the board and the pieces around it
+    let shadow_size = 7.;
+    commands
+        .spawn_bundle(DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                shadow_projection: OrthographicProjection {
+                    left: -shadow_size,
+                    right: shadow_size,
+                    bottom: -shadow_size,
+                    top: shadow_size,
+                    near: -3. * shadow_size,
+                    far: 3. * shadow_size,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(SceneLight::Key);
+    commands
+        .spawn_bundle(DirectionalLightBundle::default())
+        .insert(SceneLight::Fill);
+
+    // Wooden frame around the squares, just below them so they don't flicker
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(9.4, 0.2, 9.4))),
+            material: materials.add(StandardMaterial {
+                base_color: settings.frame_color,
+                perceptual_roughness: 0.6,
+                ..Default::default()
+            }),
+            transform: Transform::from_translation(FOCUS - Vec3::Y * 0.11),
+            ..Default::default()
+        })
+        .insert(NotShadowCaster);
+
+    // Table the board stands on
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Plane { size: 60. })),
+            material: materials.add(StandardMaterial {
+                base_color: settings.table_color,
+                perceptual_roughness: 0.8,
+                ..Default::default()
+            }),
+            transform: Transform::from_translation(FOCUS - Vec3::Y * 0.21),
+            ..Default::default()
+        })
+        .insert(NotShadowCaster);
+}
+
+fn update_lights(
+    settings: Res<EnvironmentSettings>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut lights_query: Query<(&SceneLight, &mut DirectionalLight, &mut Transform)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for (scene_light, mut light, mut transform) in lights_query.iter_mut() {
+        match scene_light {
+            SceneLight::Key => {
+                light.color = settings.key_color;
+                light.illuminance = settings.key_illuminance;
+                light.shadows_enabled = settings.shadows;
+                *transform = light_transform(settings.key_elevation, settings.key_azimuth);
+            }
+            // The fill light comes from the opposite side, lower down
+            SceneLight::Fill => {
+                light.color = settings.fill_color;
+                light.illuminance = settings.fill_illuminance;
+                light.shadows_enabled = false;
+                *transform =
+                    light_transform(settings.key_elevation * 0.5, settings.key_azimuth + PI);
+            }
+        }
+    }
+    ambient_light.color = settings.ambient_color;
+    ambient_light.brightness = settings.ambient_brightness;
+}
@@ -0,0 +1,352 @@
+use crate::board::{color_name, king_in_check, parse_square, square_name};
+use crate::piece::{Piece, PieceColor, PieceType};
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// Pieces and side to move of a position
+#[derive(Clone)]
+pub struct Position {
+    pub pieces: Vec<Piece>,
+    pub turn: PieceColor,
+}
+
+pub fn piece_from_char(c: char) -> Option<(PieceColor, PieceType)> {
+    let color = if c.is_ascii_uppercase() {
+        PieceColor::White
+    } else {
+        PieceColor::Black
+    };
+    let piece_type = match c.to_ascii_lowercase() {
+        'k' => PieceType::King,
+        'q' => PieceType::Queen,
+        'r' => PieceType::Rook,
+        'b' => PieceType::Bishop,
+        'n' => PieceType::Knight,
+        'p' => PieceType::Pawn,
+        _ => return None,
+    };
+    Some((color, piece_type))
+}
+
+// Castling rights in FEN order, with the side and the file of the rook
+pub const CASTLING: [(char, PieceColor, u8); 4] = [
+    ('K', PieceColor::White, 7),
+    ('Q', PieceColor::White, 0),
+    ('k', PieceColor::Black, 7),
+    ('q', PieceColor::Black, 0),
+];
+
+fn back_rank(color: PieceColor) -> u8 {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 7,
+    }
+}
+
+// Reads a position in Forsyth-Edwards Notation and checks it can be played from
+pub fn parse_fen(fen: &str) -> Result<Position, String> {
+    let position = read_fen(fen)?;
+    validate_position(&position.pieces, position.turn)?;
+    Ok(position)
+}
+
+// Reads a position without checking it's legal, like one being set up in the editor.
+// Castling rights are kept by marking the kings and rooks that lost them as moved,
+// and the en passant square by marking the pawn that passed it. Move counters are ignored.
+pub fn read_fen(fen: &str) -> Result<Position, String> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next().ok_or("The FEN is empty")?;
+    let turn = match fields.next().unwrap_or("w") {
+        "w" => PieceColor::White,
+        "b" => PieceColor::Black,
+        other => return Err(format!("Unknown side to move '{}'", other)),
+    };
+    let castling = fields.next().unwrap_or("-");
+    if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+        return Err(format!("Unknown castling rights '{}'", castling));
+    }
+    let en_passant = fields.next().unwrap_or("-");
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("Expected 8 ranks, found {}", ranks.len()));
+    }
+    let mut pieces = Vec::new();
+    // The first rank in a FEN is the 8th
+    for (i, rank) in ranks.iter().enumerate() {
+        let x = 7 - i as u8;
+        let mut y = 0u8;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                if empty == 0 {
+                    return Err(format!("Rank {} has a run of 0 empty squares", x + 1));
+                }
+                y += empty as u8;
+            } else {
+                let (color, piece_type) =
+                    piece_from_char(c).ok_or_else(|| format!("Unknown piece '{}'", c))?;
+                if y < 8 {
+                    pieces.push(Piece {
+                        color,
+                        piece_type,
+                        x,
+                        y,
+                        has_moved: false,
+                        en_passant: false,
+                    });
+                }
+                y += 1;
+            }
+            if y > 8 {
+                return Err(format!("Rank {} has more than 8 squares", x + 1));
+            }
+        }
+        if y != 8 {
+            return Err(format!("Rank {} has {} squares instead of 8", x + 1, y));
+        }
+    }
+
+    set_castling_rights(&mut pieces, castling);
+
+    if en_passant != "-" {
+        // The pawn that moved two squares stands just past the square
+        let passed = match (parse_square(en_passant), turn) {
+            (Some((2, y)), PieceColor::Black) => (3, y),
+            (Some((5, y)), PieceColor::White) => (4, y),
+            _ => return Err(format!("Invalid en passant square '{}'", en_passant)),
+        };
+        let pawn = pieces
+            .iter_mut()
+            .find(|piece| {
+                (piece.x, piece.y) == passed
+                    && piece.piece_type == PieceType::Pawn
+                    && piece.color != turn
+            })
+            .ok_or_else(|| format!("No pawn can be taken en passant on {}", en_passant))?;
+        pawn.en_passant = true;
+    }
+
+    Ok(Position { pieces, turn })
+}
+
+// Marks the kings and rooks that can't castle as moved, rights without a king and rook
+// on their starting squares are dropped
+pub fn set_castling_rights(pieces: &mut [Piece], castling: &str) {
+    for piece in pieces.iter_mut() {
+        let on_back_rank = piece.x == back_rank(piece.color);
+        let has_right = |rook_file: Option<u8>| {
+            CASTLING.iter().any(|(c, color, file)| {
+                *color == piece.color
+                    && castling.contains(*c)
+                    && rook_file.is_none_or(|rook_file| rook_file == *file)
+            })
+        };
+        piece.has_moved = match piece.piece_type {
+            PieceType::King => !(on_back_rank && piece.y == 4 && has_right(None)),
+            PieceType::Rook => !(on_back_rank && has_right(Some(piece.y))),
+            _ => false,
+        };
+    }
+}
+
+// Castling rights of a position in FEN, like KQkq
+pub fn castling_rights(pieces: &[Piece]) -> String {
+    let unmoved = |color: PieceColor, piece_type: PieceType, y: u8| {
+        pieces.iter().any(|piece| {
+            (
+                piece.color,
+                piece.piece_type,
+                piece.x,
+                piece.y,
+                piece.has_moved,
+            ) == (color, piece_type, back_rank(color), y, false)
+        })
+    };
+    let rights: String = CASTLING
+        .iter()
+        .filter(|(_, color, file)| {
+            unmoved(*color, PieceType::King, 4) && unmoved(*color, PieceType::Rook, *file)
+        })
+        .map(|(c, _, _)| *c)
+        .collect();
+    if rights.is_empty() {
+        "-".to_string()
+    } else {
+        rights
+    }
+}
+
+// Writes a position in Forsyth-Edwards Notation, the move counters start over
+pub fn to_fen(pieces: &[Piece], turn: PieceColor) -> String {
+    let mut fen = String::new();
+    for x in (0..8).rev() {
+        let mut empty = 0;
+        for y in 0..8 {
+            match pieces.iter().find(|piece| (piece.x, piece.y) == (x, y)) {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(piece_char(piece.color, piece.piece_type));
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if x > 0 {
+            fen.push('/');
+        }
+    }
+
+    let en_passant = pieces
+        .iter()
+        .find(|piece| piece.en_passant)
+        .map(|pawn| match pawn.color {
+            PieceColor::White => square_name((pawn.x - 1, pawn.y)),
+            PieceColor::Black => square_name((pawn.x + 1, pawn.y)),
+        })
+        .unwrap_or_else(|| "-".to_string());
+    format!(
+        "{} {} {} {} 0 1",
+        fen,
+        match turn {
+            PieceColor::White => 'w',
+            PieceColor::Black => 'b',
+        },
+        castling_rights(pieces),
+        en_passant
+    )
+}
+
+pub fn piece_char(color: PieceColor, piece_type: PieceType) -> char {
+    let c = match piece_type {
+        PieceType::King => 'k',
+        PieceType::Queen => 'q',
+        PieceType::Rook => 'r',
+        PieceType::Bishop => 'b',
+        PieceType::Knight => 'n',
+        PieceType::Pawn => 'p',
+    };
+    match color {
+        PieceColor::White => c.to_ascii_uppercase(),
+        PieceColor::Black => c,
+    }
+}
+
+// Checks a position can be played from
+pub fn validate_position(pieces: &[Piece], turn: PieceColor) -> Result<(), String> {
+    for color in [PieceColor::White, PieceColor::Black] {
+        let kings = pieces
+            .iter()
+            .filter(|piece| piece.color == color && piece.piece_type == PieceType::King)
+            .count();
+        if kings != 1 {
+            return Err(format!(
+                "{} needs exactly one king, found {}",
+                color_name(color),
+                kings
+            ));
+        }
+    }
+    if pieces
+        .iter()
+        .any(|piece| piece.piece_type == PieceType::Pawn && (piece.x == 0 || piece.x == 7))
+    {
+        return Err("Pawns can't stand on the first or last rank".to_string());
+    }
+    if king_in_check(turn.opposite(), pieces).is_some() {
+        return Err("The side that just moved can't be in check".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(fen: &str) -> String {
+        let position = read_fen(fen).unwrap();
+        to_fen(&position.pieces, position.turn)
+    }
+
+    #[test]
+    fn writes_back_what_it_reads() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 1",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b Kq d3 0 1",
+            "8/2k5/8/8/8/8/5K2/8 b - - 0 1",
+        ] {
+            assert_eq!(round_trip(fen), fen);
+        }
+    }
+
+    #[test]
+    fn reads_castling_rights() {
+        let position = read_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1").unwrap();
+        let moved = |name: &str| {
+            let square = parse_square(name).unwrap();
+            position
+                .pieces
+                .iter()
+                .find(|piece| (piece.x, piece.y) == square)
+                .unwrap()
+                .has_moved
+        };
+        assert!(!moved("e1") && !moved("h1") && moved("a1"));
+        assert!(!moved("e8") && !moved("a8") && moved("h8"));
+        assert_eq!(castling_rights(&position.pieces), "Kq");
+
+        // Rights are written in FEN order whatever order they're given in
+        assert_eq!(
+            round_trip("r3k2r/8/8/8/8/8/8/R3K2R w qkQK - 0 1"),
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"
+        );
+        // Rights without the king and rook on their squares are dropped
+        assert_eq!(
+            round_trip("4k3/8/8/8/8/8/8/R4K1R w KQkq - 0 1"),
+            "4k3/8/8/8/8/8/8/R4K1R w - - 0 1"
+        );
+        assert_eq!(
+            round_trip("r3k3/8/8/8/8/8/8/4K2R w KQkq - 0 1"),
+            "r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1"
+        );
+        assert!(read_fen("4k3/8/8/8/8/8/8/4K3 w KX - 0 1").is_err());
+    }
+
+    #[test]
+    fn rejects_en_passant_without_a_pawn() {
+        assert!(read_fen("4k3/8/8/8/8/8/8/4K3 w - d6 0 1").is_err());
+        // The square has to be behind a pawn of the side that just moved
+        assert!(read_fen("4k3/8/8/3p4/8/8/8/4K3 w - d3 0 1").is_err());
+        assert!(read_fen("4k3/8/8/3p4/8/8/8/4K3 w - d6 0 1").is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_ranks() {
+        assert!(read_fen("rnbqkbnr/pppppppp/08/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(read_fen("rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(read_fen("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(read_fen("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(read_fen("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(read_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1").is_err());
+    }
+
+    #[test]
+    fn rejects_unplayable_positions() {
+        // Two white kings
+        assert!(parse_fen("4k3/8/8/8/8/8/8/3KK3 w - - 0 1").is_err());
+        // No black king
+        assert!(parse_fen("8/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        // Pawns on the back ranks
+        assert!(parse_fen("4k3/8/8/8/8/8/8/P3K3 w - - 0 1").is_err());
+        assert!(parse_fen("p3k3/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        // Black just moved and left its king in check
+        assert!(parse_fen("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1").is_err());
+        assert!(parse_fen(START_FEN).is_ok());
+    }
+}
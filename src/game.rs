@@ -0,0 +1,145 @@
+use bevy::ecs::schedule::StateError;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::GameResult;
+use crate::clock::DEFAULT_TIME_CONTROL;
+use crate::fen::{parse_fen, Position, START_FEN};
+use crate::piece::PieceColor;
+
+// Screens of the app. Paused and GameOver are pushed on top of Playing,
+// so the game stays on the board while they're shown.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AppState {
+    Menu,
+    Setup,
+    // Setting up a position by hand
+    Editor,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+// Who plays the other side
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Opponent {
+    // Both sides are played on this computer
+    Human,
+    // The engine plays the side the player doesn't
+    Computer,
+}
+
+impl Opponent {
+    pub const ALL: [Opponent; 2] = [Opponent::Human, Opponent::Computer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Opponent::Human => "Human",
+            Opponent::Computer => "Computer",
+        }
+    }
+}
+
+// Options the next game is started with
+pub struct GameSetup {
+    pub opponent: Opponent,
+    // Side the board is seen from at the start
+    pub player_color: PieceColor,
+    // Index into the clock's time controls
+    pub time_control: usize,
+    pub fen: String,
+    // Threads the computer searches with
+    pub threads: usize,
+    // Evaluate with the network when there is one
+    pub use_network: bool,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        GameSetup {
+            opponent: Opponent::Human,
+            player_color: PieceColor::White,
+            time_control: DEFAULT_TIME_CONTROL,
+            fen: START_FEN.to_string(),
+            threads: 1,
+            use_network: true,
+        }
+    }
+}
+
+impl GameSetup {
+    // The setup screen only starts games from valid positions,
+    // anything else falls back to the standard one
+    pub fn position(&self) -> Position {
+        parse_fen(&self.fen).unwrap_or_else(|err| {
+            warn!("Invalid starting position {:?}: {}", self.fen, err);
+            parse_fen(START_FEN).expect("the starting position is valid")
+        })
+    }
+
+    // Side moved by the engine, if it plays in this game
+    pub fn computer_color(&self) -> Option<PieceColor> {
+        (self.opponent == Opponent::Computer).then(|| self.player_color.opposite())
+    }
+}
+
+// Set while a text field takes the keyboard, so typing doesn't trigger shortcuts
+#[derive(Default)]
+pub struct TextFocus(pub bool);
+
+// UI only shown while a game is on the board
+#[derive(Component)]
+pub struct Hud;
+
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(AppState::Menu)
+            .init_resource::<GameSetup>()
+            .init_resource::<TextFocus>()
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(end_game))
+            .add_system(pause_game)
+            .add_system(show_hud);
+    }
+}
+
+fn end_game(result: Res<GameResult>, mut state: ResMut<State<AppState>>) {
+    if result.0.is_some() {
+        change_state(state.push(AppState::GameOver));
+    }
+}
+
+// P pauses and resumes the game. It isn't a state system, those can run twice in the frame
+// the state changes and would resume right after pausing.
+fn pause_game(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !keys.just_pressed(KeyCode::P) || focus.0 {
+        return;
+    }
+    match state.current() {
+        AppState::Playing => change_state(state.push(AppState::Paused)),
+        AppState::Paused => change_state(state.pop()),
+        _ => {}
+    }
+}
+
+// A change can't be queued while another one is pending, like two buttons clicked at once
+pub fn change_state(result: Result<(), StateError>) {
+    if let Err(err) = result {
+        warn!("Couldn't change the app state: {:?}", err);
+    }
+}
+
+fn show_hud(state: Res<State<AppState>>, mut hud_query: Query<&mut Visibility, With<Hud>>) {
+    let in_game =
+        *state.current() == AppState::Playing || state.inactives().contains(&AppState::Playing);
+    for mut visibility in hud_query.iter_mut() {
+        if visibility.is_visible != in_game {
+            visibility.is_visible = in_game;
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::board::{Square, SquareEvent};
+use crate::game::{AppState, TextFocus};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+// Highlight layers, ordered from lowest to highest priority.
+// Higher layers are blended on top of the lower ones.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub enum HighlightLayer {
+    LastMove,
+    Check,
+    Selection,
+    LegalMove,
+    LegalCapture,
+    Hover,
+    // Square under the keyboard cursor
+    Cursor,
+    UserMark,
+}
+
+impl HighlightLayer {
+    pub const ALL: [HighlightLayer; 8] = [
+        HighlightLayer::LastMove,
+        HighlightLayer::Check,
+        HighlightLayer::Selection,
+        HighlightLayer::LegalMove,
+        HighlightLayer::LegalCapture,
+        HighlightLayer::Hover,
+        HighlightLayer::Cursor,
+        HighlightLayer::UserMark,
+    ];
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+// Each layer takes a bit of the u16 masks
+const _: () = assert!(HighlightLayer::ALL.len() <= 16);
+
+// Squares covered by each highlight layer
+#[derive(Default)]
+pub struct Highlights {
+    layers: HashMap<HighlightLayer, HashSet<(u8, u8)>>,
+}
+
+impl Highlights {
+    pub fn set(&mut self, layer: HighlightLayer, squares: impl IntoIterator<Item = (u8, u8)>) {
+        self.layers.insert(layer, squares.into_iter().collect());
+    }
+
+    pub fn clear(&mut self, layer: HighlightLayer) {
+        self.layers.remove(&layer);
+    }
+
+    pub fn toggle(&mut self, layer: HighlightLayer, square: (u8, u8)) {
+        let squares = self.layers.entry(layer).or_default();
+        if !squares.remove(&square) {
+            squares.insert(square);
+        }
+    }
+
+    pub fn contains(&self, layer: HighlightLayer, square: (u8, u8)) -> bool {
+        self.layers
+            .get(&layer)
+            .is_some_and(|squares| squares.contains(&square))
+    }
+
+    pub fn is_empty(&self, layer: HighlightLayer) -> bool {
+        self.layers
+            .get(&layer)
+            .is_none_or(|squares| squares.is_empty())
+    }
+
+    pub fn squares(&self, layer: HighlightLayer) -> impl Iterator<Item = &(u8, u8)> {
+        self.layers.get(&layer).into_iter().flatten()
+    }
+
+    // Bitmask of the layers covering a square
+    fn mask(&self, square: (u8, u8)) -> u16 {
+        HighlightLayer::ALL
+            .iter()
+            .filter(|layer| self.contains(**layer, square))
+            .fold(0, |mask, layer| mask | layer.bit())
+    }
+}
+
+// Base square colors and the color of each highlight layer.
+// The alpha of a layer color is how strongly it's blended over the layers below it.
+#[derive(Clone)]
+pub struct HighlightPalette {
+    pub light: Color,
+    pub dark: Color,
+    // Optional textures, tinted by the blended colors
+    pub light_texture: Option<Handle<Image>>,
+    pub dark_texture: Option<Handle<Image>>,
+    pub layers: HashMap<HighlightLayer, Color>,
+}
+
+impl Default for HighlightPalette {
+    fn default() -> Self {
+        HighlightPalette {
+            light: Color::rgb(1.0, 0.9, 0.9),
+            dark: Color::rgb(0.0, 0.1, 0.1),
+            light_texture: None,
+            dark_texture: None,
+            layers: HashMap::from([
+                (HighlightLayer::LastMove, Color::rgba(0.8, 0.8, 0.2, 0.5)),
+                (HighlightLayer::Check, Color::rgba(1.0, 0.0, 0.0, 0.8)),
+                (HighlightLayer::Selection, Color::rgba(0.0, 1.0, 0.0, 1.0)),
+                (HighlightLayer::LegalMove, Color::rgba(0.3, 0.6, 1.0, 0.8)),
+                (
+                    HighlightLayer::LegalCapture,
+                    Color::rgba(1.0, 0.5, 0.0, 0.9),
+                ),
+                (HighlightLayer::Hover, Color::rgba(1.0, 0.0, 0.0, 0.6)),
+                (HighlightLayer::Cursor, Color::rgba(0.2, 0.8, 1.0, 0.8)),
+                (HighlightLayer::UserMark, Color::rgba(0.6, 0.0, 0.8, 0.7)),
+            ]),
+        }
+    }
+}
+
+impl HighlightPalette {
+    fn blend(&self, is_white: bool, mask: u16) -> Color {
+        let mut color = if is_white { self.light } else { self.dark };
+        for layer in HighlightLayer::ALL {
+            if mask & layer.bit() == 0 {
+                continue;
+            }
+            if let Some(layer_color) = self.layers.get(&layer) {
+                let a = layer_color.a();
+                color = Color::rgb(
+                    color.r() * (1.0 - a) + layer_color.r() * a,
+                    color.g() * (1.0 - a) + layer_color.g() * a,
+                    color.b() * (1.0 - a) + layer_color.b() * a,
+                );
+            }
+        }
+        color
+    }
+}
+
+// Highlight colors picked for readability, used over the theme's
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum HighlightScheme {
+    // The theme's own colors
+    #[default]
+    Theme,
+    // Okabe-Ito colors, told apart with any kind of color blindness
+    Colorblind,
+    // Black and white squares with solid highlights
+    HighContrast,
+}
+
+impl HighlightScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            HighlightScheme::Theme => "theme colors",
+            HighlightScheme::Colorblind => "colorblind friendly",
+            HighlightScheme::HighContrast => "high contrast",
+        }
+    }
+
+    fn next(self) -> HighlightScheme {
+        match self {
+            HighlightScheme::Theme => HighlightScheme::Colorblind,
+            HighlightScheme::Colorblind => HighlightScheme::HighContrast,
+            HighlightScheme::HighContrast => HighlightScheme::Theme,
+        }
+    }
+
+    // The theme's palette with the scheme's colors put over it
+    pub fn apply(self, palette: &HighlightPalette) -> HighlightPalette {
+        let layers = match self {
+            HighlightScheme::Theme => return palette.clone(),
+            HighlightScheme::Colorblind => [
+                (HighlightLayer::LastMove, Color::rgba(0.94, 0.89, 0.26, 0.6)),
+                (HighlightLayer::Check, Color::rgba(0.84, 0.37, 0.0, 0.9)),
+                (HighlightLayer::Selection, Color::rgba(0.0, 0.45, 0.7, 1.0)),
+                (
+                    HighlightLayer::LegalMove,
+                    Color::rgba(0.34, 0.71, 0.91, 0.8),
+                ),
+                (
+                    HighlightLayer::LegalCapture,
+                    Color::rgba(0.9, 0.62, 0.0, 0.9),
+                ),
+                (HighlightLayer::Hover, Color::rgba(0.8, 0.47, 0.65, 0.7)),
+                (HighlightLayer::Cursor, Color::rgba(0.0, 0.62, 0.45, 0.9)),
+                (HighlightLayer::UserMark, Color::rgba(0.5, 0.5, 0.5, 0.8)),
+            ],
+            HighlightScheme::HighContrast => [
+                (HighlightLayer::LastMove, Color::rgba(1.0, 1.0, 0.0, 1.0)),
+                (HighlightLayer::Check, Color::rgba(1.0, 0.0, 0.0, 1.0)),
+                (HighlightLayer::Selection, Color::rgba(0.0, 1.0, 0.0, 1.0)),
+                (HighlightLayer::LegalMove, Color::rgba(0.0, 0.4, 1.0, 1.0)),
+                (
+                    HighlightLayer::LegalCapture,
+                    Color::rgba(1.0, 0.0, 1.0, 1.0),
+                ),
+                (HighlightLayer::Hover, Color::rgba(1.0, 0.5, 0.0, 1.0)),
+                (HighlightLayer::Cursor, Color::rgba(0.0, 1.0, 1.0, 1.0)),
+                (HighlightLayer::UserMark, Color::rgba(0.6, 0.0, 1.0, 1.0)),
+            ],
+        };
+        let mut palette = palette.clone();
+        palette.layers = HashMap::from(layers);
+        if self == HighlightScheme::HighContrast {
+            palette.light = Color::WHITE;
+            palette.dark = Color::BLACK;
+            palette.light_texture = None;
+            palette.dark_texture = None;
+        }
+        palette
+    }
+}
+
+// Materials shared between all squares with the same base color and highlight layers
+#[derive(Default)]
+pub struct SquareMaterials {
+    cache: HashMap<(bool, u16), Handle<StandardMaterial>>,
+}
+
+impl SquareMaterials {
+    pub fn get(
+        &mut self,
+        is_white: bool,
+        mask: u16,
+        palette: &HighlightPalette,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.cache
+            .entry((is_white, mask))
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: palette.blend(is_white, mask),
+                    base_color_texture: if is_white {
+                        palette.light_texture.clone()
+                    } else {
+                        palette.dark_texture.clone()
+                    },
+                    ..Default::default()
+                })
+            })
+            .clone()
+    }
+}
+
+pub struct HighlightPlugin;
+
+impl Plugin for HighlightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Highlights>()
+            .init_resource::<HighlightPalette>()
+            .init_resource::<SquareMaterials>()
+            .init_resource::<HighlightScheme>()
+            .add_system(hover_squares)
+            .add_system(switch_scheme)
+            .add_system(mark_squares.after(hover_squares))
+            .add_system_to_stage(CoreStage::PostUpdate, color_squares);
+    }
+}
+
+fn hover_squares(mut square_events: EventReader<SquareEvent>, mut highlights: ResMut<Highlights>) {
+    for event in square_events.iter() {
+        if let SquareEvent::Hovered(pos) = event {
+            match pos {
+                Some(pos) => highlights.set(HighlightLayer::Hover, [*pos]),
+                None => highlights.clear(HighlightLayer::Hover),
+            }
+        }
+    }
+}
+
+// Right click toggles a mark on the hovered square, left click clears all marks.
+// While a piece is selected right click cancels the selection instead,
+// and in the board editor it removes pieces.
+pub fn mark_squares(
+    mouse_buttons: Res<Input<MouseButton>>,
+    state: Res<State<AppState>>,
+    mut highlights: ResMut<Highlights>,
+) {
+    if *state.current() == AppState::Editor {
+        return;
+    }
+    if mouse_buttons.just_pressed(MouseButton::Right) {
+        if !highlights.is_empty(HighlightLayer::Selection) {
+            return;
+        }
+        let hovered = highlights.squares(HighlightLayer::Hover).next().copied();
+        if let Some(square) = hovered {
+            highlights.toggle(HighlightLayer::UserMark, square);
+        }
+    } else if mouse_buttons.just_pressed(MouseButton::Left)
+        && !highlights.is_empty(HighlightLayer::UserMark)
+    {
+        highlights.clear(HighlightLayer::UserMark);
+    }
+}
+
+// H goes to the next highlight scheme
+fn switch_scheme(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    mut scheme: ResMut<HighlightScheme>,
+) {
+    if keys.just_pressed(KeyCode::H) && !focus.0 {
+        *scheme = scheme.next();
+    }
+}
+
+// Swap each square's material for the shared one matching its highlight layers
+fn color_squares(
+    highlights: Res<Highlights>,
+    palette: Res<HighlightPalette>,
+    scheme: Res<HighlightScheme>,
+    mut square_materials: ResMut<SquareMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut query: Query<(&Square, &mut Handle<StandardMaterial>)>,
+) {
+    if palette.is_changed() || scheme.is_changed() {
+        square_materials.cache.clear();
+    } else if !highlights.is_changed() {
+        return;
+    }
+    let palette = scheme.apply(&palette);
+
+    for (square, mut material) in query.iter_mut() {
+        let handle = square_materials.get(
+            square.is_white(),
+            highlights.mask((square.x, square.y)),
+            &palette,
+            &mut materials,
+        );
+        if *material != handle {
+            *material = handle;
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::prelude::*;
+
+use crate::animation::AnimationSettings;
+use crate::board::{has_legal_moves, king_in_check, square_name, Captured};
+use crate::cursor::KeyboardCursor;
+use crate::game::{AppState, TextFocus};
+use crate::highlight::{HighlightLayer, Highlights};
+use crate::piece::{Piece, PieceColor, PieceType};
+
+pub struct MoveRecord {
+    pub san: String,
+    pub from: (u8, u8),
+    pub to: (u8, u8),
+    // Piece a pawn became on the last rank
+    pub promotion: Option<PieceType>,
+    // Pieces on the board after the move
+    pub position: Vec<(Entity, Piece)>,
+}
+
+#[derive(Default)]
+pub struct MoveHistory {
+    // Pieces on the board before the first move
+    pub start: Vec<(Entity, Piece)>,
+    pub moves: Vec<MoveRecord>,
+    // Number of moves played in the position shown on the board
+    pub shown: usize,
+}
+
+impl MoveHistory {
+    pub fn record(&mut self, before: Vec<(Entity, Piece)>, record: MoveRecord) {
+        if self.moves.is_empty() {
+            self.start = before;
+        }
+        self.moves.push(record);
+        self.shown = self.moves.len();
+    }
+
+    // Whether the board shows an earlier position than the one being played
+    pub fn is_browsing(&self) -> bool {
+        self.shown < self.moves.len()
+    }
+
+    // Pieces on the board after the given number of moves
+    pub fn position(&self, ply: usize) -> &[(Entity, Piece)] {
+        match ply {
+            0 => &self.start,
+            _ => &self.moves[ply - 1].position,
+        }
+    }
+}
+
+// Asks for the position after the given number of moves to be shown on the board
+pub struct ShowMove(pub usize);
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveHistory>()
+            .add_event::<ShowMove>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(clear_history))
+            .add_system(browse_history)
+            .add_system(show_position.after(browse_history));
+    }
+}
+
+pub fn piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::King => "K",
+        PieceType::Queen => "Q",
+        PieceType::Rook => "R",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Pawn => "",
+    }
+}
+
+// Standard algebraic notation of a move, given the pieces before and after it
+pub fn san(
+    piece: &Piece,
+    to: (u8, u8),
+    promotion: Option<PieceType>,
+    before: &[Piece],
+    after: &[Piece],
+) -> String {
+    let from_name = square_name((piece.x, piece.y));
+    let mut san = String::new();
+
+    if piece.piece_type == PieceType::King && (piece.y as i8 - to.1 as i8).abs() == 2 {
+        san.push_str(if to.1 > piece.y { "O-O" } else { "O-O-O" });
+    } else {
+        let is_capture = before.iter().any(|other| (other.x, other.y) == to)
+            || piece.en_passant_victim(to, before).is_some();
+        if piece.piece_type == PieceType::Pawn {
+            if is_capture {
+                san.push_str(&from_name[..1]);
+            }
+        } else {
+            san.push_str(piece_letter(piece.piece_type));
+
+            // Tell apart pieces of the same kind that could also move there,
+            // by file if that's enough, then by rank, then by both
+            let others: Vec<&Piece> = before
+                .iter()
+                .filter(|other| {
+                    other.piece_type == piece.piece_type
+                        && other.color == piece.color
+                        && (other.x, other.y) != (piece.x, piece.y)
+                        && other.is_move_legal(to, before)
+                })
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|other| other.y != piece.y) {
+                    san.push_str(&from_name[..1]);
+                } else if others.iter().all(|other| other.x != piece.x) {
+                    san.push_str(&from_name[1..]);
+                } else {
+                    san.push_str(&from_name);
+                }
+            }
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(to));
+        if let Some(piece_type) = promotion {
+            san.push('=');
+            san.push_str(piece_letter(piece_type));
+        }
+    }
+
+    let opponent = piece.color.opposite();
+    if king_in_check(opponent, after).is_some() {
+        san.push(if has_legal_moves(opponent, after) {
+            '+'
+        } else {
+            '#'
+        });
+    }
+    san
+}
+
+fn clear_history(mut history: ResMut<MoveHistory>) {
+    *history = MoveHistory::default();
+}
+
+// Page Up and Page Down step through the moves, and so do Left and Right while the keyboard
+// cursor isn't up. Home and End go to the first and last.
+fn browse_history(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    cursor: Res<KeyboardCursor>,
+    history: Res<MoveHistory>,
+    mut show_events: EventWriter<ShowMove>,
+) {
+    if focus.0 {
+        return;
+    }
+    let arrows = cursor.0.is_none();
+    let back = keys.just_pressed(KeyCode::PageUp) || arrows && keys.just_pressed(KeyCode::Left);
+    let forward =
+        keys.just_pressed(KeyCode::PageDown) || arrows && keys.just_pressed(KeyCode::Right);
+    if back && history.shown > 0 {
+        show_events.send(ShowMove(history.shown - 1));
+    } else if forward && history.is_browsing() {
+        show_events.send(ShowMove(history.shown + 1));
+    } else if keys.just_pressed(KeyCode::Home) {
+        show_events.send(ShowMove(0));
+    } else if keys.just_pressed(KeyCode::End) {
+        show_events.send(ShowMove(history.moves.len()));
+    }
+}
+
+// Puts the pieces where they stood after the requested move. Pieces that weren't on
+// the board yet are brought back and later captures are hidden, so nothing is lost.
+fn show_position(
+    mut commands: Commands,
+    mut show_events: EventReader<ShowMove>,
+    mut history: ResMut<MoveHistory>,
+    mut highlights: ResMut<Highlights>,
+    mut animation: ResMut<AnimationSettings>,
+    mut pieces_query: Query<&mut Piece>,
+    mut models_query: Query<(&mut Visibility, &mut Transform)>,
+) {
+    let ply = match show_events.iter().last() {
+        Some(ShowMove(ply)) => (*ply).min(history.moves.len()),
+        None => return,
+    };
+    if ply == history.shown {
+        return;
+    }
+    history.shown = ply;
+    animation.instant = true;
+
+    let position = history.position(ply).to_vec();
+    for (entity, _) in history.start.iter() {
+        match position.iter().find(|(other, _)| other == entity) {
+            Some((_, piece)) => {
+                if let Ok(mut current) = pieces_query.get_mut(*entity) {
+                    if *current != *piece {
+                        *current = *piece;
+                    }
+                } else {
+                    commands.entity(*entity).insert(*piece).remove::<Captured>();
+                    if let Ok((mut visibility, mut transform)) = models_query.get_mut(*entity) {
+                        visibility.is_visible = true;
+                        transform.translation = Vec3::new(piece.x as f32, 0., piece.y as f32);
+                    }
+                }
+            }
+            None => {
+                commands
+                    .entity(*entity)
+                    .remove::<Piece>()
+                    .remove::<Captured>();
+                if let Ok((mut visibility, _)) = models_query.get_mut(*entity) {
+                    visibility.is_visible = false;
+                }
+            }
+        }
+    }
+
+    match ply.checked_sub(1).map(|i| &history.moves[i]) {
+        Some(record) => highlights.set(HighlightLayer::LastMove, [record.from, record.to]),
+        None => highlights.clear(HighlightLayer::LastMove),
+    }
+    let pieces: Vec<Piece> = position.iter().map(|(_, piece)| *piece).collect();
+    highlights.set(
+        HighlightLayer::Check,
+        [PieceColor::White, PieceColor::Black]
+            .into_iter()
+            .filter_map(|color| king_in_check(color, &pieces)),
+    );
+}
@@ -1,13 +1,55 @@
 extern crate bevy;
 extern crate bevy_mod_picking;
+mod animation;
+mod announce;
+mod bench;
 mod board;
+mod camera;
+mod captured;
+mod clock;
+mod computer;
+mod coordinates;
+mod cursor;
+mod editor;
+mod engine;
+mod environment;
+mod fen;
+mod game;
+mod highlight;
+mod history;
+mod menu;
+mod move_input;
+mod move_list;
 mod piece;
+mod piece_set;
 mod pieces;
+mod save;
+mod selfplay;
+mod theme;
+mod tune;
+mod uci;
 
 use bevy::prelude::*;
 use bevy_mod_picking::*;
 
 fn main() {
+    // Engine commands run in the terminal without opening the window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        let result = match command.as_str() {
+            "bench" => bench::run(&args[1..]),
+            "selfplay" => selfplay::run(&args[1..]),
+            "tune" => tune::run(&args[1..]),
+            "uci" => uci::run(&args[1..]),
+            _ => Err(format!("Unknown command '{}'", command)),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         // Set anti aliasing to use 4 samples
         .insert_resource(Msaa { samples: 4 })
@@ -17,37 +59,29 @@ fn main() {
             ..Default::default()
         })
         .add_plugins(DefaultPlugins)
-        .add_plugins(DefaultPickingPlugins)
-        // .add_plugin(DebugCursorPickingPlugin)
+        // Squares are colored by the highlight plugin, so the picking highlight plugins are left out
         .add_plugin(PickingPlugin)
+        .add_plugin(InteractablePickingPlugin)
+        // .add_plugin(DebugCursorPickingPlugin)
+        .add_plugin(game::GamePlugin)
+        .add_plugin(menu::MenuPlugin)
+        .add_plugin(save::SavePlugin)
+        .add_plugin(editor::EditorPlugin)
+        .add_plugin(highlight::HighlightPlugin)
         .add_plugin(board::BoardPlugin)
+        .add_plugin(camera::CameraPlugin)
+        .add_plugin(coordinates::CoordinatesPlugin)
+        .add_plugin(environment::EnvironmentPlugin)
+        .add_plugin(history::HistoryPlugin)
+        .add_plugin(move_list::MoveListPlugin)
+        .add_plugin(move_input::MoveInputPlugin)
+        .add_plugin(cursor::CursorPlugin)
+        .add_plugin(announce::AnnouncePlugin)
+        .add_plugin(captured::CapturedPlugin)
+        .add_plugin(clock::ClockPlugin)
+        .add_plugin(computer::ComputerPlugin)
         .add_plugin(pieces::PiecesPlugin)
-        .add_startup_system(setup)
+        .add_plugin(theme::ThemePlugin)
+        .add_plugin(animation::PieceAnimationPlugin)
         .run();
 }
-
-fn setup(mut commands: Commands) {
-    // Camera
-    commands
-        .spawn_bundle(Camera3dBundle {
-            transform: Transform::from_matrix(Mat4::from_rotation_translation(
-                Quat::from_xyzw(-0.3, -0.5, -0.3, 0.5).normalize(),
-                Vec3::new(-7.0, 20.0, 4.0),
-            )),
-            ..Default::default()
-        })
-        .insert_bundle(PickingCameraBundle::default());
-
-    // Light
-    commands.spawn_bundle(PointLightBundle {
-        point_light: PointLight {
-            intensity: 2400.0,
-            radius: 40.0,
-            shadows_enabled: false,
-            ..Default::default()
-        },
-        transform: Transform::from_translation(Vec3::new(4.0, 4.0, 4.0)),
-        ..Default::default()
-    });
-    // TODO improve lighting
-}
@@ -0,0 +1,477 @@
+use std::thread;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+
+use crate::board::{color_name, GameResult};
+use crate::clock::time_controls;
+use crate::computer::EvalNetwork;
+use crate::fen::parse_fen;
+use crate::game::{change_state, AppState, GameSetup, Opponent, TextFocus};
+use crate::save::{has_autosave, saved_games, slot_name, GameSaves, SaveSlot, AUTOSAVE};
+
+const FONT_SIZE: f32 = 24.;
+const TITLE_SIZE: f32 = 56.;
+const BUTTON_WIDTH: f32 = 320.;
+const BUTTON_HEIGHT: f32 = 44.;
+// The FEN field fits a full position
+const FIELD_WIDTH: f32 = 640.;
+
+const OVERLAY_COLOR: Color = Color::rgba(0., 0., 0., 0.6);
+const PANEL_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.9);
+const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+const HOVERED_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+const PRESSED_COLOR: Color = Color::rgb(0.3, 0.5, 0.8);
+const ERROR_COLOR: Color = Color::rgb(1., 0.4, 0.4);
+
+// Root of the UI of a menu screen, removed when its state is left
+#[derive(Component)]
+struct Screen;
+
+#[derive(Component, Clone, PartialEq, Eq)]
+enum MenuButton {
+    NewGame,
+    // Goes on with the autosaved game
+    Continue,
+    // Loads the game saved under this name
+    Load(String),
+    Quit,
+    Opponent,
+    // Threads the computer opponent searches with
+    Threads,
+    // Whether the computer evaluates with the network or the classical evaluation
+    Evaluation,
+    Color,
+    TimeControl,
+    // Text field holding the starting position
+    Fen,
+    // Opens the board editor on the starting position
+    EditPosition,
+    Start,
+    Back,
+    Resume,
+    // Text field holding the name to save under
+    SaveName,
+    Save,
+    Rematch,
+    MainMenu,
+}
+
+// Says what's wrong with the typed position
+#[derive(Component)]
+struct FenError;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Menu).with_system(create_main_menu))
+            .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(remove_screen))
+            .add_system_set(SystemSet::on_enter(AppState::Setup).with_system(create_setup))
+            .add_system_set(
+                SystemSet::on_exit(AppState::Setup)
+                    .with_system(remove_screen)
+                    .with_system(release_focus),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(create_pause_menu))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(remove_screen))
+            .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(create_game_over))
+            .add_system_set(SystemSet::on_exit(AppState::GameOver).with_system(remove_screen))
+            .add_system(press_buttons)
+            .add_system(edit_text.after(press_buttons))
+            .add_system(update_labels.after(edit_text));
+    }
+}
+
+// A column of widgets in the middle of the window. With an overlay color the scene behind
+// is dimmed and can't be clicked, without one the column sits at the bottom, out of the way.
+fn spawn_screen(
+    commands: &mut Commands,
+    overlay: Option<Color>,
+    spawn_widgets: impl FnOnce(&mut ChildBuilder),
+) {
+    let mut root = commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            justify_content: JustifyContent::Center,
+            // UI coordinates go up, so FlexStart is the bottom
+            align_items: match overlay {
+                Some(_) => AlignItems::Center,
+                None => AlignItems::FlexStart,
+            },
+            ..default()
+        },
+        color: overlay.unwrap_or(Color::NONE).into(),
+        focus_policy: match overlay {
+            Some(_) => FocusPolicy::Block,
+            None => FocusPolicy::Pass,
+        },
+        ..default()
+    });
+    root.insert(Screen);
+    if overlay.is_some() {
+        // Keeps clicks from reaching the board
+        root.insert(Interaction::default());
+    }
+    root.with_children(|parent| {
+        parent
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(20.)),
+                    margin: UiRect::all(Val::Px(20.)),
+                    ..default()
+                },
+                color: PANEL_COLOR.into(),
+                ..default()
+            })
+            .with_children(spawn_widgets);
+    });
+}
+
+fn spawn_text(parent: &mut ChildBuilder, text: impl Into<String>, style: &TextStyle) {
+    parent.spawn_bundle(
+        TextBundle::from_section(text, style.clone()).with_style(Style {
+            margin: UiRect::all(Val::Px(8.)),
+            ..default()
+        }),
+    );
+}
+
+// Buttons get their labels from `update_labels`
+fn spawn_button(parent: &mut ChildBuilder, button: MenuButton, width: f32, style: &TextStyle) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(BUTTON_HEIGHT)),
+                margin: UiRect::all(Val::Px(5.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: BUTTON_COLOR.into(),
+            ..default()
+        })
+        .insert(button)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section("", style.clone()));
+        });
+}
+
+fn text_style(asset_server: &AssetServer, font_size: f32) -> TextStyle {
+    TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size,
+        color: Color::WHITE,
+    }
+}
+
+fn create_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = text_style(&asset_server, FONT_SIZE);
+    let saves = saved_games();
+    spawn_screen(&mut commands, Some(OVERLAY_COLOR), |parent| {
+        spawn_text(parent, "Chess", &text_style(&asset_server, TITLE_SIZE));
+        if has_autosave() {
+            spawn_button(parent, MenuButton::Continue, BUTTON_WIDTH, &style);
+        }
+        spawn_button(parent, MenuButton::NewGame, BUTTON_WIDTH, &style);
+        if !saves.is_empty() {
+            spawn_text(
+                parent,
+                "Saved games",
+                &text_style(&asset_server, FONT_SIZE * 0.75),
+            );
+            for name in saves {
+                spawn_button(parent, MenuButton::Load(name), BUTTON_WIDTH, &style);
+            }
+        }
+        spawn_button(parent, MenuButton::Quit, BUTTON_WIDTH, &style);
+    });
+}
+
+fn create_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = text_style(&asset_server, FONT_SIZE);
+    let small = text_style(&asset_server, FONT_SIZE * 0.75);
+    spawn_screen(&mut commands, Some(OVERLAY_COLOR), |parent| {
+        spawn_text(
+            parent,
+            "New game",
+            &text_style(&asset_server, TITLE_SIZE * 0.75),
+        );
+        spawn_button(parent, MenuButton::Opponent, BUTTON_WIDTH, &style);
+        spawn_button(parent, MenuButton::Threads, BUTTON_WIDTH, &style);
+        spawn_button(parent, MenuButton::Evaluation, BUTTON_WIDTH, &style);
+        spawn_button(parent, MenuButton::Color, BUTTON_WIDTH, &style);
+        spawn_button(parent, MenuButton::TimeControl, BUTTON_WIDTH, &style);
+        spawn_text(parent, "Starting position (FEN)", &small);
+        spawn_button(parent, MenuButton::Fen, FIELD_WIDTH, &small);
+        spawn_button(parent, MenuButton::EditPosition, BUTTON_WIDTH, &style);
+        parent
+            .spawn_bundle(TextBundle::from_section(
+                "",
+                TextStyle {
+                    color: ERROR_COLOR,
+                    ..small.clone()
+                },
+            ))
+            .insert(FenError);
+        parent
+            .spawn_bundle(NodeBundle {
+                color: Color::NONE.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                spawn_button(parent, MenuButton::Back, BUTTON_WIDTH / 2., &style);
+                spawn_button(parent, MenuButton::Start, BUTTON_WIDTH / 2., &style);
+            });
+    });
+}
+
+fn create_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut slot: ResMut<SaveSlot>,
+) {
+    let style = text_style(&asset_server, FONT_SIZE);
+    slot.saved = false;
+    spawn_screen(&mut commands, Some(OVERLAY_COLOR), |parent| {
+        spawn_text(
+            parent,
+            "Paused",
+            &text_style(&asset_server, TITLE_SIZE * 0.75),
+        );
+        spawn_button(parent, MenuButton::Resume, BUTTON_WIDTH, &style);
+        parent
+            .spawn_bundle(NodeBundle {
+                color: Color::NONE.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                spawn_button(parent, MenuButton::SaveName, BUTTON_WIDTH, &style);
+                spawn_button(parent, MenuButton::Save, BUTTON_WIDTH / 2., &style);
+            });
+        spawn_button(parent, MenuButton::MainMenu, BUTTON_WIDTH, &style);
+    });
+}
+
+// The final position stays in view and can still be browsed
+fn create_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    result: Res<GameResult>,
+) {
+    let style = text_style(&asset_server, FONT_SIZE);
+    let description = result.0.map(|end| end.description()).unwrap_or_default();
+    spawn_screen(&mut commands, None, |parent| {
+        spawn_text(parent, description, &style);
+        parent
+            .spawn_bundle(NodeBundle {
+                color: Color::NONE.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                spawn_button(parent, MenuButton::Rematch, BUTTON_WIDTH / 2., &style);
+                spawn_button(parent, MenuButton::NewGame, BUTTON_WIDTH / 2., &style);
+                spawn_button(parent, MenuButton::MainMenu, BUTTON_WIDTH / 2., &style);
+            });
+    });
+}
+
+fn remove_screen(mut commands: Commands, screens_query: Query<Entity, With<Screen>>) {
+    for entity in screens_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn release_focus(mut focus: ResMut<TextFocus>) {
+    focus.0 = false;
+}
+
+// Leaving a game exits every state stacked on it, so it's torn down the same way from anywhere
+fn press_buttons(
+    mut buttons_query: Query<(&MenuButton, &Interaction, &mut UiColor), Changed<Interaction>>,
+    mut state: ResMut<State<AppState>>,
+    mut saves: GameSaves,
+    mut slot: ResMut<SaveSlot>,
+    mut focus: ResMut<TextFocus>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    for (button, interaction, mut color) in buttons_query.iter_mut() {
+        color.0 = match interaction {
+            Interaction::Clicked => PRESSED_COLOR,
+            Interaction::Hovered => HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        };
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        focus.0 = matches!(button, MenuButton::Fen | MenuButton::SaveName);
+
+        let setup = &mut saves.setup;
+        match button {
+            MenuButton::NewGame => change_state(state.replace(AppState::Setup)),
+            MenuButton::Continue | MenuButton::Load(_) => {
+                let name = match button {
+                    MenuButton::Load(name) => name.as_str(),
+                    _ => AUTOSAVE,
+                };
+                match saves.load(name) {
+                    Ok(()) => change_state(state.replace(AppState::Playing)),
+                    Err(err) => warn!("Couldn't load {}: {}", name, err),
+                }
+            }
+            MenuButton::Quit => exit_events.send(AppExit),
+            MenuButton::Opponent => {
+                let i = Opponent::ALL
+                    .iter()
+                    .position(|opponent| *opponent == setup.opponent)
+                    .unwrap_or(0);
+                setup.opponent = Opponent::ALL[(i + 1) % Opponent::ALL.len()];
+            }
+            // Doubles up to the number of cores, then goes back to one
+            MenuButton::Threads => {
+                let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+                setup.threads = if setup.threads * 2 <= cores {
+                    setup.threads * 2
+                } else {
+                    1
+                };
+            }
+            MenuButton::Evaluation => setup.use_network = !setup.use_network,
+            MenuButton::Color => setup.player_color = setup.player_color.opposite(),
+            MenuButton::TimeControl => {
+                setup.time_control = (setup.time_control + 1) % time_controls().len();
+            }
+            MenuButton::Fen | MenuButton::SaveName => {}
+            MenuButton::EditPosition => change_state(state.replace(AppState::Editor)),
+            // Invalid positions are already pointed out under the field
+            MenuButton::Start => {
+                if parse_fen(&setup.fen).is_ok() {
+                    change_state(state.replace(AppState::Playing));
+                }
+            }
+            MenuButton::Back | MenuButton::MainMenu => change_state(state.replace(AppState::Menu)),
+            MenuButton::Resume => change_state(state.pop()),
+            MenuButton::Save => {
+                let name = slot_name(&slot.name);
+                if name.is_empty() {
+                    continue;
+                }
+                match saves.save(&name) {
+                    Ok(()) => slot.saved = true,
+                    Err(err) => warn!("Couldn't save {}: {}", name, err),
+                }
+            }
+            // Same options with the colors swapped
+            MenuButton::Rematch => {
+                setup.player_color = setup.player_color.opposite();
+                change_state(state.replace(AppState::Playing));
+            }
+        }
+    }
+}
+
+// Typing goes into the text field once it's clicked, Enter or Esc are done with it.
+// Screens have one field at most.
+fn edit_text(
+    mut char_events: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut focus: ResMut<TextFocus>,
+    mut setup: ResMut<GameSetup>,
+    mut slot: ResMut<SaveSlot>,
+    fields_query: Query<&MenuButton>,
+) {
+    let chars: Vec<char> = char_events.iter().map(|event| event.char).collect();
+    if !focus.0 {
+        return;
+    }
+    let text = match fields_query
+        .iter()
+        .find(|button| matches!(button, MenuButton::Fen | MenuButton::SaveName))
+    {
+        Some(MenuButton::Fen) => &mut setup.fen,
+        Some(_) => {
+            slot.saved = false;
+            &mut slot.name
+        }
+        None => return,
+    };
+    if keys.just_pressed(KeyCode::Return) || keys.just_pressed(KeyCode::Escape) {
+        focus.0 = false;
+        return;
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        text.pop();
+    }
+    text.extend(chars.into_iter().filter(|c| !c.is_control()));
+}
+
+fn button_label(
+    button: &MenuButton,
+    setup: &GameSetup,
+    slot: &SaveSlot,
+    focus: &TextFocus,
+    network: &EvalNetwork,
+) -> String {
+    match button {
+        MenuButton::NewGame => "New game".to_string(),
+        MenuButton::Continue => "Continue".to_string(),
+        MenuButton::Load(name) => name.clone(),
+        MenuButton::Quit => "Quit".to_string(),
+        MenuButton::Opponent => format!("Opponent: {}", setup.opponent.name()),
+        MenuButton::Threads => format!("Computer threads: {}", setup.threads),
+        MenuButton::Evaluation => match (setup.use_network, network.0.is_some()) {
+            (true, true) => "Evaluation: Network".to_string(),
+            (true, false) => "Evaluation: Classical (no network)".to_string(),
+            (false, _) => "Evaluation: Classical".to_string(),
+        },
+        MenuButton::Color => format!("Play as: {}", color_name(setup.player_color)),
+        MenuButton::TimeControl => {
+            format!("Time: {}", time_controls()[setup.time_control].0)
+        }
+        // With a cursor while typing
+        MenuButton::Fen if focus.0 => format!("{}|", setup.fen),
+        MenuButton::Fen => setup.fen.clone(),
+        MenuButton::EditPosition => "Edit position".to_string(),
+        MenuButton::Start => "Start".to_string(),
+        MenuButton::Back => "Back".to_string(),
+        MenuButton::Resume => "Resume".to_string(),
+        MenuButton::SaveName if focus.0 => format!("{}|", slot.name),
+        MenuButton::SaveName => slot.name.clone(),
+        MenuButton::Save if slot.saved => "Saved".to_string(),
+        MenuButton::Save => "Save".to_string(),
+        MenuButton::Rematch => "Rematch".to_string(),
+        MenuButton::MainMenu => "Main menu".to_string(),
+    }
+}
+
+fn update_labels(
+    setup: Res<GameSetup>,
+    slot: Res<SaveSlot>,
+    focus: Res<TextFocus>,
+    network: Res<EvalNetwork>,
+    buttons_query: Query<(&MenuButton, &Children, ChangeTrackers<MenuButton>)>,
+    mut texts_query: Query<&mut Text, Without<FenError>>,
+    mut errors_query: Query<(&mut Text, ChangeTrackers<FenError>), With<FenError>>,
+) {
+    let changed = setup.is_changed() || slot.is_changed() || focus.is_changed();
+    for (button, children, tracker) in buttons_query.iter() {
+        if !changed && !tracker.is_added() {
+            continue;
+        }
+        let label = button_label(button, &setup, &slot, &focus, &network);
+        for child in children.iter() {
+            if let Ok(mut text) = texts_query.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+    for (mut text, tracker) in errors_query.iter_mut() {
+        if changed || tracker.is_added() {
+            text.sections[0].value = parse_fen(&setup.fen).err().unwrap_or_default();
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use bevy::prelude::*;
+
+use crate::board::{uci, GameResult, MoveRequest, PlayerTurn};
+use crate::game::{AppState, GameSetup, Hud, TextFocus};
+use crate::history::{san, MoveHistory};
+use crate::piece::{Piece, PieceColor, PieceType};
+
+const BOX_WIDTH: f32 = 320.;
+const FIELD_HEIGHT: f32 = 36.;
+const FONT_SIZE: f32 = 22.;
+const MESSAGE_SIZE: f32 = 16.;
+// Most suggestions listed under the field
+const MAX_SUGGESTIONS: usize = 6;
+
+const FIELD_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const FOCUSED_COLOR: Color = Color::rgba(0.2, 0.2, 0.3, 0.9);
+const PLACEHOLDER_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+const ERROR_COLOR: Color = Color::rgb(1., 0.4, 0.4);
+
+const PROMOTIONS: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+// A legal move of the side to move, with the ways it can be typed
+struct Candidate {
+    from: (u8, u8),
+    to: (u8, u8),
+    promotion: Option<PieceType>,
+    san: String,
+    uci: String,
+}
+
+impl Candidate {
+    // SAN without the destination's disambiguation, two moves sharing it are ambiguous.
+    // Promotions leave out the piece so e8 matches all four.
+    fn loose_key(&self) -> String {
+        let key = san_key(&self.san);
+        let key = match self.promotion {
+            Some(_) => key[..key.len() - 1].to_string(),
+            None => key,
+        };
+        match key.chars().next() {
+            Some('K' | 'Q' | 'R' | 'B' | 'N') if key.len() > 3 => {
+                format!("{}{}", &key[..1], &key[key.len() - 2..])
+            }
+            _ => key,
+        }
+    }
+}
+
+// Every legal move of the side to move
+fn candidates(pieces: &[Piece], turn: PieceColor) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for piece in pieces.iter().filter(|piece| piece.color == turn) {
+        for to in piece.legal_moves(pieces) {
+            let promotions: Vec<Option<PieceType>> = if piece.promotes(to) {
+                PROMOTIONS.into_iter().map(Some).collect()
+            } else {
+                vec![None]
+            };
+            for promotion in promotions {
+                let after = piece.position_after(to, promotion, pieces);
+                candidates.push(Candidate {
+                    from: (piece.x, piece.y),
+                    to,
+                    promotion,
+                    san: san(piece, to, promotion, pieces, &after),
+                    uci: uci((piece.x, piece.y), to, promotion),
+                });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.san.cmp(&b.san));
+    candidates
+}
+
+// SAN stripped of what doesn't tell moves apart, so Nxf3+, Nf3 and 0-0 are matched
+// like the game writes them. Piece letters can be typed in lower case, except the
+// bishop's which is also a file. A promotion piece comes after the last rank, so e8q
+// reads as e8=Q.
+fn san_key(text: &str) -> String {
+    let mut key: String = text
+        .trim()
+        .chars()
+        .filter(|c| !"+#!?=x-".contains(*c))
+        .map(|c| if c == '0' { 'O' } else { c })
+        .collect();
+    if key.starts_with(['k', 'q', 'r', 'n']) {
+        key[..1].make_ascii_uppercase();
+    }
+    let len = key.len();
+    if key.ends_with(['q', 'r', 'b', 'n']) && key[..len - 1].ends_with(['1', '8']) {
+        key[len - 1..].make_ascii_uppercase();
+    }
+    key
+}
+
+// Outcome of reading a typed move
+enum Parsed<'a> {
+    Move(&'a Candidate),
+    Ambiguous(Vec<&'a Candidate>),
+    Illegal(Vec<&'a Candidate>),
+}
+
+fn parse_move<'a>(text: &str, candidates: &'a [Candidate]) -> Parsed<'a> {
+    let key = san_key(text);
+    let lower = text.trim().to_ascii_lowercase();
+    if let Some(candidate) = candidates
+        .iter()
+        .find(|candidate| candidate.uci == lower || san_key(&candidate.san) == key)
+    {
+        return Parsed::Move(candidate);
+    }
+    let loose: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|candidate| candidate.loose_key() == key)
+        .collect();
+    if loose.len() > 1 {
+        return Parsed::Ambiguous(loose);
+    }
+    Parsed::Illegal(suggestions(text, candidates))
+}
+
+// Moves starting with what's been typed so far
+fn suggestions<'a>(text: &str, candidates: &'a [Candidate]) -> Vec<&'a Candidate> {
+    let key = san_key(text);
+    let lower = text.trim().to_ascii_lowercase();
+    candidates
+        .iter()
+        .filter(|candidate| {
+            san_key(&candidate.san).starts_with(&key) || candidate.uci.starts_with(&lower)
+        })
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+fn list(candidates: &[&Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| candidate.san.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Text typed into the move box, and what's shown under it
+#[derive(Default)]
+struct MoveInput {
+    text: String,
+    message: String,
+    is_error: bool,
+}
+
+#[derive(Component)]
+struct MoveField;
+
+#[derive(Component)]
+struct MoveMessage;
+
+pub struct MoveInputPlugin;
+
+impl Plugin for MoveInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MoveInput>()
+            .add_startup_system(create_move_box)
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(clear_input))
+            .add_system_set(SystemSet::on_exit(AppState::Playing).with_system(release_focus))
+            .add_system_set(SystemSet::on_update(AppState::Playing).with_system(type_moves))
+            .add_system(update_move_box.after(type_moves));
+    }
+}
+
+fn create_move_box(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(10.),
+                    left: Val::Percent(50.),
+                    ..default()
+                },
+                margin: UiRect {
+                    left: Val::Px(-BOX_WIDTH / 2.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(BOX_WIDTH), Val::Undefined),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Hud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Px(FIELD_HEIGHT)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: FIELD_COLOR.into(),
+                    ..default()
+                })
+                .insert(MoveField)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                    ));
+                });
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font,
+                        font_size: MESSAGE_SIZE,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(MoveMessage);
+        });
+}
+
+fn clear_input(mut input: ResMut<MoveInput>) {
+    *input = MoveInput::default();
+}
+
+fn release_focus(mut focus: ResMut<TextFocus>) {
+    focus.0 = false;
+}
+
+// Slash or a click on the box starts typing, Enter plays the move and Esc stops typing.
+// Tab completes the first suggestion.
+#[allow(clippy::too_many_arguments)]
+fn type_moves(
+    mut char_events: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut focus: ResMut<TextFocus>,
+    mut input: ResMut<MoveInput>,
+    field_query: Query<&Interaction, (Changed<Interaction>, With<MoveField>)>,
+    pieces_query: Query<&Piece>,
+    turn: Res<PlayerTurn>,
+    result: Res<GameResult>,
+    history: Res<MoveHistory>,
+    setup: Res<GameSetup>,
+    mut move_requests: EventWriter<MoveRequest>,
+) {
+    let chars: Vec<char> = char_events.iter().map(|event| event.char).collect();
+    if !focus.0 {
+        let clicked = field_query
+            .iter()
+            .any(|interaction| *interaction == Interaction::Clicked);
+        if clicked || keys.just_pressed(KeyCode::Slash) {
+            focus.0 = true;
+        }
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        focus.0 = false;
+        input.text.clear();
+        input.message.clear();
+        return;
+    }
+    // The legal moves and their SAN are only worked out again when a key does something
+    let typed = chars.iter().any(|c| !c.is_control());
+    if !typed && !keys.any_just_pressed([KeyCode::Back, KeyCode::Tab, KeyCode::Return]) {
+        return;
+    }
+
+    let pieces: Vec<Piece> = pieces_query.iter().copied().collect();
+    let candidates = candidates(&pieces, turn.0);
+    let mut edited = false;
+    if keys.just_pressed(KeyCode::Back) {
+        edited = input.text.pop().is_some();
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        if let Some(first) = suggestions(&input.text, &candidates).first() {
+            input.text = first.san.clone();
+            edited = true;
+        }
+    }
+    for c in chars.into_iter().filter(|c| !c.is_control()) {
+        input.text.push(c);
+        edited = true;
+    }
+
+    if keys.just_pressed(KeyCode::Return) && !input.text.trim().is_empty() {
+        let (message, is_error) = if result.0.is_some() {
+            ("The game is over".to_string(), true)
+        } else if history.is_browsing() {
+            ("Go back to the last move to play".to_string(), true)
+        } else if setup.computer_color() == Some(turn.0) {
+            ("Wait for the computer's move".to_string(), true)
+        } else {
+            match parse_move(&input.text, &candidates) {
+                Parsed::Move(candidate) => {
+                    move_requests.send(MoveRequest {
+                        from: candidate.from,
+                        to: candidate.to,
+                        promotion: candidate.promotion,
+                    });
+                    input.text.clear();
+                    (String::new(), false)
+                }
+                Parsed::Ambiguous(matches) => (
+                    format!("{} is ambiguous: {}", input.text.trim(), list(&matches)),
+                    true,
+                ),
+                Parsed::Illegal(matches) if matches.is_empty() => {
+                    (format!("{} isn't a legal move", input.text.trim()), true)
+                }
+                Parsed::Illegal(matches) => (
+                    format!(
+                        "{} isn't a legal move, try {}",
+                        input.text.trim(),
+                        list(&matches)
+                    ),
+                    true,
+                ),
+            }
+        };
+        input.message = message;
+        input.is_error = is_error;
+    } else if edited {
+        let matches = suggestions(&input.text, &candidates);
+        input.message = match input.text.trim() {
+            "" => String::new(),
+            _ if matches.is_empty() => "No legal move starts like that".to_string(),
+            _ => list(&matches),
+        };
+        input.is_error = !input.text.trim().is_empty() && matches.is_empty();
+    }
+}
+
+fn update_move_box(
+    input: Res<MoveInput>,
+    focus: Res<TextFocus>,
+    mut field_query: Query<(&mut UiColor, &Children), With<MoveField>>,
+    mut texts_query: Query<&mut Text, Without<MoveMessage>>,
+    mut messages_query: Query<&mut Text, With<MoveMessage>>,
+) {
+    if !input.is_changed() && !focus.is_changed() {
+        return;
+    }
+    for (mut color, children) in field_query.iter_mut() {
+        color.0 = if focus.0 { FOCUSED_COLOR } else { FIELD_COLOR };
+        for child in children.iter() {
+            if let Ok(mut text) = texts_query.get_mut(*child) {
+                let section = &mut text.sections[0];
+                if focus.0 {
+                    section.value = format!("{}|", input.text);
+                    section.style.color = Color::WHITE;
+                } else if input.text.is_empty() {
+                    section.value = "Press / to type a move".to_string();
+                    section.style.color = PLACEHOLDER_COLOR;
+                } else {
+                    section.value = input.text.clone();
+                    section.style.color = Color::WHITE;
+                }
+            }
+        }
+    }
+    for mut text in messages_query.iter_mut() {
+        text.sections[0].value = input.message.clone();
+        text.sections[0].style.color = if input.is_error {
+            ERROR_COLOR
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::{read_fen, START_FEN};
+
+    fn moves(fen: &str) -> Vec<Candidate> {
+        let position = read_fen(fen).unwrap();
+        candidates(&position.pieces, position.turn)
+    }
+
+    // The typed move's UCI, or the SAN of the moves it could be
+    fn parse(text: &str, candidates: &[Candidate]) -> Result<String, Vec<String>> {
+        match parse_move(text, candidates) {
+            Parsed::Move(candidate) => Ok(candidate.uci.clone()),
+            Parsed::Ambiguous(matches) | Parsed::Illegal(matches) => Err(matches
+                .iter()
+                .map(|candidate| candidate.san.clone())
+                .collect()),
+        }
+    }
+
+    #[test]
+    fn file_tells_knights_apart() {
+        let candidates = moves("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1");
+        assert_eq!(parse("Nbd2", &candidates), Ok("b1d2".to_string()));
+        assert_eq!(parse("nfd2", &candidates), Ok("f3d2".to_string()));
+        assert_eq!(
+            parse("Nd2", &candidates),
+            Err(vec!["Nbd2".to_string(), "Nfd2".to_string()])
+        );
+    }
+
+    #[test]
+    fn rank_tells_rooks_apart() {
+        let candidates = moves("k7/8/8/4R3/8/8/8/4R1K1 w - - 0 1");
+        assert_eq!(parse("R1e3", &candidates), Ok("e1e3".to_string()));
+        assert_eq!(parse("R5e3", &candidates), Ok("e5e3".to_string()));
+        assert!(
+            matches!(parse_move("Re3", &candidates), Parsed::Ambiguous(matches) if matches.len() == 2)
+        );
+    }
+
+    #[test]
+    fn promotions_in_any_case() {
+        let candidates = moves("k7/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        for text in ["e8=Q", "e8Q", "e8q", "e8=q", "e7e8q", "E7E8Q"] {
+            assert_eq!(parse(text, &candidates), Ok("e7e8q".to_string()));
+        }
+        assert_eq!(parse("e8n", &candidates), Ok("e7e8n".to_string()));
+        assert_eq!(parse("e8b", &candidates), Ok("e7e8b".to_string()));
+        assert!(
+            matches!(parse_move("e8", &candidates), Parsed::Ambiguous(matches) if matches.len() == 4)
+        );
+    }
+
+    #[test]
+    fn illegal_moves_get_suggestions() {
+        let candidates = moves(START_FEN);
+        assert!(matches!(parse_move("e5", &candidates), Parsed::Illegal(_)));
+        assert_eq!(parse("Nc6", &candidates), Err(Vec::new()));
+        let knight_moves: Vec<&str> = suggestions("N", &candidates)
+            .iter()
+            .map(|candidate| candidate.san.as_str())
+            .collect();
+        assert_eq!(knight_moves, ["Na3", "Nc3", "Nf3", "Nh3"]);
+        assert_eq!(parse("0-0", &candidates), Err(Vec::new()));
+    }
+}
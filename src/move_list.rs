@@ -0,0 +1,242 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::game::Hud;
+use crate::history::{MoveHistory, ShowMove};
+
+const PANEL_WIDTH: f32 = 220.;
+const PANEL_HEIGHT: f32 = 400.;
+const ROW_HEIGHT: f32 = 24.;
+const FONT_SIZE: f32 = 18.;
+
+const PANEL_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const BUTTON_COLOR: Color = Color::NONE;
+const HOVERED_COLOR: Color = Color::rgba(1., 1., 1., 0.1);
+const SHOWN_COLOR: Color = Color::rgba(0.3, 0.5, 0.8, 0.8);
+
+#[derive(Component)]
+struct MoveListPanel;
+
+// The list of moves inside the panel, scrolled by moving it up
+#[derive(Component, Default)]
+struct MoveList {
+    scroll: f32,
+    rows: usize,
+}
+
+// A move in the list, clicking it shows the position after that many moves
+#[derive(Component)]
+struct MoveButton(usize);
+
+pub struct MoveListPlugin;
+
+impl Plugin for MoveListPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(create_panel)
+            .add_system(update_moves)
+            .add_system(click_moves.after(update_moves))
+            .add_system(scroll_moves.after(update_moves));
+    }
+}
+
+fn create_panel(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(PANEL_WIDTH), Val::Px(PANEL_HEIGHT)),
+                flex_direction: FlexDirection::ColumnReverse,
+                overflow: Overflow::Hidden,
+                ..default()
+            },
+            color: PANEL_COLOR.into(),
+            ..default()
+        })
+        // Keeps clicks on the panel from reaching the board
+        .insert(Interaction::default())
+        .insert(MoveListPanel)
+        .insert(Hud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        flex_shrink: 0.,
+                        size: Size::new(Val::Percent(100.), Val::Undefined),
+                        ..default()
+                    },
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .insert(MoveList::default());
+        });
+}
+
+// Rebuilds the rows when a move is played and marks the move shown on the board
+fn update_moves(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    history: Res<MoveHistory>,
+    mut list_query: Query<(Entity, &mut MoveList, &mut Style)>,
+    mut buttons_query: Query<(&MoveButton, &Interaction, &mut UiColor)>,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    let (list_entity, mut list, mut style) = match list_query.get_single_mut() {
+        Ok(list) => list,
+        Err(_) => return,
+    };
+
+    let rows = history.moves.len().div_ceil(2);
+    if rows != list.rows || history.moves.is_empty() {
+        list.rows = rows;
+        let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+        let text_style = TextStyle {
+            font,
+            font_size: FONT_SIZE,
+            color: Color::WHITE,
+        };
+        commands.entity(list_entity).despawn_descendants();
+        commands.entity(list_entity).with_children(|parent| {
+            // One row per move number, white's move then black's
+            for (row, moves) in history.moves.chunks(2).enumerate() {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.), Val::Px(ROW_HEIGHT)),
+                            flex_shrink: 0.,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: Color::NONE.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn_bundle(
+                            TextBundle::from_section(format!("{}.", row + 1), text_style.clone())
+                                .with_style(Style {
+                                    size: Size::new(Val::Px(40.), Val::Undefined),
+                                    margin: UiRect::new(
+                                        Val::Px(8.),
+                                        Val::Px(0.),
+                                        Val::Px(0.),
+                                        Val::Px(0.),
+                                    ),
+                                    ..default()
+                                }),
+                        );
+                        for (column, record) in moves.iter().enumerate() {
+                            let ply = row * 2 + column + 1;
+                            parent
+                                .spawn_bundle(ButtonBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(80.), Val::Px(ROW_HEIGHT)),
+                                        align_items: AlignItems::Center,
+                                        padding: UiRect::new(
+                                            Val::Px(6.),
+                                            Val::Px(0.),
+                                            Val::Px(0.),
+                                            Val::Px(0.),
+                                        ),
+                                        ..default()
+                                    },
+                                    color: shown_color(ply, &history).into(),
+                                    ..default()
+                                })
+                                .insert(MoveButton(ply))
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(TextBundle::from_section(
+                                        record.san.clone(),
+                                        text_style.clone(),
+                                    ));
+                                });
+                        }
+                    });
+            }
+        });
+    } else {
+        for (button, interaction, mut color) in buttons_query.iter_mut() {
+            *color = match interaction {
+                Interaction::None => shown_color(button.0, &history),
+                _ => HOVERED_COLOR,
+            }
+            .into();
+        }
+    }
+
+    // Keep the shown move in view
+    let visible = PANEL_HEIGHT;
+    if history.shown > 0 {
+        let row_top = ((history.shown - 1) / 2) as f32 * ROW_HEIGHT;
+        if row_top < list.scroll {
+            list.scroll = row_top;
+        } else if row_top + ROW_HEIGHT > list.scroll + visible {
+            list.scroll = row_top + ROW_HEIGHT - visible;
+        }
+    } else {
+        list.scroll = 0.;
+    }
+    style.position.top = Val::Px(-list.scroll);
+}
+
+fn shown_color(ply: usize, history: &MoveHistory) -> Color {
+    if ply == history.shown {
+        SHOWN_COLOR
+    } else {
+        BUTTON_COLOR
+    }
+}
+
+fn click_moves(
+    history: Res<MoveHistory>,
+    mut show_events: EventWriter<ShowMove>,
+    mut buttons_query: Query<(&MoveButton, &Interaction, &mut UiColor), Changed<Interaction>>,
+) {
+    for (button, interaction, mut color) in buttons_query.iter_mut() {
+        *color = match interaction {
+            Interaction::Clicked => {
+                show_events.send(ShowMove(button.0));
+                SHOWN_COLOR
+            }
+            Interaction::Hovered => HOVERED_COLOR,
+            Interaction::None => shown_color(button.0, &history),
+        }
+        .into();
+    }
+}
+
+// The mouse wheel scrolls the list while the cursor is over the panel
+fn scroll_moves(
+    mut wheel_events: EventReader<MouseWheel>,
+    panel_query: Query<&Interaction, With<MoveListPanel>>,
+    buttons_query: Query<&Interaction, With<MoveButton>>,
+    mut list_query: Query<(&mut MoveList, &mut Style)>,
+) {
+    let hovered = panel_query
+        .iter()
+        .chain(buttons_query.iter())
+        .any(|interaction| *interaction != Interaction::None);
+    let (mut list, mut style) = match list_query.get_single_mut() {
+        Ok(list) => list,
+        Err(_) => return,
+    };
+
+    for event in wheel_events.iter() {
+        if !hovered {
+            continue;
+        }
+        let lines = match event.unit {
+            MouseScrollUnit::Line => event.y * ROW_HEIGHT,
+            MouseScrollUnit::Pixel => event.y,
+        };
+        let max_scroll = (list.rows as f32 * ROW_HEIGHT - PANEL_HEIGHT).max(0.);
+        list.scroll = (list.scroll - lines).clamp(0., max_scroll);
+        style.position.top = Val::Px(-list.scroll);
+    }
+}
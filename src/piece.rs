@@ -1,14 +1,24 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::board::{color_of_square, is_path_empty};
+use crate::board::{color_of_square, is_path_empty, is_square_attacked, king_in_check, MoveError};
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PieceColor {
     White,
     Black,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+impl PieceColor {
+    pub fn opposite(self) -> PieceColor {
+        match self {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PieceType {
     King,
     Queen,
@@ -18,13 +28,30 @@ pub enum PieceType {
     Pawn,
 }
 
-#[derive(Clone, Copy, Component)]
+impl PieceType {
+    // Material value in pawns, the king can't be traded so it has none
+    pub fn value(self) -> u8 {
+        match self {
+            PieceType::King => 0,
+            PieceType::Queen => 9,
+            PieceType::Rook => 5,
+            PieceType::Knight | PieceType::Bishop => 3,
+            PieceType::Pawn => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Component)]
 pub struct Piece {
     pub color: PieceColor,
     pub piece_type: PieceType,
     // Current position
     pub x: u8,
     pub y: u8,
+    // Kings and rooks that have moved can't castle
+    pub has_moved: bool,
+    // Set on a pawn that just moved two squares, it can be taken en passant on the next move
+    pub en_passant: bool,
 }
 
 impl Piece {
@@ -42,23 +69,27 @@ impl Piece {
                 // Vertical 
                 (self.y as i8 - new_position.1 as i8).abs() == 1 && (self.x == new_position.0) || 
                 // Diagonal
-                (self.x as i8 - new_position.0 as i8).abs() == 1 && (self.y as i8 - new_position.1 as i8).abs() == 1
+                (self.x as i8 - new_position.0 as i8).abs() == 1 && (self.y as i8 - new_position.1 as i8).abs() == 1 ||
+                // Castling
+                self.is_castling_valid(new_position, &pieces)
             }
             PieceType::Queen => {
-                is_path_empty((self.x, self.y), new_position, &pieces)  &&
-                // Diagonal
-                (self.x as i8 - new_position.0 as i8).abs() == (self.y as i8 - new_position.1 as i8).abs() ||
-                // Horizontal
-                (self.x == new_position.0 && self.y != new_position.1) ||
-                // Vertical
-                (self.x != new_position.0 && self.y == new_position.1)
+                is_path_empty((self.x, self.y), new_position, &pieces)  && (
+                    // Diagonal
+                    (self.x as i8 - new_position.0 as i8).abs() == (self.y as i8 - new_position.1 as i8).abs() ||
+                    // Horizontal
+                    (self.x == new_position.0 && self.y != new_position.1) ||
+                    // Vertical
+                    (self.x != new_position.0 && self.y == new_position.1)
+                )
             },
             PieceType::Rook => {
-                is_path_empty((self.x, self.y), new_position, &pieces)  &&
-                // Horizontal
-                (self.x == new_position.0 && self.y != new_position.1) ||
-                // Vertical
-                (self.x != new_position.0 && self.y == new_position.1)
+                is_path_empty((self.x, self.y), new_position, &pieces)  && (
+                    // Horizontal
+                    (self.x == new_position.0 && self.y != new_position.1) ||
+                    // Vertical
+                    (self.x != new_position.0 && self.y == new_position.1)
+                )
             },
             PieceType::Knight => {
                 (self.x as i8 - new_position.0 as i8).abs() == 1 && (self.y as i8 - new_position.1 as i8).abs() == 2 ||
@@ -73,17 +104,30 @@ impl Piece {
                 match self.color {
                     PieceColor::White => {
                         // Normal move
-                        if new_position.0 as i8 - self.x as i8 == 1 && self.y == new_position.1 {
-                            if color_of_square(new_position, &pieces).is_none() {
-                                return true;
-                            }
+                        if new_position.0 as i8 - self.x as i8 == 1 && self.y == new_position.1
+                            && color_of_square(new_position, &pieces).is_none()
+                        {
+                            return true;
                         } 
 
+                        // Capture
+                        if new_position.0 as i8 - self.x as i8 == 1 && (self.y as i8 - new_position.1 as i8).abs() == 1
+                            && color_of_square(new_position, &pieces) == Some(PieceColor::Black)
+                        {
+                            return true;
+                        }
+
+                        // En passant
+                        if self.en_passant_victim(new_position, &pieces).is_some() {
+                            return true;
+                        }
+
                         // First move
-                        if self.x == 1 && new_position.0 as i8 - self.x as i8 == 2 && self.y == new_position.1  {
-                            if color_of_square(new_position, &pieces).is_none() {
-                                return true;
-                            }
+                        if self.x == 1 && new_position.0 as i8 - self.x as i8 == 2 && self.y == new_position.1
+                            && color_of_square(new_position, &pieces).is_none()
+                            && is_path_empty((self.x, self.y), new_position, &pieces)
+                        {
+                            return true;
                         }
 
                         false
@@ -91,17 +135,30 @@ impl Piece {
 
                     PieceColor::Black => {
                         // Normal move
-                        if new_position.0 as i8 - self.x as i8 == -1 && self.y == new_position.1 {
-                            if color_of_square(new_position, &pieces).is_none() {
-                                return true;
-                            }
+                        if new_position.0 as i8 - self.x as i8 == -1 && self.y == new_position.1
+                            && color_of_square(new_position, &pieces).is_none()
+                        {
+                            return true;
                         } 
 
+                        // Capture
+                        if new_position.0 as i8 - self.x as i8 == -1 && (self.y as i8 - new_position.1 as i8).abs() == 1
+                            && color_of_square(new_position, &pieces) == Some(PieceColor::White)
+                        {
+                            return true;
+                        }
+
+                        // En passant
+                        if self.en_passant_victim(new_position, &pieces).is_some() {
+                            return true;
+                        }
+
                         // First move
-                        if self.x == 6 && new_position.0 as i8 - self.x as i8 == -2 && self.y == new_position.1  {
-                            if color_of_square(new_position, &pieces).is_none() {
-                                return true;
-                            }
+                        if self.x == 6 && new_position.0 as i8 - self.x as i8 == -2 && self.y == new_position.1
+                            && color_of_square(new_position, &pieces).is_none()
+                            && is_path_empty((self.x, self.y), new_position, &pieces)
+                        {
+                            return true;
                         }
 
                         false
@@ -110,4 +167,307 @@ impl Piece {
             },
         }
     }
+
+    // The king moves two squares towards a rook when castling. Neither of them can have moved,
+    // the squares between them must be empty and the king can't start, pass or end on an attacked square
+    fn is_castling_valid(&self, new_position: (u8, u8), pieces: &Vec<Piece>) -> bool {
+        if self.has_moved || new_position.0 != self.x || (self.y as i8 - new_position.1 as i8).abs() != 2 {
+            return false;
+        }
+        let (rook_from, _) = self.castling_rook(new_position);
+        let passed = (self.x, (self.y + new_position.1) / 2);
+
+        pieces.iter().any(|piece| {
+            piece.piece_type == PieceType::Rook && piece.color == self.color && !piece.has_moved && (piece.x, piece.y) == rook_from
+        }) &&
+        is_path_empty((self.x, self.y), rook_from, pieces) &&
+        [(self.x, self.y), passed, new_position].iter().all(|square| !is_square_attacked(*square, self.color, pieces))
+    }
+
+    // Where the pawn taken en passant stands, if moving to the given square is an en passant capture.
+    // The pawn has to have just passed the square by moving two squares.
+    pub fn en_passant_victim(&self, new_position: (u8, u8), pieces: &[Piece]) -> Option<(u8, u8)> {
+        let forward = if self.color == PieceColor::White { 1 } else { -1 };
+        if self.piece_type != PieceType::Pawn
+            || new_position.0 as i8 - self.x as i8 != forward
+            || (self.y as i8 - new_position.1 as i8).abs() != 1
+            || pieces.iter().any(|piece| (piece.x, piece.y) == new_position)
+        {
+            return None;
+        }
+        let victim = (self.x, new_position.1);
+        pieces.iter().any(|piece| {
+            piece.piece_type == PieceType::Pawn && piece.color != self.color && piece.en_passant && (piece.x, piece.y) == victim
+        }).then_some(victim)
+    }
+
+    // Where the rook starts and ends when the king castles to the given square
+    pub fn castling_rook(&self, new_position: (u8, u8)) -> ((u8, u8), (u8, u8)) {
+        if new_position.1 > self.y {
+            ((self.x, 7), (self.x, new_position.1 - 1))
+        } else {
+            ((self.x, 0), (self.x, new_position.1 + 1))
+        }
+    }
+
+    // Returns whether the piece attacks a square. Unlike is_move_valid this doesn't include
+    // pawn pushes or castling, which can't capture
+    pub fn attacks(&self, square: (u8, u8), pieces: &[Piece]) -> bool {
+        let x_diff = square.0 as i8 - self.x as i8;
+        let y_diff = (square.1 as i8 - self.y as i8).abs();
+        match self.piece_type {
+            PieceType::Pawn => {
+                let forward = if self.color == PieceColor::White { 1 } else { -1 };
+                x_diff == forward && y_diff == 1
+            }
+            PieceType::King => x_diff.abs() <= 1 && y_diff <= 1 && (x_diff, y_diff) != (0, 0),
+            _ => self.is_move_valid(square, pieces.to_vec()),
+        }
+    }
+
+    // Returns why the move can't be played, is_move_legal is the quicker check when the reason isn't needed
+    pub fn check_move(&self, new_position: (u8, u8), pieces: &[Piece]) -> Result<(), MoveError> {
+        if !self.is_move_valid(new_position, pieces.to_vec()) {
+            return Err(self.invalid_reason(new_position, pieces));
+        }
+        let pieces_after = self.position_after(new_position, None, pieces);
+        if king_in_check(self.color, &pieces_after).is_none() {
+            Ok(())
+        } else if self.piece_type == PieceType::King {
+            Err(MoveError::KingWouldBeInCheck)
+        } else if king_in_check(self.color, pieces).is_some() {
+            Err(MoveError::InCheck)
+        } else {
+            Err(MoveError::Pinned(self.piece_type))
+        }
+    }
+
+    // Why is_move_valid turned the move down
+    fn invalid_reason(&self, new_position: (u8, u8), pieces: &[Piece]) -> MoveError {
+        if color_of_square(new_position, &pieces.to_vec()) == Some(self.color) {
+            return MoveError::OwnPiece;
+        }
+        let x_diff = new_position.0 as i8 - self.x as i8;
+        let y_diff = new_position.1 as i8 - self.y as i8;
+        let straight = x_diff == 0 || y_diff == 0;
+        let diagonal = x_diff.abs() == y_diff.abs();
+        match self.piece_type {
+            PieceType::King if x_diff == 0 && y_diff.abs() == 2 => {
+                let (rook_from, _) = self.castling_rook(new_position);
+                let has_rook = pieces.iter().any(|piece| {
+                    piece.piece_type == PieceType::Rook && piece.color == self.color && !piece.has_moved && (piece.x, piece.y) == rook_from
+                });
+                if self.has_moved || !has_rook {
+                    MoveError::NoCastlingRights
+                } else if !is_path_empty((self.x, self.y), rook_from, &pieces.to_vec()) {
+                    MoveError::BlockedPath
+                } else {
+                    MoveError::CastlingThroughCheck
+                }
+            }
+            PieceType::Queen if straight || diagonal => MoveError::BlockedPath,
+            PieceType::Rook if straight => MoveError::BlockedPath,
+            PieceType::Bishop if diagonal => MoveError::BlockedPath,
+            PieceType::Pawn => {
+                let forward = if self.color == PieceColor::White { 1 } else { -1 };
+                let start = if self.color == PieceColor::White { 1 } else { 6 };
+                if y_diff == 0 && (x_diff == forward || x_diff == 2 * forward && self.x == start) {
+                    MoveError::BlockedPath
+                } else if x_diff == forward && y_diff.abs() == 1 {
+                    MoveError::NothingToCapture
+                } else {
+                    MoveError::WrongGeometry(self.piece_type)
+                }
+            }
+            _ => MoveError::WrongGeometry(self.piece_type),
+        }
+    }
+
+    // Returns whether the move is valid and doesn't leave the king in check
+    pub fn is_move_legal(&self, new_position: (u8, u8), pieces: &[Piece]) -> bool {
+        if !self.is_move_valid(new_position, pieces.to_vec()) {
+            return false;
+        }
+
+        let pieces_after = self.position_after(new_position, None, pieces);
+        king_in_check(self.color, &pieces_after).is_none()
+    }
+
+    // Returns whether moving to the given square takes a pawn to the last rank
+    pub fn promotes(&self, new_position: (u8, u8)) -> bool {
+        self.piece_type == PieceType::Pawn && (new_position.0 == 0 || new_position.0 == 7)
+    }
+
+    // Returns the pieces after the move, without checking it's valid.
+    // A promoting pawn becomes the given piece, or a queen.
+    pub fn position_after(&self, new_position: (u8, u8), promotion: Option<PieceType>, pieces: &[Piece]) -> Vec<Piece> {
+        let mut moved = *self;
+        moved.x = new_position.0;
+        moved.y = new_position.1;
+        moved.has_moved = true;
+        moved.en_passant = self.piece_type == PieceType::Pawn && (self.x as i8 - new_position.0 as i8).abs() == 2;
+        if self.promotes(new_position) {
+            moved.piece_type = promotion.unwrap_or(PieceType::Queen);
+        }
+        let captured = self.en_passant_victim(new_position, pieces).unwrap_or(new_position);
+        let castling = self.piece_type == PieceType::King && (self.y as i8 - new_position.1 as i8).abs() == 2;
+        let (rook_from, rook_to) = self.castling_rook(new_position);
+        pieces
+            .iter()
+            .filter(|piece| (piece.x, piece.y) != (self.x, self.y) && (piece.x, piece.y) != captured)
+            .map(|piece| {
+                let mut piece = *piece;
+                piece.en_passant = false;
+                if castling && (piece.x, piece.y) == rook_from {
+                    piece.y = rook_to.1;
+                    piece.has_moved = true;
+                }
+                piece
+            })
+            .chain([moved])
+            .collect()
+    }
+
+    // Returns every square the piece can legally move to
+    pub fn legal_moves(&self, pieces: &[Piece]) -> Vec<(u8, u8)> {
+        let mut moves = Vec::new();
+        for x in 0..8 {
+            for y in 0..8 {
+                if self.is_move_legal((x, y), pieces) {
+                    moves.push((x, y));
+                }
+            }
+        }
+        moves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{parse_square, square_name};
+    use crate::fen::{read_fen, START_FEN};
+
+    fn position(fen: &str) -> Vec<Piece> {
+        read_fen(fen).unwrap().pieces
+    }
+
+    fn piece_on(pieces: &[Piece], name: &str) -> Piece {
+        let square = parse_square(name).unwrap();
+        *pieces
+            .iter()
+            .find(|piece| (piece.x, piece.y) == square)
+            .unwrap()
+    }
+
+    // Destinations of the piece on the square, as sorted square names
+    fn moves_from(pieces: &[Piece], name: &str) -> Vec<String> {
+        let mut moves: Vec<String> = piece_on(pieces, name)
+            .legal_moves(pieces)
+            .into_iter()
+            .map(square_name)
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    fn reason(fen: &str, from: &str, to: &str) -> Result<(), MoveError> {
+        let pieces = position(fen);
+        piece_on(&pieces, from).check_move(parse_square(to).unwrap(), &pieces)
+    }
+
+    #[test]
+    fn reasons_for_invalid_moves() {
+        assert!(reason(START_FEN, "e2", "e4") == Ok(()));
+        assert!(reason(START_FEN, "d1", "d2") == Err(MoveError::OwnPiece));
+        assert!(reason(START_FEN, "g1", "g3") == Err(MoveError::WrongGeometry(PieceType::Knight)));
+        assert!(reason(START_FEN, "e2", "e5") == Err(MoveError::WrongGeometry(PieceType::Pawn)));
+        assert!(reason(START_FEN, "c1", "c3") == Err(MoveError::WrongGeometry(PieceType::Bishop)));
+        assert!(reason(START_FEN, "a1", "a4") == Err(MoveError::BlockedPath));
+        assert!(reason(START_FEN, "f1", "c4") == Err(MoveError::BlockedPath));
+        assert!(reason(START_FEN, "e2", "d3") == Err(MoveError::NothingToCapture));
+
+        let blocked_pawn = "4k3/8/8/8/8/4n3/4P3/4K3 w - - 0 1";
+        assert!(reason(blocked_pawn, "e2", "e3") == Err(MoveError::BlockedPath));
+        assert!(reason(blocked_pawn, "e2", "e4") == Err(MoveError::BlockedPath));
+    }
+
+    #[test]
+    fn reasons_for_castling() {
+        assert!(
+            reason("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1", "e1", "g1")
+                == Err(MoveError::NoCastlingRights)
+        );
+        assert!(
+            reason("4k3/8/8/8/8/8/8/RN2K2R w KQ - 0 1", "e1", "c1") == Err(MoveError::BlockedPath)
+        );
+        assert!(reason("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1", "c1") == Ok(()));
+        // Through f1, out of check and into check
+        for fen in [
+            "4kr2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "4r1k1/8/8/8/8/8/8/4K2R w K - 0 1",
+            "4k1r1/8/8/8/8/8/8/4K2R w K - 0 1",
+        ] {
+            assert!(reason(fen, "e1", "g1") == Err(MoveError::CastlingThroughCheck));
+        }
+    }
+
+    #[test]
+    fn reasons_for_moves_into_check() {
+        // The king can't stay on the rook's rank
+        assert!(
+            reason("4k3/8/8/8/8/8/8/3rK3 w - - 0 1", "e1", "f1")
+                == Err(MoveError::KingWouldBeInCheck)
+        );
+        assert!(reason("4k3/8/8/8/8/8/8/3rK3 w - - 0 1", "e1", "e2") == Ok(()));
+        // Checked by the rook on e8, the knight's move doesn't block it
+        assert!(reason("4r1k1/8/8/8/8/2N5/8/4K3 w - - 0 1", "c3", "d5") == Err(MoveError::InCheck));
+        assert!(
+            reason("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", "e2", "d3")
+                == Err(MoveError::Pinned(PieceType::Bishop))
+        );
+    }
+
+    #[test]
+    fn pinned_piece_cant_leave_the_line() {
+        let pieces = position("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1");
+        assert!(moves_from(&pieces, "e2").is_empty());
+
+        let pieces = position("4k3/4r3/8/8/8/8/4R3/4K3 w - - 0 1");
+        assert_eq!(moves_from(&pieces, "e2"), ["e3", "e4", "e5", "e6", "e7"]);
+    }
+
+    #[test]
+    fn pawns_capture_diagonally() {
+        let pieces = position("4k3/8/8/3p1n2/4P3/8/8/4K3 w - - 0 1");
+        assert_eq!(moves_from(&pieces, "e4"), ["d5", "e5", "f5"]);
+
+        let pieces = position("4k3/8/8/4p3/4P3/8/8/4K3 w - - 0 1");
+        assert!(moves_from(&pieces, "e4").is_empty());
+    }
+
+    #[test]
+    fn check_has_to_be_answered() {
+        let pieces = position("4r1k1/8/8/8/8/2N5/8/R3K3 w - - 0 1");
+        assert_eq!(moves_from(&pieces, "c3"), ["e2", "e4"]);
+        assert!(moves_from(&pieces, "a1").is_empty());
+        assert_eq!(moves_from(&pieces, "e1"), ["d1", "d2", "f1", "f2"]);
+    }
+
+    #[test]
+    fn checking_piece_can_be_captured() {
+        let pieces = position("4k3/8/8/8/8/8/3q4/4K2R w K - 0 1");
+        let king = piece_on(&pieces, "e1");
+        assert!(king.is_move_legal(parse_square("d2").unwrap(), &pieces));
+        assert!(!king.is_move_legal(parse_square("f2").unwrap(), &pieces));
+        // No castling out of check
+        assert!(!king.is_move_legal(parse_square("g1").unwrap(), &pieces));
+        assert!(moves_from(&pieces, "h1").is_empty());
+    }
+
+    #[test]
+    fn king_cant_take_a_defended_piece() {
+        let pieces = position("4k3/8/8/8/1b6/8/3q4/4K3 w - - 0 1");
+        assert_eq!(moves_from(&pieces, "e1"), ["f1"]);
+    }
 }
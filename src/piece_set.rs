@@ -0,0 +1,129 @@
+use bevy::asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+use crate::piece::PieceType;
+
+// The meshes of a piece and where they sit relative to its square
+pub struct PieceModelSpec {
+    pub meshes: Vec<Handle<Mesh>>,
+    pub transform: Transform,
+}
+
+// A set of piece models, loaded from a `.pieces.ron` manifest
+#[derive(TypeUuid)]
+#[uuid = "5b8c2f4e-1d7a-4c39-9e61-0a3f6d2b7c84"]
+pub struct PieceSet {
+    pub king: PieceModelSpec,
+    pub queen: PieceModelSpec,
+    pub rook: PieceModelSpec,
+    pub bishop: PieceModelSpec,
+    pub knight: PieceModelSpec,
+    pub pawn: PieceModelSpec,
+}
+
+impl PieceSet {
+    pub fn model(&self, piece_type: PieceType) -> &PieceModelSpec {
+        match piece_type {
+            PieceType::King => &self.king,
+            PieceType::Queen => &self.queen,
+            PieceType::Rook => &self.rook,
+            PieceType::Bishop => &self.bishop,
+            PieceType::Knight => &self.knight,
+            PieceType::Pawn => &self.pawn,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PieceSetManifest {
+    king: PieceModelManifest,
+    queen: PieceModelManifest,
+    rook: PieceModelManifest,
+    bishop: PieceModelManifest,
+    knight: PieceModelManifest,
+    pawn: PieceModelManifest,
+}
+
+#[derive(Deserialize)]
+struct PieceModelManifest {
+    // glTF primitives, relative to the manifest
+    meshes: Vec<String>,
+    #[serde(default)]
+    offset: (f32, f32, f32),
+    #[serde(default = "default_scale")]
+    scale: f32,
+    // In degrees
+    #[serde(default)]
+    rotation: (f32, f32, f32),
+}
+
+fn default_scale() -> f32 {
+    1.
+}
+
+#[derive(Default)]
+pub struct PieceSetLoader;
+
+impl AssetLoader for PieceSetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let manifest: PieceSetManifest = ron::de::from_bytes(bytes)?;
+            let directory = load_context
+                .path()
+                .parent()
+                .map(|parent| parent.to_path_buf())
+                .unwrap_or_default();
+
+            // The meshes load along with the manifest
+            let mut dependencies: Vec<AssetPath<'static>> = Vec::new();
+            let mut model = |model: PieceModelManifest| {
+                let meshes = model
+                    .meshes
+                    .iter()
+                    .map(|mesh| {
+                        let path = AssetPath::from(directory.join(mesh).to_str().unwrap_or(mesh))
+                            .to_owned();
+                        dependencies.push(path.clone());
+                        load_context.get_handle(path)
+                    })
+                    .collect();
+                let (x, y, z) = model.rotation;
+                PieceModelSpec {
+                    meshes,
+                    transform: Transform {
+                        translation: Vec3::new(model.offset.0, model.offset.1, model.offset.2),
+                        rotation: Quat::from_euler(
+                            EulerRot::XYZ,
+                            x.to_radians(),
+                            y.to_radians(),
+                            z.to_radians(),
+                        ),
+                        scale: Vec3::splat(model.scale),
+                    },
+                }
+            };
+            let piece_set = PieceSet {
+                king: model(manifest.king),
+                queen: model(manifest.queen),
+                rook: model(manifest.rook),
+                bishop: model(manifest.bishop),
+                knight: model(manifest.knight),
+                pawn: model(manifest.pawn),
+            };
+
+            load_context
+                .set_default_asset(LoadedAsset::new(piece_set).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pieces.ron"]
+    }
+}
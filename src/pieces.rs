@@ -1,434 +1,281 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy_mod_picking::PickableBundle;
 
+use crate::camera::{OrbitCamera, ViewMode};
+use crate::game::{AppState, GameSetup};
 use crate::piece::{Piece, PieceColor, PieceType};
+use crate::piece_set::{PieceSet, PieceSetLoader};
 
-const P_SCALE: f32 = 0.2;
+// Manifest of the piece models used unless the theme picks another set
+pub const DEFAULT_PIECE_SET: &str = "models/chess_kit/chess_kit.pieces.ron";
+// Folder of piece images for the 2D view, named like wK.png and bN.png
+const DEFAULT_PIECE_IMAGES: &str = "pieces/dejavu";
+// Size of a piece image relative to its square
+const IMAGE_SIZE: f32 = 0.9;
 
-pub struct PiecesPlugin;
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::King,
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Pawn,
+];
 
-impl Plugin for PiecesPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_startup_system(create_pieces)
-            .add_system(move_pieces);
-    }
+pub struct PieceAssets {
+    // Replacing the set swaps the models of every piece on the board
+    pub set: Handle<PieceSet>,
+    pub white: Handle<StandardMaterial>,
+    pub black: Handle<StandardMaterial>,
+    // Materials of the piece images, shared by every piece of the same color and type
+    image_materials: HashMap<(PieceColor, PieceType), Handle<StandardMaterial>>,
+    // Square the piece images are drawn on, lying just above the board
+    image_mesh: Handle<Mesh>,
 }
 
-fn create_pieces(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let king_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh0/Primitive0");
-    let king_cross_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh1/Primitive0");
-    let pawn_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh2/Primitive0");
-    let knight_1_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh3/Primitive0");
-    let knight_2_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh4/Primitive0");
-    let rook_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh5/Primitive0");
-    let bishop_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh6/Primitive0");
-    let queen_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh7/Primitive0");
-
-    // Add material to pieces
-    let white_material = materials.add(Color::rgb(1.0, 0.8, 0.8).into());
-    let black_material = materials.add(Color::rgb(0.0, 0.2, 0.2).into());
+impl FromWorld for PieceAssets {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        let set = asset_server.load(DEFAULT_PIECE_SET);
+        let mut images = Vec::new();
+        for color in [PieceColor::White, PieceColor::Black] {
+            for piece_type in PIECE_TYPES {
+                let path = image_path(DEFAULT_PIECE_IMAGES, color, piece_type);
+                images.push(((color, piece_type), asset_server.load(&path)));
+            }
+        }
+        let image_mesh = world.resource_mut::<Assets<Mesh>>().add(image_mesh());
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let image_materials = images
+            .into_iter()
+            .map(|(key, image)| {
+                // Images aren't lit so they look the same as the files
+                let material = materials.add(StandardMaterial {
+                    base_color_texture: Some(image),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    ..Default::default()
+                });
+                (key, material)
+            })
+            .collect();
+        PieceAssets {
+            set,
+            white: materials.add(Color::rgb(1.0, 0.8, 0.8).into()),
+            black: materials.add(Color::rgb(0.0, 0.2, 0.2).into()),
+            image_materials,
+            image_mesh,
+        }
+    }
+}
 
-    spawn_rook(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        rook_handle.clone(),
-        (0, 0),
-    );
-    spawn_knight(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (0, 1),
-    );
-    spawn_bishop(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        bishop_handle.clone(),
-        (0, 2),
-    );
-    spawn_queen(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        queen_handle.clone(),
-        (0, 3),
-    );
-    spawn_king(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        king_handle.clone(),
-        king_cross_handle.clone(),
-        (0, 4),
+// A square facing up with the top of the image towards Black's side, so it reads
+// right from White's side. Bevy's plane shows images mirrored from above.
+fn image_mesh() -> Mesh {
+    let extent = IMAGE_SIZE / 2.;
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        vec![
+            [extent, 0.02, -extent],
+            [extent, 0.02, extent],
+            [-extent, 0.02, extent],
+            [-extent, 0.02, -extent],
+        ],
     );
-    spawn_bishop(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        bishop_handle.clone(),
-        (0, 5),
-    );
-    spawn_knight(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (0, 6),
-    );
-    spawn_rook(
-        &mut commands,
-        white_material.clone(),
-        PieceColor::White,
-        rook_handle.clone(),
-        (0, 7),
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 1., 0.]; 4]);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_UV_0,
+        vec![[0., 0.], [1., 0.], [1., 1.], [0., 1.]],
     );
+    mesh.set_indices(Some(Indices::U32(vec![0, 2, 1, 0, 3, 2])));
+    mesh
+}
 
-    for i in 0..8 {
-        spawn_pawn(
-            &mut commands,
-            white_material.clone(),
-            PieceColor::White,
-            pawn_handle.clone(),
-            (1, i),
-        );
-    }
+// The model a piece is shown with. It's kept on captured pieces too,
+// so their models stay up to date while they're hidden.
+#[derive(Component)]
+pub struct PieceModel {
+    color: PieceColor,
+    piece_type: PieceType,
+    built: bool,
+}
 
-    // Black pieces
-    spawn_rook(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        rook_handle.clone(),
-        (7, 0),
-    );
-    spawn_knight(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (7, 1),
-    );
-    spawn_bishop(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        bishop_handle.clone(),
-        (7, 2),
-    );
-    spawn_queen(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        queen_handle,
-        (7, 3),
-    );
-    spawn_king(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        king_handle,
-        king_cross_handle,
-        (7, 4),
-    );
-    spawn_bishop(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        bishop_handle,
-        (7, 5),
-    );
-    spawn_knight(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        knight_1_handle,
-        knight_2_handle,
-        (7, 6),
-    );
-    spawn_rook(
-        &mut commands,
-        black_material.clone(),
-        PieceColor::Black,
-        rook_handle,
-        (7, 7),
-    );
+// Parts of a piece's model, the meshes are shown in the 3D view and the image in the 2D view
+#[derive(Component)]
+enum ModelPart {
+    Mesh,
+    Image,
+}
+
+pub struct PiecesPlugin;
 
-    for i in 0..8 {
-        spawn_pawn(
-            &mut commands,
-            black_material.clone(),
-            PieceColor::Black,
-            pawn_handle.clone(),
-            (6, i),
-        );
+impl Plugin for PiecesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<PieceSet>()
+            .init_asset_loader::<PieceSetLoader>()
+            .init_resource::<PieceAssets>()
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(create_pieces))
+            .add_system_set(SystemSet::on_exit(AppState::Playing).with_system(remove_pieces))
+            .add_system_set(SystemSet::on_exit(AppState::Editor).with_system(remove_pieces))
+            .add_system(build_models)
+            .add_system(show_model_parts.after(build_models))
+            .add_system(turn_images);
     }
 }
 
-pub fn spawn_king(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    piece_color: PieceColor,
-    mesh: Handle<Mesh>,
-    mesh_cross: Handle<Mesh>,
-    position: (u8, u8),
-) {
-    commands
-        .spawn_bundle(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.0,
-                position.1 as f32,
-            )),
-            ..Default::default()
-        })
-        .insert(Piece {
-            color: piece_color,
-            piece_type: PieceType::King,
-            x: position.0,
-            y: position.1,
-        })
-        .with_children(|parent| {
-            parent.spawn_bundle(PbrBundle {
-                mesh,
-                material: material.clone(),
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., -1.9));
-                    transform.apply_non_uniform_scale(Vec3::new(P_SCALE, P_SCALE, P_SCALE));
-                    transform
-                },
-                ..Default::default()
-            });
+fn create_pieces(mut commands: Commands, setup: Res<GameSetup>) {
+    for piece in setup.position().pieces {
+        spawn_piece(&mut commands, piece);
+    }
+}
 
-            parent.spawn_bundle(PbrBundle {
-                mesh: mesh_cross,
-                material,
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., -1.9));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
-        });
+// Captured pieces are hidden rather than despawned, so they're removed here too
+fn remove_pieces(mut commands: Commands, models_query: Query<Entity, With<PieceModel>>) {
+    for entity in models_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
-pub fn spawn_knight(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    piece_color: PieceColor,
-    mesh_1: Handle<Mesh>,
-    mesh_2: Handle<Mesh>,
-    position: (u8, u8),
-) {
+pub fn spawn_piece(commands: &mut Commands, piece: Piece) -> Entity {
     commands
         .spawn_bundle(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.0,
-                position.1 as f32,
-            )),
+            transform: Transform::from_translation(Vec3::new(piece.x as f32, 0.0, piece.y as f32)),
             ..Default::default()
         })
-        .insert(Piece {
-            color: piece_color,
-            piece_type: PieceType::Knight,
-            x: position.0,
-            y: position.1,
+        .insert(piece)
+        .insert(PieceModel {
+            color: piece.color,
+            piece_type: piece.piece_type,
+            built: false,
         })
-        .with_children(|parent| {
-            parent.spawn_bundle(PbrBundle {
-                mesh: mesh_1,
-                material: material.clone(),
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 0.9));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
-
-            parent.spawn_bundle(PbrBundle {
-                mesh: mesh_2,
-                material,
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 0.9));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
-        });
+        .id()
 }
 
-pub fn spawn_queen(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    piece_color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
-) {
-    commands
-        .spawn_bundle(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.0,
-                position.1 as f32,
-            )),
-            ..Default::default()
-        })
-        .insert(Piece {
-            color: piece_color,
-            piece_type: PieceType::Queen,
-            x: position.0,
-            y: position.1,
-        })
-        .with_children(|parent| {
-            parent.spawn_bundle(PbrBundle {
-                mesh,
-                material: material.clone(),
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., -0.95));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
-        });
+fn image_path(folder: &str, color: PieceColor, piece_type: PieceType) -> String {
+    let color = match color {
+        PieceColor::White => 'w',
+        PieceColor::Black => 'b',
+    };
+    let piece_type = match piece_type {
+        PieceType::King => 'K',
+        PieceType::Queen => 'Q',
+        PieceType::Rook => 'R',
+        PieceType::Bishop => 'B',
+        PieceType::Knight => 'N',
+        PieceType::Pawn => 'P',
+    };
+    format!("{}/{}{}.png", folder, color, piece_type)
 }
 
-pub fn spawn_bishop(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    piece_color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
+// Gives pieces the meshes of the piece set once it's loaded, and again whenever
+// the set is changed or a piece turns into another type
+fn build_models(
+    mut commands: Commands,
+    mut asset_events: EventReader<AssetEvent<PieceSet>>,
+    assets: Res<PieceAssets>,
+    piece_sets: Res<Assets<PieceSet>>,
+    view_mode: Res<ViewMode>,
+    mut models_query: Query<(Entity, &mut PieceModel, Option<&Piece>)>,
 ) {
-    commands
-        .spawn_bundle(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.0,
-                position.1 as f32,
-            )),
-            ..Default::default()
-        })
-        .insert(Piece {
-            color: piece_color,
-            piece_type: PieceType::Bishop,
-            x: position.0,
-            y: position.1,
-        })
-        .with_children(|parent| {
-            parent.spawn_bundle(PbrBundle {
-                mesh,
-                material: material.clone(),
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 0.));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
+    // Every event is read, so none are left over to trigger a rebuild later
+    let set_loaded = asset_events
+        .iter()
+        .fold(false, |loaded, event| match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                loaded || *handle == assets.set
+            }
+            AssetEvent::Removed { .. } => loaded,
         });
-}
+    let set_changed = set_loaded || assets.is_changed();
+    let piece_set = match piece_sets.get(&assets.set) {
+        Some(piece_set) => piece_set,
+        None => return,
+    };
 
-pub fn spawn_rook(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    piece_color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
-) {
-    commands
-        .spawn_bundle(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.0,
-                position.1 as f32,
-            )),
-            ..Default::default()
-        })
-        .insert(Piece {
-            color: piece_color,
-            piece_type: PieceType::Rook,
-            x: position.0,
-            y: position.1,
-        })
-        .with_children(|parent| {
-            parent.spawn_bundle(PbrBundle {
-                mesh,
-                material: material.clone(),
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 1.8));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
+    for (entity, mut model, piece) in models_query.iter_mut() {
+        if let Some(piece) = piece {
+            if piece.piece_type != model.piece_type {
+                model.piece_type = piece.piece_type;
+                model.built = false;
+            }
+        }
+        if model.built && !set_changed {
+            continue;
+        }
+        model.built = true;
+
+        let material = match model.color {
+            PieceColor::White => assets.white.clone(),
+            PieceColor::Black => assets.black.clone(),
+        };
+        let image_material = assets.image_materials[&(model.color, model.piece_type)].clone();
+        let piece_model = piece_set.model(model.piece_type);
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for mesh in piece_model.meshes.iter() {
+                parent
+                    .spawn_bundle(PbrBundle {
+                        mesh: mesh.clone(),
+                        material: material.clone(),
+                        transform: piece_model.transform,
+                        visibility: Visibility {
+                            is_visible: *view_mode == ViewMode::ThreeD,
+                        },
+                        ..Default::default()
+                    })
+                    .insert_bundle(PickableBundle::default())
+                    .insert(ModelPart::Mesh);
+            }
+            parent
+                .spawn_bundle(PbrBundle {
+                    mesh: assets.image_mesh.clone(),
+                    material: image_material,
+                    visibility: Visibility {
+                        is_visible: *view_mode == ViewMode::TwoD,
+                    },
+                    ..Default::default()
+                })
+                .insert_bundle(PickableBundle::default())
+                .insert(ModelPart::Image);
         });
+    }
 }
 
-pub fn spawn_pawn(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    piece_color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
+// Hidden parts can't be picked either, so only the shown view's parts react to the mouse
+fn show_model_parts(
+    view_mode: Res<ViewMode>,
+    mut parts_query: Query<(&ModelPart, &mut Visibility)>,
 ) {
-    commands
-        .spawn_bundle(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.0,
-                position.1 as f32,
-            )),
-            ..Default::default()
-        })
-        .insert(Piece {
-            color: piece_color,
-            piece_type: PieceType::Pawn,
-            x: position.0,
-            y: position.1,
-        })
-        .with_children(|parent| {
-            parent.spawn_bundle(PbrBundle {
-                mesh,
-                material: material.clone(),
-                transform: {
-                    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 2.6));
-                    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-                    transform
-                },
-                ..Default::default()
-            });
-        });
+    if !view_mode.is_changed() {
+        return;
+    }
+    for (part, mut visibility) in parts_query.iter_mut() {
+        visibility.is_visible = match part {
+            ModelPart::Mesh => *view_mode == ViewMode::ThreeD,
+            ModelPart::Image => *view_mode == ViewMode::TwoD,
+        };
+    }
 }
 
-fn move_pieces(time: Res<Time>, mut query: Query<(&mut Transform, &Piece)>) {
-    for (mut transform, piece) in query.iter_mut() {
-        // Get the direction to move int
-        let direction = Vec3::new(piece.x as f32, 0.0, piece.y as f32) - transform.translation;
-
-        // Move only if the piece isn't in source
-        if direction.length() > 0.1 {
-            transform.translation += direction.normalize() * time.delta_seconds();
+// Keeps the piece images upright on screen when the board is flipped or turned
+fn turn_images(
+    cameras: Query<(&OrbitCamera, ChangeTrackers<OrbitCamera>)>,
+    mut parts_query: Query<(&ModelPart, &mut Transform, ChangeTrackers<ModelPart>)>,
+) {
+    let (orbit, orbit_tracker) = match cameras.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let rotation = Quat::from_rotation_y(orbit.yaw);
+    for (part, mut transform, part_tracker) in parts_query.iter_mut() {
+        if let ModelPart::Image = part {
+            if orbit_tracker.is_changed() || part_tracker.is_added() {
+                transform.rotation = rotation;
+            }
         }
     }
 }
@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::PathBuf;
+
+use bevy::ecs::event::Events;
+use bevy::ecs::system::{SystemParam, SystemState};
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::AnimationSettings;
+use crate::board::{
+    parse_uci, uci, Captured, GameEnd, GameResult, MoveError, MoveParams, MovePlayed, PlayerTurn,
+};
+use crate::clock::{press_clock, time_controls, GameClock, SideClock};
+use crate::game::{change_state, AppState, GameSetup, Opponent};
+use crate::history::MoveHistory;
+use crate::piece::{Piece, PieceColor};
+
+// Saves are kept in this folder, next to where the game is run from
+const SAVES_FOLDER: &str = "saves";
+// Written after every move, an unfinished game in it is resumed on the next launch
+pub const AUTOSAVE: &str = "autosave";
+
+// Everything needed to rebuild a game. The moves are replayed from the start position,
+// so the history comes back along with the board.
+#[derive(Serialize, Deserialize)]
+pub struct SavedGame {
+    pub opponent: Opponent,
+    pub player_color: PieceColor,
+    // Index into the clock's time controls
+    pub time_control: usize,
+    pub fen: String,
+    // In UCI notation, like e2e4 or e7e8q
+    pub moves: Vec<String>,
+    pub white_clock: SideClock,
+    pub black_clock: SideClock,
+    // Kept for the endings the moves don't show, like a flag fall
+    pub result: Option<GameEnd>,
+}
+
+fn save_path(name: &str) -> PathBuf {
+    PathBuf::from(SAVES_FOLDER).join(format!("{}.ron", name))
+}
+
+// Slot names are used as file names, so only plain characters are kept
+pub fn slot_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric() || " -_".contains(*c))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Names of the saved games, sorted, without the autosave
+pub fn saved_games() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(SAVES_FOLDER)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+                .filter_map(|path| path.file_stem()?.to_str().map(String::from))
+                .filter(|name| name != AUTOSAVE)
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+pub fn read_save(name: &str) -> Result<SavedGame, String> {
+    let text = fs::read_to_string(save_path(name)).map_err(|err| err.to_string())?;
+    ron::from_str(&text).map_err(|err| err.to_string())
+}
+
+fn write_save(name: &str, game: &SavedGame) -> Result<(), String> {
+    fs::create_dir_all(SAVES_FOLDER).map_err(|err| err.to_string())?;
+    let text =
+        ron::ser::to_string_pretty(game, PrettyConfig::default()).map_err(|err| err.to_string())?;
+    fs::write(save_path(name), text).map_err(|err| err.to_string())
+}
+
+// Whether there's an unfinished game to continue
+pub fn has_autosave() -> bool {
+    read_save(AUTOSAVE).is_ok_and(|game| game.result.is_none())
+}
+
+// Name the game is saved under from the pause menu, and whether it was just saved
+pub struct SaveSlot {
+    pub name: String,
+    pub saved: bool,
+}
+
+impl Default for SaveSlot {
+    fn default() -> Self {
+        SaveSlot {
+            name: "My game".to_string(),
+            saved: false,
+        }
+    }
+}
+
+// A loaded game, its moves are played once its start position is on the board
+struct Replay(SavedGame);
+
+// Writes the game being played and sets up saved ones
+#[derive(SystemParam)]
+pub struct GameSaves<'w, 's> {
+    commands: Commands<'w, 's>,
+    pub setup: ResMut<'w, GameSetup>,
+    history: Res<'w, MoveHistory>,
+    clock: Res<'w, GameClock>,
+    result: Res<'w, GameResult>,
+}
+
+impl<'w, 's> GameSaves<'w, 's> {
+    fn game(&self) -> SavedGame {
+        SavedGame {
+            opponent: self.setup.opponent,
+            player_color: self.setup.player_color,
+            time_control: self.setup.time_control,
+            fen: self.setup.fen.clone(),
+            moves: self
+                .history
+                .moves
+                .iter()
+                .map(|record| uci(record.from, record.to, record.promotion))
+                .collect(),
+            white_clock: self.clock.white,
+            black_clock: self.clock.black,
+            result: self.result.0,
+        }
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), String> {
+        write_save(name, &self.game())
+    }
+
+    // The saved game is rebuilt when the game starts
+    pub fn load(&mut self, name: &str) -> Result<(), String> {
+        let game = read_save(name)?;
+        if game.time_control >= time_controls().len() {
+            return Err(format!("Unknown time control {}", game.time_control));
+        }
+        self.setup.opponent = game.opponent;
+        self.setup.player_color = game.player_color;
+        self.setup.time_control = game.time_control;
+        self.setup.fen = game.fen.clone();
+        self.commands.insert_resource(Replay(game));
+        Ok(())
+    }
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveSlot>()
+            .add_startup_system(resume_autosave)
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(replay_moves.exclusive_system()),
+            )
+            .add_system(autosave.after(press_clock));
+    }
+}
+
+fn resume_autosave(mut saves: GameSaves, mut state: ResMut<State<AppState>>) {
+    if !has_autosave() {
+        return;
+    }
+    match saves.load(AUTOSAVE) {
+        Ok(()) => change_state(state.set(AppState::Playing)),
+        Err(err) => warn!("Couldn't resume the last game: {}", err),
+    }
+}
+
+// Plays the moves of a loaded game through the same path as moves on the board.
+// Commands are applied after every move so captured pieces are gone for the next one.
+fn replay_moves(world: &mut World) {
+    if !world.contains_resource::<Replay>() {
+        return;
+    }
+    // The pieces are spawned by commands when the game starts
+    if world.query::<&Piece>().iter(world).next().is_none() {
+        return;
+    }
+    let game = match world.remove_resource::<Replay>() {
+        Some(Replay(game)) => game,
+        None => return,
+    };
+
+    let mut state: SystemState<MoveParams> = SystemState::new(world);
+    for name in game.moves.iter() {
+        let mut moves = state.get_mut(world);
+        let played = match parse_uci(name) {
+            Some(request) => match moves.piece_at(request.from) {
+                Some(entity) => moves
+                    .try_move(entity, request.to, request.promotion)
+                    .map_err(MoveError::description),
+                None => Err(MoveError::NoPiece.description()),
+            },
+            None => Err("It isn't a move".to_string()),
+        };
+        state.apply(world);
+        if let Err(reason) = played {
+            warn!("Stopped replaying the saved game at {}: {}", name, reason);
+            break;
+        }
+    }
+
+    // The pieces are put straight on their squares and the captured ones hidden. A piece
+    // that was taken itself never lands, so what it captured is hidden here.
+    world.resource_mut::<AnimationSettings>().instant = true;
+    let mut captured_query = world.query_filtered::<(Entity, &mut Visibility), With<Captured>>();
+    let captured: Vec<Entity> = captured_query
+        .iter_mut(world)
+        .map(|(entity, mut visibility)| {
+            visibility.is_visible = false;
+            entity
+        })
+        .collect();
+    for entity in captured {
+        world.entity_mut(entity).remove::<Captured>();
+    }
+
+    // The clocks already ran for these moves, so they're set from the save instead
+    world.resource_mut::<Events<MovePlayed>>().clear();
+    let turn = world.resource::<PlayerTurn>().0;
+    world
+        .resource_mut::<GameClock>()
+        .resume(game.white_clock, game.black_clock, turn);
+    if game.result.is_some() {
+        world.resource_mut::<GameResult>().0 = game.result;
+    }
+}
+
+fn autosave(mut move_events: EventReader<MovePlayed>, saves: GameSaves) {
+    let moved = move_events.iter().count() > 0;
+    let ended = saves.result.is_changed() && saves.result.0.is_some();
+    if !moved && !ended {
+        return;
+    }
+    if let Err(err) = saves.save(AUTOSAVE) {
+        warn!("Couldn't save the game: {}", err);
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::engine::eval::EvalParams;
+use crate::engine::nnue::{Network, DEFAULT_NETWORK_FILE};
+use crate::engine::{Board, Engine, Limits};
+use crate::fen::START_FEN;
+use crate::piece::PieceColor;
+
+const DEFAULT_POSITIONS: usize = 100_000;
+const DEFAULT_NODES: u64 = 5_000;
+// Random moves played from the start, so no two games are alike
+const RANDOM_PLIES: usize = 8;
+// Longer games are called drawn
+const MAX_PLIES: usize = 400;
+// A side this far ahead for a few moves in a row wins, the rest of the game teaches little
+const WIN_SCORE: i32 = 2_000;
+const WIN_PLIES: usize = 8;
+
+// Where the finished games' positions go, and how many were written
+struct Output {
+    writer: BufWriter<File>,
+    written: usize,
+}
+
+// A position of a game with its search score for White
+struct Record {
+    fen: String,
+    score: i32,
+}
+
+// xorshift64*, enough to pick opening moves
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n
+    }
+}
+
+// `selfplay <file> [positions] [games at once] [nodes per move]` has the engine play itself
+// from random openings until the file holds that many positions.
+// Each line is `<fen> | <score> | <result>`, the score in centipawns and the result 1.0, 0.5
+// or 0.0, both for White.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let path = args
+        .first()
+        .ok_or("Expected the file to write the positions to")?;
+    let number = |i: usize, default: u64| match args.get(i) {
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| format!("Expected a number instead of '{}'", arg)),
+        None => Ok(default),
+    };
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let target = number(1, DEFAULT_POSITIONS as u64)? as usize;
+    let games = number(2, cores as u64)?.max(1) as usize;
+    let nodes = number(3, DEFAULT_NODES)?;
+
+    let file = File::create(path).map_err(|err| format!("{}: {}", path, err))?;
+    let output = Arc::new(Mutex::new(Output {
+        writer: BufWriter::new(file),
+        written: 0,
+    }));
+    let params = EvalParams::tuned()?;
+    let network = Network::load(Path::new(DEFAULT_NETWORK_FILE))
+        .ok()
+        .map(Arc::new);
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+
+    let workers: Vec<_> = (0..games)
+        .map(|i| {
+            let output = output.clone();
+            let mut engine = Engine::new(16);
+            engine.set_params(params.clone());
+            engine.set_network(network.clone());
+            // Never zero, which xorshift would stay at
+            let mut rng = Rng(seed ^ (i as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+            thread::spawn(move || -> Result<(), String> {
+                loop {
+                    if output.lock().unwrap().written >= target {
+                        return Ok(());
+                    }
+                    let (records, result) = play_game(&engine, &mut rng, nodes)?;
+                    let mut output = output.lock().unwrap();
+                    let count = records.len().min(target.saturating_sub(output.written));
+                    for record in &records[..count] {
+                        writeln!(
+                            output.writer,
+                            "{} | {} | {}",
+                            record.fen, record.score, result
+                        )
+                        .map_err(|err| err.to_string())?;
+                    }
+                    if count > 0 {
+                        output.written += count;
+                        eprintln!("{} / {} positions", output.written, target);
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().map_err(|_| "A game failed".to_string())??;
+    }
+    let mut output = output.lock().unwrap();
+    output.writer.flush().map_err(|err| err.to_string())
+}
+
+// Plays a game, returning the positions worth learning from and the result for White
+fn play_game(
+    engine: &Engine,
+    rng: &mut Rng,
+    nodes: u64,
+) -> Result<(Vec<Record>, &'static str), String> {
+    let mut board = random_opening(rng)?;
+    engine.new_game();
+    let limits = Limits {
+        nodes: Some(nodes),
+        ..Default::default()
+    };
+    let mut records = Vec::new();
+    let mut winning = 0;
+    let mut last_score: i32 = 0;
+    for _ in 0..MAX_PLIES {
+        if board.is_draw() {
+            return Ok((records, "0.5"));
+        }
+        let info = engine.search(&board, &limits, |_| {});
+        let mv = match info.best_move() {
+            Some(mv) => mv,
+            // Mated, or stalemated
+            None if board.in_check() => {
+                let result = match board.turn {
+                    PieceColor::White => "0.0",
+                    PieceColor::Black => "1.0",
+                };
+                return Ok((records, result));
+            }
+            None => return Ok((records, "0.5")),
+        };
+        let score = match board.turn {
+            PieceColor::White => info.score,
+            PieceColor::Black => -info.score,
+        };
+        // Positions in check, about to capture or with a mate on the board are left out, the
+        // evaluation doesn't see those on its own
+        if !board.in_check() && !board.is_tactical(mv) && info.mate_in().is_none() {
+            records.push(Record {
+                fen: board.fen(),
+                score,
+            });
+        }
+        winning = if score.abs() >= WIN_SCORE && score.signum() == last_score.signum() {
+            winning + 1
+        } else {
+            0
+        };
+        last_score = score;
+        if winning >= WIN_PLIES {
+            return Ok((records, if score > 0 { "1.0" } else { "0.0" }));
+        }
+        board.make(mv);
+    }
+    Ok((records, "0.5"))
+}
+
+// Random legal moves from the start, played again if they end the game
+fn random_opening(rng: &mut Rng) -> Result<Board, String> {
+    'opening: loop {
+        let mut board = Board::from_fen(START_FEN)?;
+        for _ in 0..RANDOM_PLIES + rng.below(2) {
+            let moves = board.legal_moves();
+            if moves.is_empty() {
+                continue 'opening;
+            }
+            board.make(moves[rng.below(moves.len())]);
+        }
+        if !board.legal_moves().is_empty() {
+            return Ok(board);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::path::{Component, PathBuf};
+
+use bevy::asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+use crate::game::TextFocus;
+use crate::highlight::{HighlightLayer, HighlightPalette};
+use crate::piece_set::PieceSet;
+use crate::pieces::{PieceAssets, DEFAULT_PIECE_SET};
+
+// Every `.theme.ron` file in this folder can be picked, custom themes just need to be added to it
+const THEMES_FOLDER: &str = "themes";
+const DEFAULT_THEME: &str = "classic";
+
+pub struct SquareStyle {
+    pub color: Color,
+    // Tinted by the color and the highlights
+    pub texture: Option<Handle<Image>>,
+}
+
+pub struct PieceStyle {
+    pub color: Color,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+// Look of the board, pieces and highlights, loaded from a `.theme.ron` file
+#[derive(TypeUuid)]
+#[uuid = "a3e7c1d2-6b4f-4e8a-9c05-7f2d1b8e6a39"]
+pub struct Theme {
+    pub name: String,
+    pub light_square: SquareStyle,
+    pub dark_square: SquareStyle,
+    pub white_pieces: PieceStyle,
+    pub black_pieces: PieceStyle,
+    pub piece_set: Handle<PieceSet>,
+    // Layers left out keep their default color
+    pub highlights: HashMap<HighlightLayer, Color>,
+}
+
+#[derive(Deserialize)]
+struct ThemeFile {
+    name: String,
+    light_square: SquareStyleFile,
+    dark_square: SquareStyleFile,
+    white_pieces: PieceStyleFile,
+    black_pieces: PieceStyleFile,
+    // `.pieces.ron` manifest relative to the theme file, the default set if left out
+    #[serde(default)]
+    piece_set: Option<String>,
+    // Colors with the alpha they're blended with
+    #[serde(default)]
+    highlights: HashMap<HighlightLayer, (f32, f32, f32, f32)>,
+}
+
+#[derive(Deserialize)]
+struct SquareStyleFile {
+    color: (f32, f32, f32),
+    // Image relative to the theme file
+    #[serde(default)]
+    texture: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PieceStyleFile {
+    color: (f32, f32, f32),
+    #[serde(default)]
+    metallic: f32,
+    #[serde(default = "default_roughness")]
+    roughness: f32,
+}
+
+// Same as StandardMaterial's default
+fn default_roughness() -> f32 {
+    0.089
+}
+
+// Path of a file the theme refers to. Going up a folder is resolved, so a piece set shared
+// with the default one, or another theme, is loaded once.
+fn asset_path(path: PathBuf) -> AssetPath<'static> {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+    AssetPath::from(resolved.as_path()).to_owned()
+}
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let file: ThemeFile = ron::de::from_bytes(bytes)?;
+            let directory = load_context
+                .path()
+                .parent()
+                .map(|parent| parent.to_path_buf())
+                .unwrap_or_default();
+
+            // Textures and the piece set load along with the theme
+            let mut dependencies: Vec<AssetPath<'static>> = Vec::new();
+            let mut square = |square: SquareStyleFile| {
+                let (r, g, b) = square.color;
+                SquareStyle {
+                    color: Color::rgb(r, g, b),
+                    texture: square.texture.map(|texture| {
+                        let path = asset_path(directory.join(texture));
+                        dependencies.push(path.clone());
+                        load_context.get_handle(path)
+                    }),
+                }
+            };
+            let light_square = square(file.light_square);
+            let dark_square = square(file.dark_square);
+            let piece_set = match file.piece_set {
+                Some(piece_set) => asset_path(directory.join(piece_set)),
+                None => AssetPath::from(DEFAULT_PIECE_SET).to_owned(),
+            };
+            dependencies.push(piece_set.clone());
+            let piece = |piece: PieceStyleFile| {
+                let (r, g, b) = piece.color;
+                PieceStyle {
+                    color: Color::rgb(r, g, b),
+                    metallic: piece.metallic,
+                    roughness: piece.roughness,
+                }
+            };
+            let theme = Theme {
+                name: file.name,
+                light_square,
+                dark_square,
+                white_pieces: piece(file.white_pieces),
+                black_pieces: piece(file.black_pieces),
+                piece_set: load_context.get_handle(piece_set),
+                highlights: file
+                    .highlights
+                    .into_iter()
+                    .map(|(layer, (r, g, b, a))| (layer, Color::rgba(r, g, b, a)))
+                    .collect(),
+            };
+
+            load_context.set_default_asset(LoadedAsset::new(theme).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+pub struct Themes {
+    // Keeps the themes in the folder loaded
+    handles: Vec<HandleUntyped>,
+    // Name of the theme in use
+    pub current: String,
+}
+
+impl FromWorld for Themes {
+    fn from_world(world: &mut World) -> Self {
+        let handles = world
+            .resource::<AssetServer>()
+            .load_folder(THEMES_FOLDER)
+            .unwrap_or_else(|err| {
+                warn!("Couldn't load themes: {:?}", err);
+                Vec::new()
+            });
+        Themes {
+            handles,
+            current: DEFAULT_THEME.to_string(),
+        }
+    }
+}
+
+impl Themes {
+    // Loaded themes, sorted by name
+    pub fn names(&self, themes: &Assets<Theme>) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .handles
+            .iter()
+            .filter_map(|handle| themes.get(&handle.typed_weak::<Theme>()))
+            .map(|theme| theme.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_resource::<Themes>()
+            .add_system(switch_theme)
+            .add_system(apply_theme.after(switch_theme));
+    }
+}
+
+// Tab goes to the next theme
+fn switch_theme(
+    keys: Res<Input<KeyCode>>,
+    focus: Res<TextFocus>,
+    mut themes: ResMut<Themes>,
+    theme_assets: Res<Assets<Theme>>,
+) {
+    if !keys.just_pressed(KeyCode::Tab) || focus.0 {
+        return;
+    }
+    let names = themes.names(&theme_assets);
+    if names.is_empty() {
+        return;
+    }
+    let next = match names.iter().position(|name| *name == themes.current) {
+        Some(i) => (i + 1) % names.len(),
+        None => 0,
+    };
+    themes.current = names[next].clone();
+}
+
+// Puts the current theme on the squares and pieces, when it's picked or its file changes
+fn apply_theme(
+    themes: Res<Themes>,
+    mut theme_events: EventReader<AssetEvent<Theme>>,
+    theme_assets: Res<Assets<Theme>>,
+    mut piece_assets: ResMut<PieceAssets>,
+    mut palette: ResMut<HighlightPalette>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let loaded = theme_events.iter().any(|event| {
+        matches!(
+            event,
+            AssetEvent::Created { .. } | AssetEvent::Modified { .. }
+        )
+    });
+    if !loaded && !themes.is_changed() {
+        return;
+    }
+    let theme = match theme_assets
+        .iter()
+        .map(|(_, theme)| theme)
+        .find(|theme| theme.name == themes.current)
+    {
+        Some(theme) => theme,
+        None => return,
+    };
+
+    let default_palette = HighlightPalette::default();
+    *palette = HighlightPalette {
+        light: theme.light_square.color,
+        dark: theme.dark_square.color,
+        light_texture: theme.light_square.texture.clone(),
+        dark_texture: theme.dark_square.texture.clone(),
+        layers: HighlightLayer::ALL
+            .iter()
+            .filter_map(|layer| {
+                theme
+                    .highlights
+                    .get(layer)
+                    .or_else(|| default_palette.layers.get(layer))
+                    .map(|color| (*layer, *color))
+            })
+            .collect(),
+    };
+
+    for (handle, style) in [
+        (&piece_assets.white, &theme.white_pieces),
+        (&piece_assets.black, &theme.black_pieces),
+    ] {
+        if let Some(material) = materials.get_mut(handle) {
+            material.base_color = style.color;
+            material.metallic = style.metallic;
+            material.perceptual_roughness = style.roughness;
+        }
+    }
+    if piece_assets.set != theme.piece_set {
+        piece_assets.set = theme.piece_set.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn resolves_paths_out_of_the_themes_folder() {
+        assert_eq!(
+            asset_path(Path::new("themes").join("../models/set.pieces.ron")).path(),
+            Path::new("models/set.pieces.ron")
+        );
+        assert_eq!(
+            asset_path(Path::new("themes").join("./wood/grain.png")).path(),
+            Path::new("themes/wood/grain.png")
+        );
+    }
+}
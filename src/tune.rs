@@ -0,0 +1,242 @@
+use std::fs;
+use std::path::Path;
+
+use crate::engine::eval::{
+    add_terms, phase, EvalParams, Terms, MAX_PHASE, TERM_COUNT, TUNED_PARAMS_FILE,
+};
+use crate::engine::Board;
+
+const DEFAULT_EPOCHS: usize = 500;
+// Adam's step size, in centipawns
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+// A position's terms that don't cancel out, with its phase and result for White
+struct Sample {
+    terms: Vec<(u16, i16)>,
+    phase: f64,
+    result: f64,
+}
+
+// Counts each term of one position at a time
+struct Counts(Vec<i32>);
+
+impl Terms for Counts {
+    fn add(&mut self, term: usize, count: i32) {
+        self.0[term] += count;
+    }
+}
+
+// `tune <dataset> [output] [epochs]` fits the evaluation's weights to positions with known
+// results and writes them where the engine reads them at startup, unless told otherwise.
+// The dataset holds one position per line, either as `<fen> | <score> | <result>` like
+// `selfplay` writes, or as EPD with the result in a `c9 "1-0";` operation or in brackets.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let dataset = args.first().ok_or("Expected a file of positions")?;
+    let output = args.get(1).map_or(TUNED_PARAMS_FILE, |path| path.as_str());
+    let epochs = match args.get(2) {
+        Some(arg) => arg
+            .parse()
+            .map_err(|_| format!("Expected a number instead of '{}'", arg))?,
+        None => DEFAULT_EPOCHS,
+    };
+
+    let text = fs::read_to_string(dataset).map_err(|err| format!("{}: {}", dataset, err))?;
+    let samples = read_samples(&text)?;
+    if samples.is_empty() {
+        return Err(format!("{} has no positions", dataset));
+    }
+    println!("{} positions", samples.len());
+
+    let params = EvalParams::tuned()?;
+    let mut weights: Vec<[f64; 2]> = params
+        .weights
+        .iter()
+        .map(|[middlegame, endgame]| [*middlegame as f64, *endgame as f64])
+        .collect();
+    let k = fit_scale(&samples, &weights);
+    println!("Scale {:.3}, loss {:.6}", k, loss(&samples, &weights, k));
+    tune(&samples, &mut weights, k, epochs, |epoch, loss| {
+        if epoch % 50 == 0 {
+            println!("Epoch {}, loss {:.6}", epoch, loss);
+        }
+    });
+    println!("Loss {:.6}", loss(&samples, &weights, k));
+
+    let tuned = EvalParams {
+        weights: weights
+            .iter()
+            .map(|[middlegame, endgame]| [middlegame.round() as i32, endgame.round() as i32])
+            .collect(),
+    };
+    tuned
+        .save(Path::new(output))
+        .map_err(|err| format!("{}: {}", output, err))?;
+    println!("Saved to {}", output);
+    Ok(())
+}
+
+fn read_samples(text: &str) -> Result<Vec<Sample>, String> {
+    let mut counts = Counts(vec![0; TERM_COUNT]);
+    let mut samples = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (fen, result) = parse_line(line).ok_or_else(|| format!("Line {}: no result", i + 1))?;
+        let board = Board::from_fen(&fen).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+        counts.0.iter_mut().for_each(|count| *count = 0);
+        add_terms(&board, &mut counts);
+        let terms = counts
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != 0)
+            .map(|(term, count)| (term as u16, *count as i16))
+            .collect();
+        samples.push(Sample {
+            terms,
+            phase: phase(&board) as f64 / MAX_PHASE as f64,
+            result,
+        });
+    }
+    Ok(samples)
+}
+
+// The FEN and White's result of a line
+fn parse_line(line: &str) -> Option<(String, f64)> {
+    if let Some((fen, rest)) = line.split_once('|') {
+        let result = rest.rsplit('|').next()?.trim().parse().ok()?;
+        return Some((fen.trim().to_string(), result));
+    }
+    let fen = line
+        .split_whitespace()
+        .take(4)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let result = if line.contains("1/2-1/2") || line.contains("[0.5]") {
+        0.5
+    } else if line.contains("1-0") || line.contains("[1.0]") {
+        1.0
+    } else if line.contains("0-1") || line.contains("[0.0]") {
+        0.0
+    } else {
+        return None;
+    };
+    Some((fen, result))
+}
+
+// The evaluation for White, blended by phase like the engine's
+fn evaluate(sample: &Sample, weights: &[[f64; 2]]) -> f64 {
+    let [middlegame, endgame] =
+        sample
+            .terms
+            .iter()
+            .fold([0.0; 2], |[middlegame, endgame], (term, count)| {
+                let [mg, eg] = weights[*term as usize];
+                [
+                    middlegame + mg * *count as f64,
+                    endgame + eg * *count as f64,
+                ]
+            });
+    middlegame * sample.phase + endgame * (1.0 - sample.phase)
+}
+
+// Expected result for White of a score, k scales centipawns to the results of the dataset
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+// Mean logistic loss (cross-entropy) of the predicted results
+fn loss(samples: &[Sample], weights: &[[f64; 2]], k: f64) -> f64 {
+    let total: f64 = samples
+        .iter()
+        .map(|sample| {
+            let predicted = sigmoid(evaluate(sample, weights), k).clamp(1e-9, 1.0 - 1e-9);
+            -(sample.result * predicted.ln() + (1.0 - sample.result) * (1.0 - predicted).ln())
+        })
+        .sum();
+    total / samples.len() as f64
+}
+
+// The scale that fits the current weights best, so tuning moves the weights rather than
+// making up for a badly chosen scale
+fn fit_scale(samples: &[Sample], weights: &[[f64; 2]]) -> f64 {
+    (1..=40)
+        .map(|i| i as f64 * 0.05)
+        .min_by(|a, b| loss(samples, weights, *a).total_cmp(&loss(samples, weights, *b)))
+        .expect("scales to try")
+}
+
+// Gradient descent on the whole dataset with Adam, reporting the loss before each epoch
+fn tune(
+    samples: &[Sample],
+    weights: &mut [[f64; 2]],
+    k: f64,
+    epochs: usize,
+    mut report: impl FnMut(usize, f64),
+) {
+    let mut momentum = vec![[0.0; 2]; weights.len()];
+    let mut velocity = vec![[0.0; 2]; weights.len()];
+    // The loss's derivative by the score is (predicted - result) times this
+    let slope = k * 10f64.ln() / 400.0;
+    for epoch in 0..epochs {
+        report(epoch, loss(samples, weights, k));
+        let mut gradient = vec![[0.0; 2]; weights.len()];
+        for sample in samples {
+            let error = (sigmoid(evaluate(sample, weights), k) - sample.result) * slope;
+            for (term, count) in &sample.terms {
+                let [middlegame, endgame] = &mut gradient[*term as usize];
+                *middlegame += error * *count as f64 * sample.phase;
+                *endgame += error * *count as f64 * (1.0 - sample.phase);
+            }
+        }
+        let step = (epoch + 1) as i32;
+        for term in 0..weights.len() {
+            for phase in 0..2 {
+                let g = gradient[term][phase] / samples.len() as f64;
+                momentum[term][phase] = BETA1 * momentum[term][phase] + (1.0 - BETA1) * g;
+                velocity[term][phase] = BETA2 * velocity[term][phase] + (1.0 - BETA2) * g * g;
+                let m = momentum[term][phase] / (1.0 - BETA1.powi(step));
+                let v = velocity[term][phase] / (1.0 - BETA2.powi(step));
+                weights[term][phase] -= LEARNING_RATE * m / (v.sqrt() + 1e-8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_results_in_each_format() {
+        let fen = "4k3/8/8/8/8/8/8/3QK3 w - -";
+        for (line, result) in [
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1 | 950 | 1.0", 1.0),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - c9 \"1/2-1/2\";", 0.5),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - c9 \"0-1\";", 0.0),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - [1.0]", 1.0),
+        ] {
+            let (read, read_result) = parse_line(line).unwrap();
+            assert!(read.starts_with(fen), "{}", line);
+            assert_eq!(read_result, result, "{}", line);
+        }
+        assert!(parse_line("4k3/8/8/8/8/8/8/3QK3 w - -").is_none());
+    }
+
+    #[test]
+    fn learns_that_a_queen_up_wins() {
+        let text = "\
+            3qk3/8/8/8/8/8/8/4K3 w - - | 0 | 0.0\n\
+            4k3/8/8/8/8/8/8/3QK3 b - - | 0 | 1.0\n\
+            4k3/8/8/8/8/8/8/4K3 w - - | 0 | 0.5\n";
+        let samples = read_samples(text).unwrap();
+        let mut weights = vec![[0.0; 2]; TERM_COUNT];
+        let before = loss(&samples, &weights, 1.0);
+        tune(&samples, &mut weights, 1.0, 200, |_, _| {});
+        assert!(loss(&samples, &weights, 1.0) < before);
+        assert!(evaluate(&samples[1], &weights) > 100.0);
+    }
+}
@@ -0,0 +1,253 @@
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::engine::eval::EvalParams;
+use crate::engine::nnue::{Network, DEFAULT_NETWORK_FILE};
+use crate::engine::search::{SearchInfo, DEFAULT_HASH_MB, MAX_THREADS};
+use crate::engine::{Board, Engine, Limits};
+use crate::fen::START_FEN;
+use crate::piece::PieceColor;
+
+const MAX_HASH_MB: usize = 4096;
+
+// The network read from EvalFile and whether UseNNUE has the engine evaluate with it
+struct Evaluation {
+    network: Option<Arc<Network>>,
+    use_network: bool,
+    // Whether a file was read yet, the default one is read before the first search
+    loaded: bool,
+}
+
+impl Evaluation {
+    fn load(&mut self, path: &str) {
+        self.loaded = true;
+        self.network = match Network::load(Path::new(path)) {
+            Ok(network) => Some(Arc::new(network)),
+            Err(err) => {
+                println!("info string No network, evaluating classically: {}", err);
+                None
+            }
+        };
+    }
+
+    fn load_default(&mut self, engine: &mut Engine) {
+        if !self.loaded {
+            self.load(DEFAULT_NETWORK_FILE);
+            self.apply(engine);
+        }
+    }
+
+    fn apply(&self, engine: &mut Engine) {
+        engine.set_network(self.network.clone().filter(|_| self.use_network));
+    }
+}
+
+// A `go` running on its own thread, so `stop` can still be read
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Search {
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // A failed search already printed its panic
+        let _ = self.handle.join();
+    }
+}
+
+// `uci` plays through the Universal Chess Interface on stdin and stdout,
+// so the engine can be used from chess GUIs and match runners
+pub fn run(_args: &[String]) -> Result<(), String> {
+    let mut engine = Engine::default();
+    // Before `uci` only stderr is read by people rather than the GUI
+    match EvalParams::tuned() {
+        Ok(params) => engine.set_params(params),
+        Err(err) => eprintln!("Evaluating with the default weights: {}", err),
+    }
+    let mut evaluation = Evaluation {
+        network: None,
+        use_network: true,
+        loaded: false,
+    };
+    let mut board = Board::from_fen(START_FEN)?;
+    let mut search: Option<Search> = None;
+
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|err| err.to_string())?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+        // Only a stop or the end can come while searching, anything else waits for the search
+        if command != "stop" && command != "quit" && command != "isready" {
+            if let Some(search) = search.take() {
+                let _ = search.handle.join();
+            }
+        }
+        match command {
+            "uci" => {
+                println!("id name Chess");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name EvalFile type string default {}",
+                    DEFAULT_NETWORK_FILE
+                );
+                println!("option name UseNNUE type check default true");
+                println!("uciok");
+            }
+            // The network is read once the GUI has had a chance to set EvalFile
+            "isready" => {
+                if search.is_none() {
+                    evaluation.load_default(&mut engine);
+                }
+                println!("readyok")
+            }
+            "setoption" => set_option(&mut engine, &mut evaluation, args),
+            "ucinewgame" => engine.new_game(),
+            "position" => match parse_position(args) {
+                Ok(position) => board = position,
+                Err(err) => println!("info string {}", err),
+            },
+            "go" => {
+                evaluation.load_default(&mut engine);
+                let (limits, infinite) = parse_go(args, board.turn);
+                search = Some(start_search(&engine, &board, limits, infinite));
+            }
+            "stop" => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+            }
+            "quit" => break,
+            _ => println!("info string Unknown command '{}'", command),
+        }
+    }
+    if let Some(search) = search.take() {
+        search.stop();
+    }
+    Ok(())
+}
+
+// `setoption name <name> value <value>`
+fn set_option(engine: &mut Engine, evaluation: &mut Evaluation, args: &[&str]) {
+    let value = args.iter().position(|word| *word == "value");
+    let name = args
+        .get(1..value.unwrap_or(args.len()))
+        .unwrap_or_default()
+        .join(" ");
+    let text = value
+        .and_then(|i| args.get(i + 1..))
+        .unwrap_or_default()
+        .join(" ");
+    let number = text.parse::<usize>().ok();
+    match (name.to_lowercase().as_str(), number) {
+        ("hash", Some(megabytes)) => engine.set_hash_size(megabytes.clamp(1, MAX_HASH_MB)),
+        ("threads", Some(threads)) => engine.set_threads(threads),
+        ("evalfile", _) => {
+            evaluation.load(&text);
+            evaluation.apply(engine);
+        }
+        ("usennue", _) => {
+            evaluation.use_network = text == "true";
+            evaluation.apply(engine);
+        }
+        _ => println!("info string Unknown option '{}'", name),
+    }
+}
+
+// `position (startpos | fen <fen>) [moves <move>...]`
+fn parse_position(args: &[&str]) -> Result<Board, String> {
+    let moves = args.iter().position(|word| *word == "moves");
+    let setup = &args[..moves.unwrap_or(args.len())];
+    let mut board = match setup.split_first() {
+        Some((&"startpos", _)) => Board::from_fen(START_FEN)?,
+        Some((&"fen", fen)) => Board::from_fen(&fen.join(" "))?,
+        _ => return Err("Expected startpos or fen".to_string()),
+    };
+    for text in args.iter().skip(moves.map_or(args.len(), |i| i + 1)) {
+        let mv = board
+            .parse_move(text)
+            .ok_or_else(|| format!("Illegal move '{}'", text))?;
+        board.make(mv);
+    }
+    Ok(board)
+}
+
+// `go` with any of wtime, btime, winc, binc, movetime, depth, nodes and infinite.
+// Also says whether the best move waits for a stop.
+fn parse_go(args: &[&str], turn: PieceColor) -> (Limits, bool) {
+    let value = |name: &str| {
+        args.iter()
+            .position(|word| *word == name)
+            .and_then(|i| args.get(i + 1))
+            .and_then(|value| value.parse::<u64>().ok())
+    };
+    let millis = |name: &str| value(name).map(Duration::from_millis);
+    let (time, increment) = match turn {
+        PieceColor::White => (millis("wtime"), millis("winc")),
+        PieceColor::Black => (millis("btime"), millis("binc")),
+    };
+    let mut limits = match time {
+        Some(time) => Limits::for_clock(time, increment.unwrap_or_default()),
+        None => Limits::default(),
+    };
+    if let Some(time) = millis("movetime") {
+        limits.time = Some(time);
+    }
+    limits.depth = value("depth").map(|depth| depth as i32);
+    limits.nodes = value("nodes");
+    (limits, args.contains(&"infinite"))
+}
+
+fn start_search(engine: &Engine, board: &Board, limits: Limits, infinite: bool) -> Search {
+    let stop = Arc::new(AtomicBool::new(false));
+    let limits = Limits {
+        stop: Some(stop.clone()),
+        ..limits
+    };
+    let (engine, board) = (engine.clone(), board.clone());
+    let handle = thread::spawn(move || {
+        let info = engine.search(&board, &limits, |info| print_info(&engine, info));
+        // The best move of an infinite search is only given once it's stopped
+        while infinite && !limits.stop.as_ref().unwrap().load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        match info.best_move() {
+            Some(mv) => println!("bestmove {}", mv.uci()),
+            None => println!("bestmove 0000"),
+        }
+    });
+    Search { stop, handle }
+}
+
+fn print_info(engine: &Engine, info: &SearchInfo) {
+    let score = match info.mate_in() {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", info.score),
+    };
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.uci()).collect();
+    println!(
+        "info depth {} seldepth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+        info.depth,
+        info.seldepth,
+        score,
+        info.nodes,
+        (info.nodes as f64 / info.elapsed.as_secs_f64().max(0.001)) as u64,
+        engine.hashfull(),
+        info.elapsed.as_millis(),
+        pv.join(" ")
+    );
+}